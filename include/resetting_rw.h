@@ -0,0 +1,45 @@
+/* C interface of the resetting_rw simulators.
+ * Build with `cargo build --release`, which creates libresetting_rw.so
+ * (or .dylib/.dll) in target/release. */
+#ifndef RESETTING_RW_H
+#define RESETTING_RW_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RrwWalker RrwWalker;
+
+/* Create a walker from the json walker settings, e.g.
+ * {"walker": "Eff2", "settings": {...}, "bisection": {"Threshold": 1e-6}}
+ * Returns NULL on invalid input. Release with rrw_walker_free */
+RrwWalker *rrw_walker_new(const char *json, uint64_t seed);
+void rrw_walker_free(RrwWalker *walker);
+
+/* Draw additional samples. Returns 0 on success */
+int rrw_walker_run(RrwWalker *walker, uint64_t samples);
+uint64_t rrw_walker_samples(const RrwWalker *walker);
+double rrw_walker_mean(const RrwWalker *walker);
+double rrw_walker_variance(const RrwWalker *walker);
+
+/* Histogram of the first passage times in [left, right), hist needs space for bins entries.
+ * Returns 0 on success */
+int rrw_walker_histogram(
+    const RrwWalker *walker,
+    double left,
+    double right,
+    size_t bins,
+    uint64_t *hist
+);
+
+/* Analytic dimensionless mfpt T(beta, a). boundary is only used for a < 0 */
+double rrw_analytic_t(double beta, double a, double boundary, int32_t cutoff);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,71 @@
+import ctypes
+import json
+
+class Walker:
+    '''
+    Thin ctypes wrapper around the C interface of resetting_rw, see include/resetting_rw.h
+
+    Parameters:
+        - (dict) settings: walker settings, e.g. 
+            {"walker": "Eff2", "settings": {...}, "bisection": {"Threshold": 1e-6}}
+        - (int) seed: seed of the rng
+        - (str) lib: path to libresetting_rw.so
+    '''
+    def __init__(self, settings, seed, lib = "target/release/libresetting_rw.so"):
+        self.lib = load(lib)
+        self.handle = self.lib.rrw_walker_new(json.dumps(settings).encode(), seed)
+        if not self.handle:
+            raise ValueError('invalid walker settings')
+
+    def __del__(self):
+        if getattr(self, 'handle', None):
+            self.lib.rrw_walker_free(self.handle)
+
+    def run(self, samples):
+        if self.lib.rrw_walker_run(self.handle, samples) != 0:
+            raise RuntimeError('simulation failed')
+
+    def samples(self):
+        return self.lib.rrw_walker_samples(self.handle)
+
+    def mean(self):
+        return self.lib.rrw_walker_mean(self.handle)
+
+    def variance(self):
+        return self.lib.rrw_walker_variance(self.handle)
+
+    def histogram(self, left, right, bins):
+        hist = (ctypes.c_uint64 * bins)()
+        if self.lib.rrw_walker_histogram(self.handle, left, right, bins, hist) != 0:
+            raise ValueError('invalid histogram parameters')
+        return list(hist)
+
+
+def analytic_t(beta, a, boundary = 1.0, cutoff = 1000, lib = "target/release/libresetting_rw.so"):
+    '''
+    Analytic dimensionless mfpt, the sums are truncated at cutoff
+    '''
+    return load(lib).rrw_analytic_t(beta, a, boundary, cutoff)
+
+
+def load(lib = "target/release/libresetting_rw.so"):
+    '''
+    Loads the library and declares the signatures of the exported functions
+    '''
+    l = ctypes.CDLL(lib)
+    l.rrw_walker_new.restype = ctypes.c_void_p
+    l.rrw_walker_new.argtypes = [ctypes.c_char_p, ctypes.c_uint64]
+    l.rrw_walker_free.argtypes = [ctypes.c_void_p]
+    l.rrw_walker_run.argtypes = [ctypes.c_void_p, ctypes.c_uint64]
+    l.rrw_walker_samples.restype = ctypes.c_uint64
+    l.rrw_walker_samples.argtypes = [ctypes.c_void_p]
+    l.rrw_walker_mean.restype = ctypes.c_double
+    l.rrw_walker_mean.argtypes = [ctypes.c_void_p]
+    l.rrw_walker_variance.restype = ctypes.c_double
+    l.rrw_walker_variance.argtypes = [ctypes.c_void_p]
+    l.rrw_walker_histogram.argtypes = [
+        ctypes.c_void_p, ctypes.c_double, ctypes.c_double, ctypes.c_size_t, ctypes.POINTER(ctypes.c_uint64)
+    ]
+    l.rrw_analytic_t.restype = ctypes.c_double
+    l.rrw_analytic_t.argtypes = [ctypes.c_double, ctypes.c_double, ctypes.c_double, ctypes.c_int32]
+    return l
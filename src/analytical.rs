@@ -1,4 +1,7 @@
+//! Analytic results
 mod eq23;
 pub use eq23::*;
 mod fig4;
-pub use fig4::*;
\ No newline at end of file
+pub use fig4::*;
+mod mfpt;
+pub use mfpt::*;
\ No newline at end of file
@@ -1,8 +1,10 @@
 use clap::Parser;
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use std::io::Write;
 use crate::misc::*;
+use crate::error::*;
 
 
 
@@ -28,7 +30,8 @@ fn left(a: f64, n: i32, r: f64) -> f64
     0.5 * r.sqrt() / (1.0 + sum)
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, Parser)]
+#[derive(Clone, Debug, Serialize, Deserialize, Parser, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct Eq23Opt{
     /// a
     #[arg(long, short)]
@@ -52,9 +55,29 @@ pub struct Eq23Opt{
     filename: String
 }
 
-pub fn exec_eq_23(opt: Eq23Opt)
+impl Eq23Opt{
+    pub fn filename(&self) -> &str
+    {
+        &self.filename
+    }
+
+    /// Number of x values, i.e., of lines in the output file
+    pub fn samples(&self) -> i64
+    {
+        self.samples
+    }
+}
+
+impl Validate for Eq23Opt{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("r", self.r)
+    }
+}
+
+pub fn exec_eq_23(opt: Eq23Opt) -> Result<()>
 {
-    let x_arr = RatioIter::get_ratio_iter(opt.x_start, opt.x_end, opt.samples)
+    opt.validate()?;
+    let x_arr = RatioIter::get_ratio_iter(opt.x_start, opt.x_end, opt.samples)?
         .float_iter()
         .collect_vec();
     write_res(
@@ -66,21 +89,60 @@ pub fn exec_eq_23(opt: Eq23Opt)
     )
 }
 
+/// Stationary density P(x) of the walker with mirroring, Eq. 23
+pub struct Eq23{
+    a: f64,
+    left: f64,
+    r_root: f64,
+    products: Vec<f64>
+}
+
+impl Eq23{
+    /// Note: only the absolute value of a is used.
+    /// The sums are truncated at `cutoff`, something like 1000 should be good
+    pub fn new(a: f64, r: f64, cutoff: i32) -> Self
+    {
+        let a = a.abs();
+        Self{
+            a,
+            left: left(a, cutoff, r),
+            r_root: r.sqrt(),
+            products: naive_product(a, cutoff)
+        }
+    }
+
+    /// Evaluate P(x)
+    pub fn density(&self, x: f64) -> f64
+    {
+        let inner_left = (-x.abs() * self.r_root).exp();
+        let mut sum = 0.0;
+        for (prod, i) in self.products.iter().zip(1..){
+            let a_term = self.a.powi(-i);
+            // for small a the powers overflow long before the cutoff,
+            // all following terms vanish, but would be NaN
+            if a_term.is_infinite(){
+                break;
+            }
+            let factor = a_term * (-a_term * self.r_root * x.abs()).exp();
+            sum += factor / prod;
+        }
+        self.left * (inner_left + sum)
+    }
+}
+
 fn write_res(
     x_arr: &[f64], 
     a: f64, 
     cutoff: i32, 
     r: f64,
     filename: &str
-)
+) -> Result<()>
 {
     assert!(
         a >= 0.0,
         "Negative a not allowed here"
     );
-    let left = left(a, cutoff, r);
-
-    let products = naive_product(a, cutoff);
+    let eq23 = Eq23::new(a, r, cutoff);
 
     let header = [
         "x",
@@ -88,17 +150,9 @@ fn write_res(
     ];
     let mut buf = create_buf_with_command_and_version_and_header(filename, header);
 
-    let r_root = r.sqrt();
-    for x in x_arr{
-        let inner_left = (-x.abs() * r_root).exp();
-        let mut sum = 0.0;
-        for (prod, i) in products.iter().zip(1..){
-            let a_term = a.powi(-i);
-            let factor = a_term * (-a_term * r_root * x.abs()).exp();
-            sum += factor / prod;
-        }
-        let result = left * (inner_left + sum);
-        writeln!(buf, "{x} {result}").unwrap();
+    for &x in x_arr{
+        let result = eq23.density(x);
+        writeln!(buf, "{x} {result}")?;
     }
-
+    Ok(())
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 
-use crate::{misc::*, Fig4};
+use crate::{error::*, misc::*, Fig4};
 use core::f64;
 use std::{io::Write, process::Command};
 
@@ -41,14 +41,14 @@ impl Fig4Python{
                 {
                     let mut iter = line.split_ascii_whitespace();
                     let beta: f64 = match iter.next().unwrap().parse(){
-                        Result::Ok(val) =>  val,
+                        Ok(val) =>  val,
                         Err(_) => {
                             eprintln!("WARNING: BETA Parse Issue with {line}");
                             f64::NAN
                         }
                     };
                     let mfpt: f64  = match iter.next().unwrap().parse(){
-                        Result::Ok(val) =>  val,
+                        Ok(val) =>  val,
                         Err(_) => {
                             eprintln!("WARNING: MFPT Parse Issue with {line}");
                             f64::NAN
@@ -61,13 +61,13 @@ impl Fig4Python{
 }
 
 
-pub fn fig4(options: Fig4)
+pub fn fig4(options: Fig4) -> Result<()>
 {
     let range = RatioIter::get_ratio_iter(
         options.a_start, 
         options.a_end, 
         options.steps.get()
-    );
+    )?;
 
     let a_values = range.float_iter().collect_vec();
 
@@ -177,7 +177,7 @@ pub fn fig4(options: Fig4)
             writeln!(
                 buf,
                 "{a} {beta} {mfpt}"
-            ).unwrap();
+            )?;
         }
-
+        Ok(())
 }
\ No newline at end of file
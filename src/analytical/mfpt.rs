@@ -0,0 +1,68 @@
+//! Port of `python/analytics.py`
+
+fn f_even(beta: f64, a: f64, cutoff: i32) -> f64
+{
+    let beta2 = beta * beta;
+    let mut last = beta2 * 0.5;
+    let mut out = last;
+    for n in 2..cutoff{
+        let n = n as f64;
+        last *= beta2 / ((2.0 * n) * (2.0 * n - 1.0)) * (1.0 - a.powf(2.0 * (n - 1.0)));
+        out += last;
+    }
+    out
+}
+
+fn f_odd(beta: f64, a: f64, cutoff: i32, sign: f64) -> f64
+{
+    let beta2 = beta * beta;
+    let mut last = beta;
+    let mut out = beta;
+    for n in 1..cutoff{
+        let n = n as f64;
+        last *= beta2 / ((2.0 * n + 1.0) * (2.0 * n)) * (1.0 - sign * a.powf(2.0 * n - 1.0));
+        out += last;
+    }
+    out
+}
+
+/// Dimensionless mfpt for 0 < a < 1
+fn t_pos(beta: f64, a: f64, cutoff: i32) -> f64
+{
+    let mut r = (1.0 - a).recip();
+    for i in 1..cutoff{
+        r *= (1.0 - a.powi(2 * i)) / (1.0 - a.powi(2 * i + 1));
+    }
+    (f_even(beta, a, cutoff) + r * f_odd(beta, a, cutoff, 1.0)) / (beta * beta)
+}
+
+/// Dimensionless mfpt for -1 < a < 0. Here a is the absolute value.
+fn t_neg(beta: f64, a: f64, boundary: f64, cutoff: i32) -> f64
+{
+    let odd = f_odd(beta, a, cutoff, -1.0);
+    let odd_a = f_odd(beta / a, a, cutoff, -1.0);
+    let even = f_even(beta, a, cutoff);
+    let even_a = f_even(beta / a, a, cutoff);
+    (odd_a * even + odd * (even_a + boundary)) / (beta * beta * (odd + odd_a))
+}
+
+/// Dimensionless mfpt $\tilde{T}_a(0) = D T_a(0) / L^2$, where
+/// $\beta = \sqrt{r/D} L$.
+///
+/// For negative a the value of the mfpt at the boundary,
+/// $\tilde{T}_a(-L/|a|)$, is required. It has to be found numerically,
+/// 1 is a relatively ok approximation in some regimes.
+/// For positive a the boundary is ignored.
+///
+/// The series are truncated at `cutoff`, something like 1000 should be good.
+/// Returns NaN for a = 0, which is not supported
+pub fn mfpt(beta: f64, a: f64, boundary: f64, cutoff: i32) -> f64
+{
+    if a > 0.0 {
+        t_pos(beta, a, cutoff)
+    } else if a < 0.0 {
+        t_neg(beta, -a, boundary, cutoff)
+    } else {
+        f64::NAN
+    }
+}
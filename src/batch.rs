@@ -0,0 +1,175 @@
+//! Run all jobs of a manifest, see [crate::sweep], in one process.
+//!
+//! All jobs share one rayon thread pool. They are either executed one after another
+//! or concurrently. Jobs whose outputs are already complete are skipped,
+//! such that an interrupted batch can simply be restarted.
+//! The status and the run time of every job is recorded in a status file,
+//! which is rewritten whenever a job finishes.
+use std::{
+    io::BufWriter,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Instant, SystemTime}
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    error::*,
+    job::RunJob,
+    misc::with_job_additions,
+    parse::parse,
+    scan::{build_global_pool, ScanPart},
+    sweep::Manifest
+};
+
+#[derive(Parser, Debug)]
+pub struct BatchOpt{
+    /// Manifest created by `sweep`. The jobs have to be `run` jobs
+    pub manifest: Utf8PathBuf,
+
+    #[arg(short, long)]
+    /// Number of threads, shared by all jobs. The number of threads in the job files is ignored
+    pub threads: NonZeroUsize,
+
+    #[arg(long)]
+    /// Run the jobs concurrently instead of one after another
+    pub concurrent: bool,
+
+    #[arg(long)]
+    /// Also run the jobs whose outputs are already complete
+    pub force: bool,
+
+    #[arg(long)]
+    /// Where the status of the jobs is recorded. Default: `{manifest}_status.json`
+    pub status: Option<Utf8PathBuf>
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state")]
+pub enum JobState{
+    Pending,
+    Running,
+    /// All outputs were already complete
+    Skipped,
+    Done,
+    Failed{
+        error: String
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus{
+    pub file: Utf8PathBuf,
+    #[serde(flatten)]
+    pub state: JobState,
+    /// Time the job was started, RFC 3339
+    pub started: Option<String>,
+    pub seconds: Option<f64>
+}
+
+struct StatusFile{
+    path: Utf8PathBuf,
+    jobs: Mutex<Vec<JobStatus>>
+}
+
+impl StatusFile{
+    fn update(&self, idx: usize, f: impl FnOnce(&mut JobStatus)) -> Result<()>
+    {
+        let mut jobs = self.jobs.lock().unwrap();
+        f(&mut jobs[idx]);
+        // written while locked, such that the file always contains the latest state
+        let writer = BufWriter::new(fs_err::File::create(&self.path)?);
+        serde_json::to_writer_pretty(writer, &*jobs)?;
+        Ok(())
+    }
+}
+
+fn run_job(path: &Utf8Path, force: bool) -> Result<JobState>
+{
+    let (job, json): (RunJob, _) = parse(Some(path), &[])?;
+    if !force {
+        let mut complete = true;
+        for output in job.outputs(){
+            complete &= output.is_complete()?;
+        }
+        if complete {
+            return Ok(JobState::Skipped);
+        }
+    }
+    with_job_additions(
+        json.to_string(),
+        || job.run(ScanPart::default())
+    )?;
+    Ok(JobState::Done)
+}
+
+/// Run all jobs of the manifest.
+/// Failing jobs do not stop the batch, but an error is returned in the end
+pub fn batch(opt: BatchOpt) -> Result<()>
+{
+    let manifest = Manifest::read(&opt.manifest)?;
+    let dir = opt.manifest.parent().unwrap_or(Utf8Path::new(""));
+    let files: Vec<_> = manifest.jobs
+        .iter()
+        .map(|entry| dir.join(&entry.file))
+        .collect();
+
+    let status = StatusFile{
+        path: opt.status.unwrap_or_else(
+            || opt.manifest.with_file_name(format!("{}_status.json", opt.manifest.file_stem().unwrap_or("batch")))
+        ),
+        jobs: Mutex::new(
+            files.iter()
+                .map(|file| JobStatus { file: file.clone(), state: JobState::Pending, started: None, seconds: None })
+                .collect()
+        )
+    };
+
+    build_global_pool(opt.threads)?;
+
+    let execute = |(idx, file): (usize, &Utf8PathBuf)| -> Result<()> {
+        println!("starting {file}");
+        let start = Instant::now();
+        status.update(
+            idx,
+            |s| {
+                s.state = JobState::Running;
+                s.started = Some(humantime::format_rfc3339_seconds(SystemTime::now()).to_string());
+            }
+        )?;
+        let state = run_job(file, opt.force)
+            .unwrap_or_else(|e| JobState::Failed { error: e.to_string() });
+        println!("{file}: {state:?}");
+        status.update(
+            idx,
+            |s| {
+                s.state = state;
+                s.seconds = Some(start.elapsed().as_secs_f64());
+            }
+        )
+    };
+
+    if opt.concurrent {
+        files.par_iter()
+            .enumerate()
+            .try_for_each(execute)?;
+    } else {
+        files.iter()
+            .enumerate()
+            .try_for_each(execute)?;
+    }
+
+    let jobs = status.jobs.into_inner().unwrap();
+    let failed = jobs.iter()
+        .filter(|job| matches!(job.state, JobState::Failed{..}))
+        .count();
+    if failed > 0 {
+        Err(Error::Input(format!("{failed} of {} jobs failed, see {}", jobs.len(), status.path)))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+//! Throughput, work and memory of the efficient walkers over a grid of settings.
+//!
+//! Every combination of walker, bisection, rough step size and maximal depth is measured
+//! with the same number of samples. The mfpt is compared with a reference,
+//! such that the cost of a setting can be weighed against its bias.
+use std::{num::NonZeroUsize, time::Instant};
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use derivative::Derivative;
+use rand_pcg::Pcg64;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    compare::{analytic_mfpt, ANALYTIC_CUTOFF},
+    error::*,
+    parse::FieldOverride,
+    scan::{build_global_pool, sample_packets, PointSeed, PointStats},
+    shard::Shard,
+    table::TableWriter,
+    walker::{even_more_efficient_mirror::EffRandWalk2, *}
+};
+
+/// Walkers that can be benchmarked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum BenchmarkWalker{
+    /// [EffRandWalk]
+    Eff,
+    /// [EffRandWalk2], only with [Bisect::Threshold]
+    Eff2
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+pub struct BenchmarkJob{
+    /// rough_step_size and max_depth are replaced by the values of the grid
+    #[derivative(Default(value="RadomWalkSettings{ a: 0.5, lambda_mirror: 4.0, ..Default::default() }"))]
+    pub settings: RadomWalkSettings,
+    #[derivative(Default(value="vec![BenchmarkWalker::Eff, BenchmarkWalker::Eff2]"))]
+    pub walkers: Vec<BenchmarkWalker>,
+    /// [EffRandWalk2] skips the bisections with a fixed number of steps
+    #[derivative(Default(value="vec![Bisect::Threshold(1e-6), Bisect::Steps(NonZeroUsize::new(100).unwrap())]"))]
+    pub bisections: Vec<Bisect>,
+    #[derivative(Default(value="vec![0.01, 0.1]"))]
+    pub rough_step_sizes: Vec<f64>,
+    #[derivative(Default(value="vec![20, 40]"))]
+    pub max_depths: Vec<usize>,
+    /// mfpt the estimates are compared with.
+    /// If omitted, the analytic mfpt is used, which is only known for positive a and origin 0
+    pub reference: Option<f64>,
+    /// samples of every setting
+    #[derivative(Default(value="NonZeroUsize::new(10000).unwrap()"))]
+    pub samples: NonZeroUsize,
+    /// Number of threads
+    #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
+    pub j: NonZeroUsize,
+    pub seed: u64
+}
+
+/// One setting of the grid
+#[derive(Debug, Clone)]
+struct Setting{
+    walker: BenchmarkWalker,
+    settings: EffWalkerSettings
+}
+
+impl BenchmarkJob{
+    /// All settings of the grid that the walker supports, in the order walker, bisection, rough step size, depth
+    fn grid(&self) -> Vec<Setting>
+    {
+        let mut grid = Vec::new();
+        for &walker in self.walkers.iter(){
+            for &bisection in self.bisections.iter(){
+                if walker == BenchmarkWalker::Eff2 && bisection.threshold().is_none(){
+                    continue;
+                }
+                for &rough_step_size in self.rough_step_sizes.iter(){
+                    for &max_depth in self.max_depths.iter(){
+                        let mut settings = self.settings.clone();
+                        settings.rough_step_size = rough_step_size;
+                        settings.max_depth = max_depth;
+                        grid.push(Setting { walker, settings: EffWalkerSettings { settings, bisection } });
+                    }
+                }
+            }
+        }
+        grid
+    }
+
+    /// The reference mfpt
+    fn reference(&self) -> f64
+    {
+        self.reference
+            .or_else(|| analytic_reference(&self.settings))
+            .expect("validated")
+    }
+}
+
+/// The analytic mfpt of the walker, if it is known, i.e., for positive a and origin 0
+pub(crate) fn analytic_reference(settings: &RadomWalkSettings) -> Option<f64>
+{
+    (settings.a > 0.0 && settings.origin == 0.0)
+        .then(
+            || {
+                let beta = settings.lambda_mirror.sqrt() * settings.target;
+                analytic_mfpt(beta, settings.a, settings.target, 1.0, ANALYTIC_CUTOFF)
+            }
+        )
+}
+
+impl Validate for BenchmarkJob{
+    fn validate(&self) -> Result<()> {
+        for (name, empty) in [
+            ("walkers", self.walkers.is_empty()),
+            ("bisections", self.bisections.is_empty()),
+            ("rough_step_sizes", self.rough_step_sizes.is_empty()),
+            ("max_depths", self.max_depths.is_empty())
+        ]{
+            if empty{
+                return Err(Error::validation(name, "at least one value is required"));
+            }
+        }
+        match self.reference{
+            Some(reference) => ensure_positive("reference", reference)?,
+            None if analytic_reference(&self.settings).is_none() => {
+                return Err(Error::validation("reference", "is required, the analytic mfpt is only known for positive a and origin 0"));
+            },
+            None => ()
+        }
+        let grid = self.grid();
+        if grid.is_empty(){
+            return Err(Error::validation("bisections", "Eff2 needs at least one bisection with threshold"));
+        }
+        for setting in grid.iter(){
+            setting.settings.settings
+                .validate()
+                .map_err(|e| e.within("settings"))?;
+            setting.settings.bisection
+                .validate()
+                .map_err(|e| e.within("bisections"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct BenchmarkOpt{
+    #[arg(long, short, requires("out"))]
+    /// Path to json file. If omitted, an example is printed
+    pub json: Option<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of output file, the extension selects the format
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=1000`. Can be repeated
+    pub set: Vec<FieldOverride>
+}
+
+/// Accumulated samples and their cost
+#[derive(Debug, Default)]
+pub(crate) struct Measurement{
+    pub(crate) stats: PointStats,
+    pub(crate) rough_steps: u64,
+    pub(crate) bisections: u64,
+    pub(crate) walk_bytes: u64,
+    pub(crate) max_walk_bytes: usize,
+    pub(crate) queue_bytes: u64,
+    pub(crate) max_queue_bytes: usize,
+    /// Wall time of all samples
+    pub(crate) seconds: f64
+}
+
+impl Measurement{
+    fn add(&mut self, sample: &Sample, cost: WalkCost)
+    {
+        self.stats.add(sample);
+        self.rough_steps += cost.rough_steps as u64;
+        self.bisections += cost.bisections as u64;
+        self.walk_bytes += cost.walk_bytes as u64;
+        self.max_walk_bytes = self.max_walk_bytes.max(cost.walk_bytes);
+        self.queue_bytes += cost.queue_bytes as u64;
+        self.max_queue_bytes = self.max_queue_bytes.max(cost.queue_bytes);
+    }
+
+    fn merge(mut self, other: &Self) -> Self
+    {
+        self.stats.merge(&other.stats);
+        self.rough_steps += other.rough_steps;
+        self.bisections += other.bisections;
+        self.walk_bytes += other.walk_bytes;
+        self.max_walk_bytes = self.max_walk_bytes.max(other.max_walk_bytes);
+        self.queue_bytes += other.queue_bytes;
+        self.max_queue_bytes = self.max_queue_bytes.max(other.max_queue_bytes);
+        self
+    }
+
+    /// Mean per sample
+    pub(crate) fn mean(&self, sum: u64) -> f64
+    {
+        sum as f64 / self.stats.samples as f64
+    }
+
+    pub(crate) fn standard_error(&self) -> f64
+    {
+        (self.stats.variance_fpt() / self.stats.samples as f64).sqrt()
+    }
+
+    pub(crate) fn samples_per_second(&self) -> f64
+    {
+        self.stats.samples as f64 / self.seconds
+    }
+}
+
+/// Draw the samples of a setting in the current rayon pool, like [crate::scan::measure_point]
+pub(crate) fn measure(
+    walker: BenchmarkWalker,
+    settings: &EffWalkerSettings,
+    seed: PointSeed,
+    samples: NonZeroUsize
+) -> Measurement
+{
+    let start = Instant::now();
+    let mut measurement = match walker{
+        BenchmarkWalker::Eff => measure_walker::<EffRandWalk<Pcg64>>(settings, seed, samples),
+        BenchmarkWalker::Eff2 => measure_walker::<EffRandWalk2<Pcg64>>(settings, seed, samples)
+    };
+    measurement.seconds = start.elapsed().as_secs_f64();
+    measurement
+}
+
+fn measure_walker<W>(settings: &EffWalkerSettings, seed: PointSeed, samples: NonZeroUsize) -> Measurement
+where W: Walker<Settings = EffWalkerSettings>
+{
+    sample_packets::<W, Measurement, _>(
+        settings,
+        seed,
+        samples,
+        Shard::WHOLE,
+        false,
+        |measurement, walker, sample| measurement.add(sample, walker.cost().expect("the efficient walkers know their cost"))
+    ).iter()
+        .fold(Measurement::default(), Measurement::merge)
+}
+
+/// Measure every setting of the grid and write one row per setting
+pub fn benchmark(job: BenchmarkJob, file_name: Utf8PathBuf) -> Result<()>
+{
+    build_global_pool(job.j)?;
+    let reference = job.reference();
+    let grid = job.grid();
+
+    let comments = vec![
+        "walker: 0 = Eff, 1 = Eff2, a bisection with threshold has steps NaN, one with steps has threshold NaN".to_owned(),
+        format!("reference: {reference}"),
+        format!("threads: {} seed: {}, the point of the seed is the row", job.j, job.seed)
+    ];
+    let mut table = TableWriter::create_with_comments(
+        &file_name,
+        comments,
+        [
+            "walker",
+            "threshold",
+            "steps",
+            "rough_step_size",
+            "max_depth",
+            "mfpt",
+            "standard_error",
+            "bias",
+            "z",
+            "samples_per_second",
+            "rough_steps",
+            "bisections",
+            "walk_bytes",
+            "max_walk_bytes",
+            "queue_bytes",
+            "max_queue_bytes"
+        ]
+    )?;
+
+    println!("reference mfpt {reference}");
+    for (i, setting) in grid.iter().enumerate(){
+        let seed = PointSeed::new(job.seed, i);
+        let measurement = measure(setting.walker, &setting.settings, seed, job.samples);
+        let samples_per_second = measurement.samples_per_second();
+
+        let stats = &measurement.stats;
+        let standard_error = measurement.standard_error();
+        let bias = stats.mean_fpt() - reference;
+        let z = bias / standard_error;
+        let (threshold, steps) = match setting.settings.bisection{
+            Bisect::Threshold(th) => (th, f64::NAN),
+            Bisect::Steps(steps) => (f64::NAN, steps.get() as f64)
+        };
+        let walk = &setting.settings.settings;
+        table.write_row(
+            &[
+                setting.walker as usize as f64,
+                threshold,
+                steps,
+                walk.rough_step_size,
+                walk.max_depth as f64,
+                stats.mean_fpt(),
+                standard_error,
+                bias,
+                z,
+                samples_per_second,
+                measurement.mean(measurement.rough_steps),
+                measurement.mean(measurement.bisections),
+                measurement.mean(measurement.walk_bytes),
+                measurement.max_walk_bytes as f64,
+                measurement.mean(measurement.queue_bytes),
+                measurement.max_queue_bytes as f64
+            ]
+        )?;
+        table.record_point(i as f64, &seed)?;
+        println!(
+            "{:?} {:?} rough step {} depth {}: bias {bias} (z = {z:.2}), {samples_per_second:.0} samples/s, {:.1} bisections/sample, {} bytes at most",
+            setting.walker,
+            setting.settings.bisection,
+            walk.rough_step_size,
+            walk.max_depth,
+            measurement.mean(measurement.bisections),
+            measurement.max_walk_bytes + measurement.max_queue_bytes
+        );
+    }
+    table.finish()
+}
@@ -0,0 +1,131 @@
+//! Compare a simulated scan of β with the analytic mfpt, see [crate::analytical::mfpt].
+//!
+//! a and the target are read from the json configuration embedded in the scan,
+//! the errors of the simulation are the standard errors of the mean.
+use camino::Utf8PathBuf;
+use clap::Parser;
+use serde_json::Value;
+
+use crate::{
+    analytical::mfpt,
+    dat::DatFile,
+    error::*,
+    table::TableWriter
+};
+
+/// Truncation of the series of the analytic mfpt
+pub const ANALYTIC_CUTOFF: i32 = 1000;
+
+#[derive(Parser, Debug)]
+pub struct CompareOpt{
+    /// Output file of a scan of β, e.g., of eff-rand-walk-beta or of a merge of its shards
+    pub file: Utf8PathBuf,
+
+    #[arg(long, short)]
+    /// Name of the output file, the extension selects the format
+    pub out: Utf8PathBuf,
+
+    #[arg(long, default_value_t = 3.0)]
+    /// Points whose z-score exceeds this in magnitude are flagged as deviating
+    pub threshold: f64,
+
+    #[arg(long, default_value_t = 1.0)]
+    /// Dimensionless mfpt at the boundary, only used by the analytic mfpt for negative a
+    pub boundary: f64,
+
+    #[arg(long, default_value_t = ANALYTIC_CUTOFF)]
+    /// Truncation of the series of the analytic mfpt
+    pub cutoff: i32
+}
+
+/// a and the target of the walker in the configuration of a file.
+/// The analytic mfpt is only known for walkers that start at the origin 0, about which they are mirrored
+pub fn walker_parameters(file: &DatFile) -> Result<(f64, f64)>
+{
+    let missing = || Error::Input(
+        format!("{} contains no configuration with `a` and `target`", file.path.display())
+    );
+    let settings = file.header
+        .find_config("settimgs")
+        .ok_or_else(missing)?;
+    let a = settings.get("a").and_then(Value::as_f64).ok_or_else(missing)?;
+    let target = settings.get("target").and_then(Value::as_f64).ok_or_else(missing)?;
+    if a == 0.0{
+        return Err(Error::Input(format!("{}: the analytic mfpt is not known for a = 0", file.path.display())));
+    }
+    // configurations from before the origin could be chosen start at 0
+    let origin = match settings.get("origin"){
+        None => 0.0,
+        Some(origin) => origin.as_f64().ok_or_else(missing)?
+    };
+    if origin != 0.0{
+        return Err(
+            Error::Input(
+                format!("{}: the analytic mfpt is only known for origin 0, but the walker starts at {origin}", file.path.display())
+            )
+        );
+    }
+    Ok((a, target))
+}
+
+/// Analytic mfpt of our walkers with the target at distance `target`.
+/// They have D = 1, thus this is the dimensionless mfpt times the target squared
+pub fn analytic_mfpt(beta: f64, a: f64, target: f64, boundary: f64, cutoff: i32) -> f64
+{
+    mfpt(beta, a, boundary, cutoff) * target * target
+}
+
+/// Write the residuals and z-scores of every point of the scan
+/// and summarize them by χ²
+pub fn compare(opt: CompareOpt) -> Result<()>
+{
+    if opt.threshold.is_nan() || opt.threshold <= 0.0{
+        return Err(Error::validation("threshold", "has to be positive"));
+    }
+    let file = DatFile::read_dat_only(&opt.file)?;
+    let (a, target) = walker_parameters(&file)?;
+    let beta = file.column("β")?;
+    let simulated = file.column("mfpt")?;
+    let error = file.standard_error()?
+        .ok_or_else(|| Error::Input(format!("{} has no columns variance and samples", opt.file)))?;
+
+    let mut rows = Vec::with_capacity(beta.len());
+    let mut chi_sq = 0.0;
+    let mut points = 0_usize;
+    let mut deviating = 0_usize;
+    for ((beta, simulated), error) in beta.iter().zip(simulated.iter()).zip(error.iter()){
+        let analytic = analytic_mfpt(*beta, a, target, opt.boundary, opt.cutoff);
+        let residual = simulated - analytic;
+        // points without error, e.g., with a single sample, have no z-score
+        let z = if *error > 0.0 { residual / error } else { f64::NAN };
+        let deviates = z.abs() > opt.threshold;
+        if z.is_finite(){
+            chi_sq += z * z;
+            points += 1;
+        }
+        deviating += usize::from(deviates);
+        rows.push([*beta, *simulated, analytic, residual, *error, z, f64::from(u8::from(deviates))]);
+    }
+    let reduced = chi_sq / points as f64;
+
+    let comments = vec![
+        format!("a: {a} target: {target} boundary: {} cutoff: {}", opt.boundary, opt.cutoff),
+        format!(
+            "χ²: {chi_sq} points: {points} reduced χ²: {reduced} deviating (|z| > {}): {deviating}",
+            opt.threshold
+        )
+    ];
+    let mut table = TableWriter::create_with_comments(
+        &opt.out,
+        comments,
+        ["β", "mfpt", "analytic", "residual", "standard_error", "z", "deviates"]
+    )?;
+    for row in rows.iter(){
+        table.write_row(row)?;
+    }
+    table.finish()?;
+
+    println!("χ² = {chi_sq} for {points} points, reduced χ² = {reduced}");
+    println!("{deviating} of {} points deviate by more than {} standard errors", rows.len(), opt.threshold);
+    Ok(())
+}
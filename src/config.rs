@@ -1,9 +1,28 @@
+//! Command line options
 use std::num::{NonZeroI64, NonZeroUsize};
 
 use clap::{Parser, Subcommand};
 use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 
 use crate::analytical::Eq23Opt;
+use crate::schema::SchemaOpt;
+use crate::sweep::SweepOpt;
+use crate::batch::BatchOpt;
+use crate::{scan::ScanPart, shard::MergeOpt};
+use crate::pool::MergeRunsOpt;
+use crate::replay::ReplayOpt;
+use crate::events::EventsOpt;
+use crate::plot::PlotOpt;
+use crate::compare::CompareOpt;
+use crate::cross_validation::CrossValidateOpt;
+use crate::benchmark::BenchmarkOpt;
+use crate::extrapolation::ExtrapolateOpt;
+use crate::tune::TuneOpt;
+use crate::table::OutputFormat;
+use crate::error::*;
+use crate::parse::FieldOverride;
 
 
 #[derive(Parser)]
@@ -39,18 +58,60 @@ pub enum Exec{
     /// Testing efficient random Walker
     TestEffRandWalk,
     #[clap(visible_alias="effrm")]
+    /// Do an efficient scan by measuring the mfpt for varying mirror rate lambda
     EffRandWalkLambda(JsonPathOpt),
     #[clap(visible_alias="effrt")]
+    /// Do an efficient scan by measuring the mfpt for varying target position L
     EffRandWalkTarget(JsonPathOpt),
+    /// Do an efficient scan by measuring the mfpt for varying beta,
+    /// with the walker that keeps all refinement levels
     EffRandWalkBeta(BetaOpt),
     #[clap(visible_alias="beta")]
     /// Do an efficient scan by measuring the mfpt for varying beta
     Eff2RandWalkBeta(BetaOpt),
     EffBetaCreateJob(BetaJob),
+    #[clap(visible_alias="measure")]
+    /// Measure the mfpt of any walker, which is selected in the json file
+    MeasureWalker(JsonPathOpt),
     /// Execute equation 23
     Eq23(Eq23Opt),
     /// Create data for figure 4 b c
-    Fig4(Fig4)
+    Fig4(Fig4),
+    /// Print the JSON Schema of the job files
+    Schema(SchemaOpt),
+    /// Run the job described by a json file, which contains all parameters
+    ///
+    /// `--shard` and `--point` are supported by the eff jobs and MeasureWalker,
+    /// the part is written to `{out}_shard{INDEX}of{COUNT}_point{INDEX}`
+    Run(RunOpt),
+    /// Create job files by sweeping over fields of a base job file
+    Sweep(SweepOpt),
+    /// Run all jobs of a manifest created by sweep
+    Batch(BatchOpt),
+    /// Merge the outputs of all shards of a scan, see `--shard`
+    Merge(MergeOpt),
+    /// Pool the results of runs that only differ in their seed
+    MergeRuns(MergeRunsOpt),
+    /// Regenerate the trajectory of a single sample of an eff scan
+    Replay(ReplayOpt),
+    /// Distribution of the number of mirror or reset events before the first passage
+    /// and the mfpt conditioned on it, for any walker like `measure-walker`
+    Events(EventsOpt),
+    /// Plot scans, histograms and trajectories as SVG, without gnuplot
+    Plot(PlotOpt),
+    /// Compare a scan of β with the analytic mfpt: residuals, z-scores and χ²
+    Compare(CompareOpt),
+    /// Measure the mfpt of one configuration with several walker implementations
+    /// and check that they agree
+    CrossValidate(CrossValidateOpt),
+    /// Samples per second, bisections per sample, memory and bias of the efficient walkers
+    /// over a grid of settings
+    Benchmark(BenchmarkOpt),
+    /// Run a walker at several resolutions, i.e., step sizes or depths, and extrapolate the mfpt to step size zero
+    Extrapolate(ExtrapolateOpt),
+    /// Choose the cheapest threshold, max_depth and rough_step_size of the efficient walkers
+    /// whose bias is below a tolerance
+    Tune(TuneOpt)
 }
 
 #[derive(Parser)]
@@ -88,6 +149,10 @@ pub struct A{
     #[arg(long, short)]
     /// Path to json file
     pub json: Option<String>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set settimgs.origin=0.1`. Can be repeated
+    pub set: Vec<FieldOverride>
 }
 
 #[derive(Parser, Debug, Clone)]
@@ -106,7 +171,29 @@ pub struct Refine{
 
     #[arg(long, short)]
     /// Change max depth
-    pub max_depth: Option<NonZeroUsize>
+    pub max_depth: Option<NonZeroUsize>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set settimgs.origin=0.1`. Can be repeated
+    pub set: Vec<FieldOverride>
+}
+
+impl Refine{
+    /// The explicit options are applied after the ones given via `--set`
+    pub fn overrides(&self) -> Vec<FieldOverride>
+    {
+        let mut overrides = self.set.clone();
+        if let Some(p) = self.samples_per_point{
+            overrides.push(FieldOverride::new("samples_per_point", p.get()));
+        }
+        if let Some(j) = self.j{
+            overrides.push(FieldOverride::new("j", j.get()));
+        }
+        if let Some(depth) = self.max_depth{
+            overrides.push(FieldOverride::new("settimgs.max_depth", depth.get()));
+        }
+        overrides
+    }
 }
 
 #[derive(Subcommand, Debug, Clone)]
@@ -125,7 +212,14 @@ pub struct JsonPathOpt{
 
     #[arg(long, short)]
     /// Name of output file
-    pub out: Option<Utf8PathBuf>
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set j=4`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[command(flatten)]
+    pub part: ScanPart
 }
 
 #[derive(Parser)]
@@ -139,15 +233,42 @@ pub struct BetaOpt{
     pub out: Option<Utf8PathBuf>,
 
     #[arg(short)]
-    /// force change a, override settings
-    pub a: Option<f64>
+    /// force change a, short for `--set settimgs.a=A`
+    pub a: Option<f64>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set settimgs.a=0.5`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[command(flatten)]
+    pub part: ScanPart
+}
+
+impl BetaOpt{
+    /// `-a` is applied after the overrides given via `--set`
+    pub fn overrides(&self) -> Vec<FieldOverride>
+    {
+        let mut overrides = self.set.clone();
+        if let Some(a) = self.a{
+            overrides.push(FieldOverride::new("settimgs.a", a));
+        }
+        overrides
+    }
 }
 
 #[derive(Parser)]
 pub struct MirrorHists{
     #[arg(long, short)]
     /// Path to json
-    pub json_path: Option<Utf8PathBuf>
+    pub json_path: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=100`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[arg(long, value_enum, default_value_t)]
+    /// Format of the output files
+    pub format: OutputFormat
 }
 
 #[derive(Parser)]
@@ -211,39 +332,50 @@ pub struct MirrorResetPdfOpts{
     pub mirror_prob: f64
 }
 
-#[derive(Parser)]
-pub struct ScanOpts{
-    /// Path to the input JSON file. If not given, it will print out a default config file.
+/// Scan of the rate of the events. Start and end are included
+#[derive(Parser, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LambdaScanOpts{
     #[arg(long, short)]
-    pub json: Option<Utf8PathBuf>,
-
     /// Number of samples to take.
-    #[arg(long, short)]
     pub samples: usize,
 
-    /// Number of threads to use.
     #[arg(long, short)]
-    pub threads: usize,
+    /// Number of threads to use.
+    pub threads: NonZeroUsize,
 
     /// Start value of lambda.
-    #[arg(long, short)]
+    #[arg(long)]
     pub lambda_start: f64,
 
     /// End value of lambda.
-    #[arg(long, short)]
+    #[arg(long)]
     pub lambda_end: f64,
 
     /// Number of samples to take for lambda.
-    #[arg(long, short)]
+    #[arg(long)]
     pub lambda_samples: usize
 }
 
-#[derive(Parser)]
-pub struct UniScanOpts{
-    /// Path to the input JSON file. If not given, it will print out a default config file
-    #[arg(long, short, requires("out"))]
-    pub json: Option<Utf8PathBuf>,
+impl LambdaScanOpts{
+    /// The lambda values of the scan
+    pub fn lambdas(&self) -> impl Iterator<Item = f64> + '_
+    {
+        (0..self.lambda_samples)
+            .map(|i| self.lambda_start + i as f64 *(self.lambda_end - self.lambda_start) / (self.lambda_samples - 1) as f64)
+    }
+
+    /// The largest lambda of the scan, the step size has to be small compared to its mean time between events
+    pub fn max_lambda(&self) -> f64
+    {
+        self.lambda_start.max(self.lambda_end)
+    }
+}
 
+/// Scan of the target position L. Start and end are included
+#[derive(Parser, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct LScanOpts{
     #[arg(long, short)]
     /// Number of samples to take.
     pub samples: usize,
@@ -252,52 +384,80 @@ pub struct UniScanOpts{
     /// Number of threads to use.
     pub threads: NonZeroUsize,
 
-    /// Start value of lambda.
-    #[arg(long, short)]
-    pub lambda_start: f64,
+    /// Start value of L.
+    #[arg(long)]
+    pub l_start: f64,
 
-    /// End value of lambda.
-    #[arg(long, short)]
-    pub lambda_end: f64,
+    /// End value of L.
+    #[arg(long)]
+    pub l_end: f64,
 
-    /// Number of samples to take for lambda.
-    #[arg(long, short)]
-    pub lambda_samples: usize,
+    /// Number of samples to take for L.
+    #[arg(long)]
+    pub l_samples: usize
+}
 
-    /// Path to the output file
+impl LScanOpts{
+    /// The values of L of the scan
+    pub fn ls(&self) -> impl Iterator<Item = f64> + '_
+    {
+        (0..self.l_samples)
+            .map(|i| self.l_start + i as f64 *(self.l_end - self.l_start) / (self.l_samples - 1) as f64)
+    }
+}
+
+#[derive(Parser)]
+pub struct ScanOpts{
+    /// Path to the input JSON file. If not given, it will print out a default config file.
     #[arg(long, short)]
-    pub out: Option<Utf8PathBuf>
+    pub json: Option<Utf8PathBuf>,
+
+    #[command(flatten)]
+    pub scan: LambdaScanOpts,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set step_size=0.001`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[arg(long, value_enum, default_value_t)]
+    /// Format of the output files
+    pub format: OutputFormat
 }
 
 #[derive(Parser)]
-pub struct LUniScanOpts{
+pub struct UniScanOpts{
     /// Path to the input JSON file. If not given, it will print out a default config file
     #[arg(long, short, requires("out"))]
     pub json: Option<Utf8PathBuf>,
 
-    #[arg(long, short)]
-    /// Number of samples to take.
-    pub samples: usize,
+    #[command(flatten)]
+    pub scan: LambdaScanOpts,
 
+    /// Path to the output file
     #[arg(long, short)]
-    /// Number of threads to use.
-    pub threads: NonZeroUsize,
+    pub out: Option<Utf8PathBuf>,
 
-    /// Start value of L.
-    #[arg(long, short)]
-    pub l_start: f64,
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set uni_mid=-0.5`. Can be repeated
+    pub set: Vec<FieldOverride>
+}
 
-    /// End value of L.
-    #[arg(long, short)]
-    pub l_end: f64,
+#[derive(Parser)]
+pub struct LUniScanOpts{
+    /// Path to the input JSON file. If not given, it will print out a default config file
+    #[arg(long, short, requires("out"))]
+    pub json: Option<Utf8PathBuf>,
 
-    /// Number of samples to take for L.
-    #[arg(long, short)]
-    pub l_samples: usize,
+    #[command(flatten)]
+    pub scan: LScanOpts,
 
     /// Path to the output file
     #[arg(long, short)]
-    pub out: Option<Utf8PathBuf>
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set uni_mid=-0.5`. Can be repeated
+    pub set: Vec<FieldOverride>
 }
 
 #[derive(Parser)]
@@ -310,27 +470,73 @@ pub struct MirrorScanOpts{
     #[arg(long, short)]
     pub json: Option<Utf8PathBuf>,
 
-    /// Number of samples to take.
-    #[arg(long, short)]
-    pub samples: usize,
+    #[command(flatten)]
+    pub scan: LambdaScanOpts,
 
-    /// Number of threads to use.
+    /// Probability of mirror reset.
     #[arg(long, short)]
-    pub threads: usize,
+    pub mirror_prob: f64,
 
-    /// Start value of lambda.
-    #[arg(long, short)]
-    pub lambda_start: f64,
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set step_size=0.001`. Can be repeated
+    pub set: Vec<FieldOverride>,
 
-    /// End value of lambda.
-    #[arg(long, short)]
-    pub lambda_end: f64,
+    #[arg(long, value_enum, default_value_t)]
+    /// Format of the output files
+    pub format: OutputFormat
+}
 
-    /// Number of samples to take for lambda.
-    #[arg(long, short)]
-    pub lambda_samples: usize,
+#[derive(Parser)]
+pub struct RunOpt{
+    /// Path to the job file. If not given, an example job is printed.
+    /// The schema of the job files is printed by `schema run`
+    pub job: Option<Utf8PathBuf>,
 
-    /// Probability of mirror reset.
-    #[arg(long, short)]
-    pub mirror_prob: f64
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set settings.j=4`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[command(flatten)]
+    pub part: ScanPart
+}
+
+impl Validate for LambdaScanOpts{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("lambda_start", self.lambda_start)?;
+        ensure_positive("lambda_end", self.lambda_end)?;
+        ensure_range_samples("lambda_samples", self.lambda_samples)
+    }
+}
+
+impl Validate for LScanOpts{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("l_start", self.l_start)?;
+        ensure_positive("l_end", self.l_end)?;
+        ensure_range_samples("l_samples", self.l_samples)
+    }
+}
+
+impl Validate for ScanOpts{
+    fn validate(&self) -> Result<()> {
+        self.scan.validate()
+    }
+}
+
+impl Validate for MirrorScanOpts{
+    fn validate(&self) -> Result<()> {
+        self.scan.validate()?;
+        ensure_probability("mirror_prob", self.mirror_prob)
+    }
+}
+
+impl Validate for UniScanOpts{
+    fn validate(&self) -> Result<()> {
+        self.scan.validate()
+    }
+}
+
+impl Validate for LUniScanOpts{
+    fn validate(&self) -> Result<()> {
+        self.scan.validate()
+    }
 }
@@ -0,0 +1,253 @@
+//! Run several implementations of the mirroring walker on the same configuration and check that they agree.
+//!
+//! Every implementation draws its samples from its own seed, see [crate::scan::PointSeed],
+//! such that the estimates are independent and the discrepancy of two of them
+//! can be judged by their standard errors.
+use std::{num::NonZeroUsize, time::Instant};
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    error::*,
+    parse::FieldOverride,
+    scan::{build_global_pool, measure_point, PointSeed, PointStats},
+    table::TableWriter,
+    walker::*
+};
+
+/// Implementations that estimate the mfpt of a walker which is mirrored, x → a x, at rate λ
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum Implementation{
+    /// [ResettingUniWalker::mirror_until_found], steps of `step_size`
+    Uni,
+    /// [ResettingUniWalker::adaptive_mirror_until_found], the steps shrink close to the target
+    AdaptiveUni,
+    /// [EffRandWalk]
+    Eff,
+    /// [even_more_efficient_mirror::EffRandWalk2]
+    Eff2
+}
+
+impl Implementation{
+    /// Name as in the json file
+    pub fn name(self) -> String
+    {
+        format!("{self:?}")
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+pub struct CrossValidationJob{
+    /// at a mirror event the position x is mapped to a*x
+    #[derivative(Default(value="0.5"))]
+    pub a: f64,
+    /// rate of the mirror events
+    #[derivative(Default(value="1.0"))]
+    pub lambda_mirror: f64,
+    /// position of the target, the walkers start at 0
+    #[derivative(Default(value="1.0"))]
+    pub target: f64,
+    /// step size of uni
+    #[derivative(Default(value="0.00025"))]
+    pub step_size: f64,
+    /// step size of the rough walk of eff and eff2
+    #[derivative(Default(value="0.01"))]
+    pub rough_step_size: f64,
+    /// refinement of eff and eff2, eff2 needs a threshold
+    #[derivative(Default(value="Bisect::Threshold(1e-6)"))]
+    pub bisection: Bisect,
+    /// maximum number of refinement levels of eff and eff2
+    #[derivative(Default(value="40"))]
+    pub max_depth: usize,
+    #[derivative(Default(value="vec![Implementation::Uni, Implementation::Eff, Implementation::Eff2]"))]
+    pub implementations: Vec<Implementation>,
+    /// samples of every implementation
+    #[derivative(Default(value="NonZeroUsize::new(10000).unwrap()"))]
+    pub samples: NonZeroUsize,
+    /// Number of threads
+    #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
+    pub j: NonZeroUsize,
+    pub seed: u64
+}
+
+impl CrossValidationJob{
+    /// Settings of the walker of one implementation
+    pub fn walker(&self, implementation: Implementation) -> AnyWalkerSettings
+    {
+        let uni = |mode| {
+            AnyWalkerSettings::Uni(
+                ResettingUniWalkerSettings{
+                    husk: ResettingUniWalkerHusk{
+                        rng_seed: self.seed,
+                        uni_mid: self.a,
+                        uni_delta_2: 0.0,
+                        // not used by the walk functions that only mirror
+                        reset_lambda: self.lambda_mirror,
+                        mirror_lambda: self.lambda_mirror,
+                        target_pos: self.target,
+                        step_size: self.step_size
+                    },
+                    mode
+                }
+            )
+        };
+        let eff = EffWalkerSettings{
+            settings: RadomWalkSettings{
+                lambda_mirror: self.lambda_mirror,
+                rough_step_size: self.rough_step_size,
+                target: self.target,
+                a: self.a,
+                max_depth: self.max_depth,
+                origin: 0.0
+            },
+            bisection: self.bisection
+        };
+        match implementation{
+            Implementation::Uni => uni(UniMode::OnlyMirror),
+            Implementation::AdaptiveUni => uni(UniMode::AdaptiveOnlyMirror),
+            Implementation::Eff => AnyWalkerSettings::Eff(eff),
+            Implementation::Eff2 => AnyWalkerSettings::Eff2(eff)
+        }
+    }
+}
+
+impl Validate for CrossValidationJob{
+    fn validate(&self) -> Result<()> {
+        if self.implementations.is_empty(){
+            return Err(Error::validation("implementations", "at least one implementation is required"));
+        }
+        for (i, implementation) in self.implementations.iter().enumerate(){
+            if self.implementations[..i].contains(implementation){
+                return Err(Error::validation("implementations", format!("{} is listed twice", implementation.name())));
+            }
+            self.walker(*implementation)
+                .validate()
+                .map_err(|e| e.within(&implementation.name()))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct CrossValidateOpt{
+    #[arg(long, short, requires("out"))]
+    /// Path to json file. If omitted, an example is printed
+    pub json: Option<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of output file, the extension selects the format
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=1000`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[arg(long, default_value_t = 3.0)]
+    /// Pairs of implementations whose discrepancy exceeds this many standard errors are flagged
+    pub threshold: f64
+}
+
+/// Result of one implementation
+struct Estimate{
+    implementation: Implementation,
+    stats: PointStats,
+    seconds: f64
+}
+
+impl Estimate{
+    fn standard_error(&self) -> f64
+    {
+        (self.stats.variance_fpt() / self.stats.samples as f64).sqrt()
+    }
+}
+
+/// Measure the mfpt with every implementation of the job, write one row per implementation
+/// and report the significance of the pairwise discrepancies
+pub fn cross_validate(job: CrossValidationJob, file_name: Utf8PathBuf, threshold: f64) -> Result<()>
+{
+    if threshold.is_nan() || threshold <= 0.0{
+        return Err(Error::validation("threshold", "has to be positive"));
+    }
+    build_global_pool(job.j)?;
+
+    let mut estimates = Vec::with_capacity(job.implementations.len());
+    for implementation in job.implementations.iter().copied(){
+        println!("running {}", implementation.name());
+        // the seed depends on the implementation, not on its position in the list
+        let seed = PointSeed::new(job.seed, implementation as usize);
+        let start = Instant::now();
+        let stats = measure_point::<AnyWalker>(&job.walker(implementation), seed, job.samples);
+        estimates.push(Estimate { implementation, stats, seconds: start.elapsed().as_secs_f64() });
+    }
+
+    let mut comments = vec![
+        format!(
+            "implementation: {}",
+            estimates.iter()
+                .map(|e| format!("{} = {}", e.implementation as usize, e.implementation.name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        format!("threads: {} seed: {}, the point of the seed is the number of the implementation", job.j, job.seed)
+    ];
+    let mut deviating = 0;
+    for (i, first) in estimates.iter().enumerate(){
+        for second in estimates[i + 1..].iter(){
+            let difference = first.stats.mean_fpt() - second.stats.mean_fpt();
+            let z = difference / first.standard_error().hypot(second.standard_error());
+            let flag = if z.abs() > threshold {
+                deviating += 1;
+                " DEVIATING"
+            } else {
+                ""
+            };
+            comments.push(
+                format!("{} - {}: {difference} z: {z}{flag}", first.implementation.name(), second.implementation.name())
+            );
+        }
+    }
+
+    let mut table = TableWriter::create_with_comments(
+        &file_name,
+        comments.clone(),
+        ["implementation", "samples", "mfpt", "standard_error", "variance", "mirrors", "seconds", "seconds_per_sample"]
+    )?;
+    for estimate in estimates.iter(){
+        let stats = &estimate.stats;
+        // thread seconds, such that the cost does not depend on the number of threads
+        let per_sample = estimate.seconds * job.j.get() as f64 / stats.samples as f64;
+        table.write_row(
+            &[
+                estimate.implementation as usize as f64,
+                stats.samples as f64,
+                stats.mean_fpt(),
+                estimate.standard_error(),
+                stats.variance_fpt(),
+                stats.mean_mirrors(),
+                estimate.seconds,
+                per_sample
+            ]
+        )?;
+        println!(
+            "{:<13} mfpt {} ± {}, {per_sample:e} thread seconds per sample",
+            estimate.implementation.name(),
+            stats.mean_fpt(),
+            estimate.standard_error()
+        );
+    }
+    table.finish()?;
+
+    for line in comments.iter().skip(2){
+        println!("{line}");
+    }
+    if deviating > 0{
+        println!("{deviating} pairs deviate by more than {threshold} standard errors");
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Reader for our commented `.dat` output files.
+//!
+//! The header of an output file consists of comment lines starting with `#`:
+//! the version, the git hash and compile time, the json configuration,
+//! the command line and the column header, see [crate::misc::write_commands_and_version]
+//! and [crate::misc::write_slice_head]. Older files start with the json directly after the `#`,
+//! see [crate::misc::write_json], and have column headers without the `_N` suffixes.
+//! Lines are recognized by their content, not by their position, so every part of the header is optional.
+//! All other lines are rows of numbers.
+use std::{
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf}
+};
+
+use serde_json::Value;
+
+use crate::{error::*, table::OutputFormat};
+
+const GIT_HASH_PREFIX: &str = "Git Hash: ";
+const COMPILE_TIME_SEPARATOR: &str = " Compile-time: ";
+
+/// Everything in the comment lines of an output file
+#[derive(Debug, Clone, Default)]
+pub struct DatHeader{
+    /// Version of the program that wrote the file
+    pub version: Option<String>,
+    pub git_hash: Option<String>,
+    pub compile_time: Option<String>,
+    /// The embedded json configuration
+    pub config: Option<Value>,
+    /// The command line, split at whitespace
+    pub command: Option<Vec<String>>,
+    /// Names of the columns, without the `_N` suffixes
+    pub columns: Vec<String>,
+    /// All other comment lines, without the leading `# `
+    pub other: Vec<String>
+}
+
+impl DatHeader{
+    /// Index of the column with the given name
+    pub fn column(&self, name: &str) -> Option<usize>
+    {
+        self.columns
+            .iter()
+            .position(|col| col == name)
+    }
+
+    /// First comment line that starts with `prefix`, without the prefix
+    pub fn other_with_prefix(&self, prefix: &str) -> Option<&str>
+    {
+        self.other
+            .iter()
+            .find_map(|line| line.strip_prefix(prefix))
+    }
+
+    /// First value with the given key anywhere in the json configuration
+    pub fn find_config(&self, key: &str) -> Option<&Value>
+    {
+        self.config
+            .as_ref()
+            .and_then(|config| find_key(config, key))
+    }
+}
+
+fn find_key<'a>(json: &'a Value, key: &str) -> Option<&'a Value>
+{
+    match json{
+        Value::Object(map) => map.get(key)
+            .or_else(|| map.values().find_map(|val| find_key(val, key))),
+        Value::Array(arr) => arr.iter().find_map(|val| find_key(val, key)),
+        _ => None
+    }
+}
+
+/// A whole output file
+#[derive(Debug, Clone)]
+pub struct DatFile{
+    pub path: PathBuf,
+    pub header: DatHeader,
+    pub rows: Vec<Vec<f64>>
+}
+
+impl DatFile{
+    pub fn read<P>(path: P) -> Result<Self>
+    where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        let reader = BufReader::new(fs_err::File::open(path)?);
+        Self::from_reader(reader, path)
+    }
+
+    /// Like [DatFile::read], but files in the other [OutputFormat]s are rejected
+    /// with a proper error message instead of a parse error
+    pub fn read_dat_only<P>(path: P) -> Result<Self>
+    where P: AsRef<Path>
+    {
+        let path = path.as_ref();
+        if OutputFormat::from_path(path) != OutputFormat::Dat{
+            return Err(Error::Input(format!("{}: only .dat files can be read", path.display())));
+        }
+        Self::read(path)
+    }
+
+    /// Parse a file, `path` is only used in the error messages
+    pub fn from_reader<R>(reader: R, path: &Path) -> Result<Self>
+    where R: BufRead
+    {
+        let mut header = DatHeader::default();
+        let mut rows = Vec::new();
+        for (line, nr) in reader.lines().zip(1..){
+            let line = line?;
+            match line.strip_prefix('#'){
+                Some(comment) => parse_comment(comment, &mut header, path)?,
+                None if line.trim().is_empty() => (),
+                None => {
+                    let row = line.split_whitespace()
+                        .map(str::parse)
+                        .collect::<std::result::Result<Vec<f64>, _>>()
+                        .map_err(|e| Error::Input(format!("{}:{nr}: invalid number: {e}", path.display())))?;
+                    rows.push(row);
+                }
+            }
+        }
+        Ok(Self{ path: path.to_owned(), header, rows })
+    }
+
+    /// All values of the column with the given name
+    pub fn column(&self, name: &str) -> Result<Vec<f64>>
+    {
+        let idx = self.header
+            .column(name)
+            .ok_or_else(|| Error::Input(format!("{} has no column {name}", self.path.display())))?;
+        self.rows
+            .iter()
+            .zip(1..)
+            .map(
+                |(row, nr)| row.get(idx)
+                    .copied()
+                    .ok_or_else(|| Error::Input(format!("{}: row {nr} has no column {name}", self.path.display())))
+            ).collect()
+    }
+
+    /// Standard error of the mean of every row, sqrt(variance / samples),
+    /// if the file has the columns `variance` and `samples`
+    pub fn standard_error(&self) -> Result<Option<Vec<f64>>>
+    {
+        if self.header.column("variance").is_none() || self.header.column("samples").is_none(){
+            return Ok(None);
+        }
+        let variance = self.column("variance")?;
+        let samples = self.column("samples")?;
+        Ok(Some(
+            variance.iter()
+                .zip(samples.iter())
+                .map(|(var, samples)| (var / samples).sqrt())
+                .collect()
+        ))
+    }
+}
+
+fn parse_comment(comment: &str, header: &mut DatHeader, path: &Path) -> Result<()>
+{
+    // the older scans write their column header without a space after the `#`
+    let legacy_columns = !comment.starts_with(' ') && !comment.starts_with('{');
+    let comment = comment.trim();
+
+    if comment.is_empty(){
+        return Ok(());
+    } else if comment.starts_with('{'){
+        let config = serde_json::from_str(comment)
+            .map_err(|source| Error::Json { path: Some(path.to_owned()), source })?;
+        header.config = Some(config);
+    } else if let Some(rest) = comment.strip_prefix(GIT_HASH_PREFIX){
+        let (hash, time) = rest.split_once(COMPILE_TIME_SEPARATOR)
+            .unwrap_or((rest, ""));
+        header.git_hash = Some(hash.to_owned());
+        header.compile_time = (!time.is_empty()).then(|| time.to_owned());
+    } else if header.version.is_none() && header.command.is_none() && is_version(comment){
+        header.version = Some(comment.to_owned());
+    } else if let Some(columns) = numbered_columns(comment){
+        header.columns = columns;
+    } else if legacy_columns{
+        header.columns = comment.split_whitespace()
+            .map(str::to_owned)
+            .collect();
+    } else if header.command.is_none() && !comment.contains(": "){
+        header.command = Some(
+            comment.split_whitespace()
+                .map(str::to_owned)
+                .collect()
+        );
+    } else {
+        header.other.push(comment.to_owned());
+    }
+    Ok(())
+}
+
+fn is_version(comment: &str) -> bool
+{
+    comment.starts_with(|c: char| c.is_ascii_digit())
+        && comment.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+'))
+}
+
+/// Column header written by [crate::misc::write_slice_head], e.g., `lambda_1 mfpt_2`
+fn numbered_columns(comment: &str) -> Option<Vec<String>>
+{
+    let columns = comment.split_whitespace()
+        .zip(1_u16..)
+        .map(
+            |(col, i)| {
+                let (name, num) = col.rsplit_once('_')?;
+                (num.parse() == Ok(i)).then(|| name.to_owned())
+            }
+        ).collect::<Option<Vec<_>>>()?;
+    (!columns.is_empty()).then_some(columns)
+}
@@ -0,0 +1,191 @@
+//! Error type of the crate and validation of the settings
+use std::{fmt::{self, Display}, path::PathBuf};
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error{
+    /// Reading or writing a file failed
+    Io(std::io::Error),
+    /// The json file could not be parsed
+    Json{
+        path: Option<PathBuf>,
+        source: serde_json::Error
+    },
+    /// The json file does not match the schema of the job,
+    /// see [crate::schema::diff]
+    Schema{
+        path: Option<PathBuf>,
+        source: serde_json::Error,
+        differences: Vec<String>
+    },
+    /// Parameters that are syntactically fine, but do not make sense
+    Validation{
+        field: String,
+        message: String
+    },
+    /// Anything else that is wrong with the input, e.g., unexpected content of a file
+    Input(String)
+}
+
+impl Error{
+    pub fn validation<F, M>(field: F, message: M) -> Self
+    where F: Into<String>,
+        M: Into<String>
+    {
+        Self::Validation { field: field.into(), message: message.into() }
+    }
+
+    /// Prefix the name of the field that failed the validation, e.g.,
+    /// `lambda_mirror` becomes `settimgs.lambda_mirror`
+    pub fn within(self, parent: &str) -> Self
+    {
+        match self{
+            Self::Validation { field, message } => {
+                let field = if field.is_empty(){
+                    parent.to_owned()
+                } else {
+                    format!("{parent}.{field}")
+                };
+                Self::Validation { field, message }
+            },
+            other => other
+        }
+    }
+
+    /// Exit codes follow sysexits.h
+    pub fn exit_code(&self) -> u8
+    {
+        match self{
+            Self::Io(_) => 74,
+            Self::Json{..} | Self::Schema{..} | Self::Validation{..} | Self::Input(_) => 65
+        }
+    }
+}
+
+impl Display for Error{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self{
+            Self::Io(e) => write!(f, "I/O error: {e}"),
+            Self::Json { path: Some(path), source } => {
+                write!(f, "json parsing error in {}: {source}", path.display())
+            },
+            Self::Json { path: None, source } => write!(f, "json parsing error: {source}"),
+            Self::Schema { path, source, differences } => {
+                match path{
+                    Some(path) => write!(f, "{} does not match the schema: {source}", path.display())?,
+                    None => write!(f, "json does not match the schema: {source}")?
+                }
+                for difference in differences{
+                    write!(f, "\n  - {difference}")?;
+                }
+                write!(f, "\nthe schemas of all job files are printed by the `schema` subcommand")
+            },
+            Self::Validation { field, message } => write!(f, "invalid value of `{field}`: {message}"),
+            Self::Input(message) => write!(f, "{message}")
+        }
+    }
+}
+
+impl std::error::Error for Error{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self{
+            Self::Io(e) => Some(e),
+            Self::Json { source, .. } | Self::Schema { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Error{
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+impl From<serde_json::Error> for Error{
+    fn from(value: serde_json::Error) -> Self {
+        if value.is_io(){
+            return Self::Io(value.into());
+        }
+        Self::Json { path: None, source: value }
+    }
+}
+
+/// Checks that the settings make sense before anything is simulated
+pub trait Validate{
+    fn validate(&self) -> Result<()>;
+}
+
+impl<T: Validate> Validate for Option<T>{
+    fn validate(&self) -> Result<()> {
+        match self{
+            Some(val) => val.validate(),
+            None => Ok(())
+        }
+    }
+}
+
+pub(crate) fn ensure_positive(field: &str, value: f64) -> Result<()>
+{
+    if value > 0.0 && value.is_finite(){
+        Ok(())
+    } else {
+        Err(Error::validation(field, format!("has to be positive and finite, but is {value}")))
+    }
+}
+
+pub(crate) fn ensure_finite(field: &str, value: f64) -> Result<()>
+{
+    if value.is_finite(){
+        Ok(())
+    } else {
+        Err(Error::validation(field, format!("has to be finite, but is {value}")))
+    }
+}
+
+/// The step size has to be small compared to the mean time between events,
+/// otherwise the walker will (almost) never move between events
+pub(crate) fn ensure_step_below_event_time(field: &str, step_size: f64, rate: f64) -> Result<()>
+{
+    ensure_positive(field, step_size)?;
+    let mean_time = rate.recip();
+    if step_size < mean_time{
+        Ok(())
+    } else {
+        Err(
+            Error::validation(
+                field,
+                format!("step size {step_size} has to be smaller than the mean time between events, which is {mean_time}")
+            )
+        )
+    }
+}
+
+pub(crate) fn ensure_below_target(field: &str, origin: f64, target: f64) -> Result<()>
+{
+    if origin < target{
+        Ok(())
+    } else {
+        Err(Error::validation(field, format!("the walker has to start below the target {target}, but starts at {origin}")))
+    }
+}
+
+/// Ranges need at least the start and the end point
+pub(crate) fn ensure_range_samples(field: &str, samples: usize) -> Result<()>
+{
+    if samples >= 2 {
+        Ok(())
+    } else {
+        Err(Error::validation(field, format!("a range needs at least 2 samples, but {samples} were requested")))
+    }
+}
+
+pub(crate) fn ensure_probability(field: &str, value: f64) -> Result<()>
+{
+    if (0.0..=1.0).contains(&value){
+        Ok(())
+    } else {
+        Err(Error::validation(field, format!("has to be a probability in [0, 1], but is {value}")))
+    }
+}
@@ -0,0 +1,158 @@
+//! Statistics of the mirror and reset events before the first passage.
+//!
+//! The samples are drawn like by `measure-walker`, see [crate::scan::measure_walker],
+//! and grouped by their number of events. Optionally every single event is logged.
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, ValueEnum};
+
+use crate::{
+    error::*,
+    parse::FieldOverride,
+    scan::{build_global_pool, sample_packets, MeasureWalkerOpt, PointSeed, PointStats},
+    shard::Shard,
+    table::TableWriter,
+    walker::{AnyWalker, Sample, WalkEvent, Walker}
+};
+
+/// Which events are counted
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum EventCount{
+    #[default]
+    Mirrors,
+    Resets,
+    /// Mirrors and resets
+    All
+}
+
+impl EventCount{
+    fn name(self) -> &'static str
+    {
+        match self{
+            Self::Mirrors => "mirrors",
+            Self::Resets => "resets",
+            Self::All => "events"
+        }
+    }
+
+    fn count(self, sample: &Sample) -> u64
+    {
+        match self{
+            Self::Mirrors => sample.mirrors,
+            Self::Resets => sample.resets,
+            Self::All => sample.mirrors + sample.resets
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct EventsOpt{
+    #[arg(long, short, requires("out"))]
+    /// Path to json file, the same as for `measure-walker`
+    pub json: Option<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of output file, the extension selects the format
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=1000`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[arg(long, value_enum, default_value_t)]
+    /// Events that are counted
+    pub count: EventCount,
+
+    #[arg(long)]
+    /// Also write every event of every sample into this file
+    pub log: Option<Utf8PathBuf>
+}
+
+/// Accumulated samples of one packet
+#[derive(Default)]
+struct PacketEvents{
+    /// Statistics of the samples with `i` events at index `i`
+    by_count: Vec<PointStats>,
+    /// Events of every sample, only if they are logged
+    log: Vec<Vec<WalkEvent>>
+}
+
+/// Measure the distribution of the number of events before the first passage
+/// and the mfpt conditioned on the number of events
+pub fn measure_events(
+    opt: MeasureWalkerOpt,
+    file_name: Utf8PathBuf,
+    count: EventCount,
+    log: Option<&Utf8Path>
+) -> Result<()>
+{
+    build_global_pool(opt.j)?;
+    let seed = PointSeed::new(opt.seed, 0);
+    let packets = sample_packets::<AnyWalker, PacketEvents, _>(
+        &opt.walker,
+        seed,
+        opt.samples,
+        Shard::WHOLE,
+        log.is_some(),
+        |acc, walker, sample| {
+            let n = count.count(sample) as usize;
+            if acc.by_count.len() <= n{
+                acc.by_count.resize_with(n + 1, PointStats::default);
+            }
+            acc.by_count[n].add(sample);
+            if log.is_some(){
+                acc.log.push(walker.events().to_vec());
+            }
+        }
+    );
+
+    let mut by_count: Vec<PointStats> = Vec::new();
+    let mut total = PointStats::default();
+    for packet in packets.iter(){
+        if by_count.len() < packet.by_count.len(){
+            by_count.resize_with(packet.by_count.len(), PointStats::default);
+        }
+        for (stats, other) in by_count.iter_mut().zip(packet.by_count.iter()){
+            stats.merge(other);
+            total.merge(other);
+        }
+    }
+    let mean_events = by_count.iter()
+        .zip(0_u64..)
+        .map(|(stats, n)| (stats.samples * n) as f64)
+        .sum::<f64>() / total.samples as f64;
+
+    let name = count.name();
+    let comments = vec![
+        format!("mean {name}: {mean_events} mfpt: {}", total.mean_fpt())
+    ];
+    let mut table = TableWriter::create_with_comments(
+        &file_name,
+        comments,
+        [name, "samples", "probability", "mfpt", "variance"]
+    )?;
+    for (stats, n) in by_count.iter().zip(0_u64..){
+        if stats.samples == 0{
+            continue;
+        }
+        let probability = stats.samples as f64 / total.samples as f64;
+        table.write_row(&[n as f64, stats.samples as f64, probability, stats.mean_fpt(), stats.variance_fpt()])?;
+    }
+    table.record_point(0.0, &seed)?;
+    table.finish()?;
+
+    if let Some(log) = log{
+        let comments = vec!["event: 0 = mirror, 1 = reset".to_owned()];
+        let mut table = TableWriter::create_with_comments(log, comments, ["sample", "time", "event", "before", "after"])?;
+        let samples = packets.iter()
+            .flat_map(|packet| packet.log.iter());
+        for (events, sample) in samples.zip(0_u64..){
+            for event in events{
+                table.write_row(&[sample as f64, event.time, event.kind as u8 as f64, event.before, event.after])?;
+            }
+        }
+        table.record_point(0.0, &seed)?;
+        table.finish()?;
+    }
+    println!("mean {name} before the first passage: {mean_events}, mfpt {}", total.mean_fpt());
+    Ok(())
+}
@@ -0,0 +1,283 @@
+//! Richardson extrapolation of the mfpt to step size zero.
+//!
+//! A walker is run at several resolutions h and the mfpt is fitted by
+//! $$ T(h) = T_0 + \sum_k c_k h^{p_k} $$
+//! with weights given by the standard errors. The discrete walkers overshoot the target
+//! by an amount of order $\sqrt{h}$, thus the default is a single exponent 1/2.
+//! $T_0$ is the extrapolated mfpt, $\sum_k c_k h^{p_k}$ the bias at resolution h.
+//! h is the step size of the naive walkers and the length of the deepest intervals
+//! of the efficient walkers, which are refined by increasing `max_depth`,
+//! see [AnyWalkerSettings::resolution].
+use std::num::NonZeroUsize;
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    error::*,
+    parse::FieldOverride,
+    scan::{build_global_pool, measure_point, PointSeed},
+    table::TableWriter,
+    walker::{AnyWalker, AnyWalkerSettings}
+};
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+pub struct ExtrapolationJob{
+    /// The walker at the resolution of interest, its bias is estimated.
+    /// See [AnyWalkerSettings::refine] for what is varied
+    pub walker: AnyWalkerSettings,
+    /// Resolutions at which the walker is run, relative to its own.
+    /// Powers of two for the efficient walkers, each halving adds a level to `max_depth`
+    #[derivative(Default(value="vec![1.0, 0.5, 0.25, 0.125]"))]
+    pub refinements: Vec<f64>,
+    /// Exponents of the resolution in the fit of the bias
+    #[derivative(Default(value="vec![0.5]"))]
+    pub exponents: Vec<f64>,
+    /// samples of every resolution
+    #[derivative(Default(value="NonZeroUsize::new(10000).unwrap()"))]
+    pub samples: NonZeroUsize,
+    /// Number of threads
+    #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
+    pub j: NonZeroUsize,
+    pub seed: u64
+}
+
+impl ExtrapolationJob{
+    /// The walker refined by `refinement`
+    fn refined(&self, refinement: f64) -> Result<AnyWalkerSettings>
+    {
+        let mut walker = self.walker.clone();
+        walker.refine(refinement)
+            .map_err(|e| e.within("refinements"))?;
+        Ok(walker)
+    }
+}
+
+/// Ensures that the values are positive and distinct
+fn ensure_distinct_positive(field: &str, values: &[f64]) -> Result<()>
+{
+    for (i, value) in values.iter().enumerate(){
+        ensure_positive(field, *value)?;
+        if values[..i].contains(value){
+            return Err(Error::validation(field, format!("{value} is listed twice")));
+        }
+    }
+    Ok(())
+}
+
+impl Validate for ExtrapolationJob{
+    fn validate(&self) -> Result<()> {
+        self.walker.validate().map_err(|e| e.within("walker"))?;
+        ensure_distinct_positive("refinements", &self.refinements)?;
+        ensure_distinct_positive("exponents", &self.exponents)?;
+        if self.exponents.is_empty(){
+            return Err(Error::validation("exponents", "at least one exponent is required"));
+        }
+        if self.refinements.len() <= self.exponents.len(){
+            return Err(
+                Error::validation(
+                    "refinements",
+                    format!("the fit with {} exponents needs at least {} resolutions", self.exponents.len(), self.exponents.len() + 1)
+                )
+            );
+        }
+        if self.samples.get() < 2{
+            return Err(Error::validation("samples", "at least 2 are needed for the standard errors"));
+        }
+        for refinement in self.refinements.iter(){
+            self.refined(*refinement)?
+                .validate()
+                .map_err(|e| e.within("walker"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct ExtrapolateOpt{
+    #[arg(long, short, requires("out"))]
+    /// Path to json file. If omitted, an example is printed
+    pub json: Option<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of output file, the extension selects the format
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=1000`. Can be repeated
+    pub set: Vec<FieldOverride>
+}
+
+/// Inverse of a symmetric positive definite matrix by Gauss-Jordan elimination with partial pivoting.
+/// None if it is singular
+fn invert(mut matrix: Vec<Vec<f64>>) -> Option<Vec<Vec<f64>>>
+{
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<f64>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect())
+        .collect();
+    for col in 0..n{
+        let pivot = (col..n).max_by(|a, b| matrix[*a][col].abs().total_cmp(&matrix[*b][col].abs()))?;
+        if matrix[pivot][col] == 0.0 || !matrix[pivot][col].is_finite(){
+            return None;
+        }
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let factor = matrix[col][col].recip();
+        matrix[col].iter_mut().for_each(|val| *val *= factor);
+        inverse[col].iter_mut().for_each(|val| *val *= factor);
+        for row in 0..n{
+            if row == col{
+                continue;
+            }
+            let factor = matrix[row][col];
+            for k in 0..n{
+                matrix[row][k] -= factor * matrix[col][k];
+                inverse[row][k] -= factor * inverse[col][k];
+            }
+        }
+    }
+    Some(inverse)
+}
+
+/// Weighted least squares fit of T(h) = T_0 + Σ_k c_k h^{p_k}
+struct Fit{
+    exponents: Vec<f64>,
+    /// T_0 and the c_k
+    params: Vec<f64>,
+    covariance: Vec<Vec<f64>>
+}
+
+impl Fit{
+    fn basis(exponents: &[f64], h: f64) -> Vec<f64>
+    {
+        std::iter::once(1.0)
+            .chain(exponents.iter().map(|p| h.powf(*p)))
+            .collect()
+    }
+
+    fn new(exponents: &[f64], resolutions: &[f64], mfpt: &[f64], standard_error: &[f64]) -> Result<Self>
+    {
+        let n = exponents.len() + 1;
+        let mut normal = vec![vec![0.0; n]; n];
+        let mut rhs = vec![0.0; n];
+        for ((h, y), error) in resolutions.iter().zip(mfpt).zip(standard_error){
+            let weight = error.powi(-2);
+            let basis = Self::basis(exponents, *h);
+            for i in 0..n{
+                rhs[i] += weight * basis[i] * y;
+                for j in 0..n{
+                    normal[i][j] += weight * basis[i] * basis[j];
+                }
+            }
+        }
+        let covariance = invert(normal)
+            .ok_or_else(|| Error::Input("the resolutions do not determine the fit, the normal matrix is singular".to_owned()))?;
+        let params = covariance.iter()
+            .map(|row| row.iter().zip(rhs.iter()).map(|(c, r)| c * r).sum())
+            .collect();
+        Ok(Self { exponents: exponents.to_vec(), params, covariance })
+    }
+
+    fn extrapolated(&self) -> (f64, f64)
+    {
+        (self.params[0], self.covariance[0][0].sqrt())
+    }
+
+    fn value(&self, h: f64) -> f64
+    {
+        Self::basis(&self.exponents, h)
+            .iter()
+            .zip(self.params.iter())
+            .map(|(b, p)| b * p)
+            .sum()
+    }
+
+    /// Fitted bias at resolution h and its standard error
+    fn bias(&self, h: f64) -> (f64, f64)
+    {
+        let mut gradient = Self::basis(&self.exponents, h);
+        gradient[0] = 0.0;
+        let bias = gradient.iter().zip(self.params.iter()).map(|(g, p)| g * p).sum();
+        let variance: f64 = gradient.iter()
+            .zip(self.covariance.iter())
+            .map(|(g, row)| g * row.iter().zip(gradient.iter()).map(|(c, g)| c * g).sum::<f64>())
+            .sum();
+        (bias, variance.sqrt())
+    }
+}
+
+/// Run the walker at every resolution, fit the mfpt and report the extrapolation to resolution zero
+pub fn extrapolate(job: ExtrapolationJob, file_name: Utf8PathBuf) -> Result<()>
+{
+    build_global_pool(job.j)?;
+
+    let mut resolutions = Vec::with_capacity(job.refinements.len());
+    let mut mfpt = Vec::with_capacity(job.refinements.len());
+    let mut standard_error = Vec::with_capacity(job.refinements.len());
+    for (i, refinement) in job.refinements.iter().enumerate(){
+        let walker = job.refined(*refinement)?;
+        let h = walker.resolution();
+        println!("running resolution {h}");
+        let stats = measure_point::<AnyWalker>(&walker, PointSeed::new(job.seed, i), job.samples);
+        let error = (stats.variance_fpt() / stats.samples as f64).sqrt();
+        if error == 0.0{
+            return Err(Error::Input(format!("the first passage times at resolution {h} have no variance, more samples are needed")));
+        }
+        resolutions.push(h);
+        mfpt.push(stats.mean_fpt());
+        standard_error.push(error);
+    }
+
+    let fit = Fit::new(&job.exponents, &resolutions, &mfpt, &standard_error)?;
+    let chi_sq: f64 = resolutions.iter()
+        .zip(mfpt.iter())
+        .zip(standard_error.iter())
+        .map(|((h, y), e)| ((y - fit.value(*h)) / e).powi(2))
+        .sum();
+    let dof = resolutions.len() - fit.params.len();
+
+    let resolution = job.walker.resolution();
+    let (extrapolated, extrapolated_error) = fit.extrapolated();
+    let (bias, bias_error) = fit.bias(resolution);
+    let terms: String = job.exponents
+        .iter()
+        .enumerate()
+        .map(|(k, p)| format!(" + c_{} h^{p}", k + 1))
+        .collect();
+    let mut comments = vec![
+        format!("fit: mfpt(h) = T_0{terms}"),
+        format!("T_0: {extrapolated} ± {extrapolated_error}")
+    ];
+    comments.extend(
+        fit.params[1..]
+            .iter()
+            .enumerate()
+            .map(|(k, c)| format!("c_{}: {c} ± {}", k + 1, fit.covariance[k + 1][k + 1].sqrt()))
+    );
+    comments.push(format!("bias at resolution {resolution}: {bias} ± {bias_error}"));
+    if dof > 0{
+        comments.push(format!("χ²: {chi_sq} degrees of freedom: {dof} reduced χ²: {}", chi_sq / dof as f64));
+    }
+
+    let mut table = TableWriter::create_with_comments(
+        &file_name,
+        comments.clone(),
+        ["resolution", "mfpt", "standard_error", "fit", "z"]
+    )?;
+    for ((h, y), e) in resolutions.iter().zip(mfpt.iter()).zip(standard_error.iter()){
+        let value = fit.value(*h);
+        table.write_row(&[*h, *y, *e, value, (y - value) / e])?;
+    }
+    table.finish()?;
+
+    for line in comments{
+        println!("{line}");
+    }
+    Ok(())
+}
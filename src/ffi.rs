@@ -0,0 +1,192 @@
+//! C ABI of the simulators, see `include/resetting_rw.h`.
+//!
+//! A walker is created from a json string containing [AnyWalkerSettings],
+//! samples are accumulated inside of the walker handle and can be evaluated afterwards.
+//! All functions catch panics, errors are signaled via the return values.
+use std::{
+    ffi::{c_char, c_int, CStr},
+    panic::{catch_unwind, AssertUnwindSafe}
+};
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+
+use crate::{
+    analytical,
+    error::Validate,
+    walker::{AnyWalker, AnyWalkerSettings, Walker}
+};
+
+/// Opaque handle containing a walker and all first passage times sampled so far
+pub struct RrwWalker{
+    walker: AnyWalker,
+    fpts: Vec<f64>
+}
+
+const OK: c_int = 0;
+const ERROR: c_int = -1;
+
+/// Create a walker from a json string containing the walker settings.
+/// Returns null if the json or the settings are invalid.
+///
+/// # Safety
+/// `json` has to be a valid, nul terminated string.
+/// The returned handle has to be released with [rrw_walker_free]
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_new(json: *const c_char, seed: u64) -> *mut RrwWalker
+{
+    if json.is_null(){
+        return std::ptr::null_mut();
+    }
+    let json = match CStr::from_ptr(json).to_str(){
+        Ok(json) => json,
+        Err(_) => return std::ptr::null_mut()
+    };
+    let result = catch_unwind(
+        ||
+        {
+            let settings: AnyWalkerSettings = serde_json::from_str(json).ok()?;
+            settings.validate().ok()?;
+            let rng = Pcg64::seed_from_u64(seed);
+            let walker = AnyWalker::create(&settings, rng);
+            Some(
+                RrwWalker{
+                    walker,
+                    fpts: Vec::new()
+                }
+            )
+        }
+    );
+    match result{
+        Ok(Some(walker)) => Box::into_raw(Box::new(walker)),
+        _ => std::ptr::null_mut()
+    }
+}
+
+/// Release a walker
+///
+/// # Safety
+/// `walker` has to be created by [rrw_walker_new] or be null.
+/// It must not be used afterwards
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_free(walker: *mut RrwWalker)
+{
+    if !walker.is_null(){
+        drop(Box::from_raw(walker));
+    }
+}
+
+/// Draw `samples` additional samples. Returns 0 on success
+///
+/// # Safety
+/// `walker` has to be a valid handle created by [rrw_walker_new]
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_run(walker: *mut RrwWalker, samples: u64) -> c_int
+{
+    let walker = match walker.as_mut(){
+        Some(w) => w,
+        None => return ERROR
+    };
+    let result = catch_unwind(
+        AssertUnwindSafe(
+            ||
+            {
+                walker.fpts.reserve(samples as usize);
+                for _ in 0..samples{
+                    let sample = walker.walker.sample();
+                    walker.fpts.push(sample.fpt);
+                    walker.walker.recycle();
+                }
+            }
+        )
+    );
+    match result{
+        Ok(_) => OK,
+        Err(_) => ERROR
+    }
+}
+
+/// Number of samples drawn so far
+///
+/// # Safety
+/// `walker` has to be a valid handle created by [rrw_walker_new]
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_samples(walker: *const RrwWalker) -> u64
+{
+    walker.as_ref()
+        .map_or(0, |w| w.fpts.len() as u64)
+}
+
+/// Mean first passage time of all samples drawn so far. NaN if there are none
+///
+/// # Safety
+/// `walker` has to be a valid handle created by [rrw_walker_new]
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_mean(walker: *const RrwWalker) -> f64
+{
+    match walker.as_ref(){
+        Some(w) if !w.fpts.is_empty() => {
+            w.fpts.iter().sum::<f64>() / w.fpts.len() as f64
+        },
+        _ => f64::NAN
+    }
+}
+
+/// Variance of the first passage times of all samples drawn so far. NaN if there are none
+///
+/// # Safety
+/// `walker` has to be a valid handle created by [rrw_walker_new]
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_variance(walker: *const RrwWalker) -> f64
+{
+    let mean = rrw_walker_mean(walker);
+    match walker.as_ref(){
+        Some(w) if !w.fpts.is_empty() => {
+            w.fpts.iter()
+                .map(|fpt| (fpt - mean) * (fpt - mean))
+                .sum::<f64>() / w.fpts.len() as f64
+        },
+        _ => f64::NAN
+    }
+}
+
+/// Histogram of the first passage times with `bins` equally sized bins in [left, right).
+/// Samples outside of the interval are ignored.
+/// Returns 0 on success
+///
+/// # Safety
+/// `walker` has to be a valid handle created by [rrw_walker_new] and
+/// `hist` has to point to an array of at least `bins` elements
+#[no_mangle]
+pub unsafe extern "C" fn rrw_walker_histogram(
+    walker: *const RrwWalker,
+    left: f64,
+    right: f64,
+    bins: usize,
+    hist: *mut u64
+) -> c_int
+{
+    let walker = match walker.as_ref(){
+        Some(w) => w,
+        None => return ERROR
+    };
+    if hist.is_null() || bins == 0 || left >= right {
+        return ERROR;
+    }
+    let hist = std::slice::from_raw_parts_mut(hist, bins);
+    hist.fill(0);
+    let bin_width = (right - left) / bins as f64;
+    for fpt in walker.fpts.iter(){
+        if (left..right).contains(fpt){
+            let bin = (((fpt - left) / bin_width) as usize).min(bins - 1);
+            hist[bin] += 1;
+        }
+    }
+    OK
+}
+
+/// Analytic dimensionless mfpt, see [analytical::mfpt]
+#[no_mangle]
+pub extern "C" fn rrw_analytic_t(beta: f64, a: f64, boundary: f64, cutoff: i32) -> f64
+{
+    analytical::mfpt(beta, a, boundary, cutoff)
+}
@@ -0,0 +1,237 @@
+//! Jobs that are completely described by a json file, see the `run` subcommand.
+//!
+//! The kind of the job is selected by the `type` key, all other keys are the
+//! parameters of the job, including the output file and the number of threads.
+//! Thus every experiment can be versioned and rerun.
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    analytical::{exec_eq_23, Eq23Opt},
+    config::{LScanOpts, LambdaScanOpts},
+    error::*,
+    scan::{measure_walker, MeasureWalkerOpt, ScanPart},
+    table::{count_rows, OutputFormat},
+    walker::*
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", deny_unknown_fields)]
+pub enum RunJob{
+    /// Scan lambda with the resetting walker, like `scan-resetting`
+    ScanResetting{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        scan: LambdaScanOpts,
+        walker: ResettingWalkerHusk
+    },
+    /// Scan lambda with the walker that mirrors or resets, like `scan-mirror-resetting`
+    ScanMirrorResetting{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        scan: LambdaScanOpts,
+        mirror_prob: f64,
+        walker: ResettingMirrorWalkerHusk
+    },
+    /// Scan the mirror rate with the uniform mirror walker, like `uni`, `unim` and `unima`
+    ScanUni{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        scan: LambdaScanOpts,
+        mode: UniMode,
+        walker: ResettingUniWalkerHusk
+    },
+    /// Scan the target position with the uniform mirror walker, like `scanl` and `scanlb`.
+    /// Does not support the mode `AdaptiveOnlyMirror`
+    ScanLUni{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        scan: LScanOpts,
+        mode: UniMode,
+        walker: ResettingUniWalkerHusk
+    },
+    /// Histograms of the only mirroring walker, like `mirror-hists`.
+    /// Writes one file `{out_prefix}_{time}.{extension}` per time
+    MirrorHists{
+        out_prefix: String,
+        /// Format of the files `{out_prefix}_{time}.{extension}`
+        #[serde(default)]
+        format: OutputFormat,
+        settings: MirroringWalkerHistJob
+    },
+    /// Scan lambda with the efficient walker, like `eff-rand-walk-lambda`
+    EffLambda{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        settings: MeasureMfptOpt
+    },
+    /// Scan the target with the efficient walker, like `eff-rand-walk-target`
+    EffTarget{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        settings: MeasureMfptLOpt
+    },
+    /// Scan beta with the efficient walker, like `eff-rand-walk-beta`
+    EffBeta{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        settings: MeasureMfptBetaOpt
+    },
+    /// Scan beta with the even more efficient walker, like `eff2-rand-walk-beta`
+    Eff2Beta{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        settings: MeasureMfptBetaOpt
+    },
+    /// Measure the mfpt of any walker, like `measure-walker`
+    MeasureWalker{
+        #[schemars(with = "String")]
+        out: Utf8PathBuf,
+        settings: MeasureWalkerOpt
+    },
+    /// Evaluate equation 23, like `eq23`
+    Eq23{
+        settings: Eq23Opt
+    }
+}
+
+impl Default for RunJob{
+    fn default() -> Self {
+        Self::Eff2Beta {
+            out: Utf8PathBuf::from("beta.dat"),
+            settings: MeasureMfptBetaOpt::default()
+        }
+    }
+}
+
+impl Validate for RunJob{
+    fn validate(&self) -> Result<()> {
+        match self{
+            Self::ScanResetting { scan, walker, .. } => {
+                scan.validate().map_err(|e| e.within("scan"))?;
+                walker.validate_lambda_scan(scan).map_err(|e| e.within("walker"))
+            },
+            Self::ScanMirrorResetting { scan, mirror_prob, walker, .. } => {
+                scan.validate().map_err(|e| e.within("scan"))?;
+                ensure_probability("mirror_prob", *mirror_prob)?;
+                walker.validate_lambda_scan(scan).map_err(|e| e.within("walker"))
+            },
+            Self::ScanUni { scan, walker, .. } => {
+                scan.validate().map_err(|e| e.within("scan"))?;
+                walker.validate_lambda_scan(scan).map_err(|e| e.within("walker"))
+            },
+            Self::ScanLUni { scan, mode, walker, .. } => {
+                scan.validate().map_err(|e| e.within("scan"))?;
+                if let UniMode::AdaptiveOnlyMirror = mode{
+                    return Err(Error::validation("mode", "AdaptiveOnlyMirror is not supported by the scan of L"));
+                }
+                walker.validate().map_err(|e| e.within("walker"))
+            },
+            Self::Eff2Beta { settings, .. } => {
+                settings.validate().map_err(|e| e.within("settings"))?;
+                ensure_threshold(&settings.bisection)
+                    .map_err(|e| e.within("settings"))
+            },
+            Self::MirrorHists { settings, .. } => settings.validate().map_err(|e| e.within("settings")),
+            Self::EffLambda { settings, .. } => settings.validate().map_err(|e| e.within("settings")),
+            Self::EffTarget { settings, .. } => settings.validate().map_err(|e| e.within("settings")),
+            Self::EffBeta { settings, .. } => settings.validate().map_err(|e| e.within("settings")),
+            Self::MeasureWalker { settings, .. } => settings.validate().map_err(|e| e.within("settings")),
+            Self::Eq23 { settings } => settings.validate().map_err(|e| e.within("settings"))
+        }
+    }
+}
+
+/// A file written by a job
+#[derive(Debug, Clone)]
+pub struct JobOutput{
+    pub path: Utf8PathBuf,
+    /// Number of data lines, i.e., lines that are not comments, of the finished file
+    pub rows: usize
+}
+
+impl JobOutput{
+    fn new<P: Into<Utf8PathBuf>>(path: P, rows: usize) -> Self
+    {
+        Self { path: path.into(), rows }
+    }
+
+    /// The file exists and contains all data lines
+    pub fn is_complete(&self) -> Result<bool>
+    {
+        let rows = count_rows(&self.path)?;
+        Ok(rows.is_some_and(|rows| rows >= self.rows))
+    }
+}
+
+impl RunJob{
+    /// All files written by the job
+    pub fn outputs(&self) -> Vec<JobOutput>
+    {
+        match self{
+            Self::ScanResetting { out, scan, .. }
+            | Self::ScanMirrorResetting { out, scan, .. }
+            | Self::ScanUni { out, scan, .. } => vec![JobOutput::new(out.clone(), scan.lambda_samples)],
+            Self::ScanLUni { out, scan, .. } => vec![JobOutput::new(out.clone(), scan.l_samples)],
+            Self::MirrorHists { out_prefix, format, settings } => {
+                settings.get_times()
+                    .0
+                    .into_iter()
+                    .map(|time| JobOutput::new(format!("{out_prefix}_{time}.{}", format.extension()), settings.samples.get()))
+                    .collect()
+            },
+            Self::EffLambda { out, settings } => vec![JobOutput::new(out.clone(), settings.lambda_samples.get())],
+            Self::EffTarget { out, settings } => vec![JobOutput::new(out.clone(), settings.target_samples.get())],
+            Self::EffBeta { out, settings }
+            | Self::Eff2Beta { out, settings } => vec![JobOutput::new(out.clone(), settings.beta_samples.get())],
+            Self::MeasureWalker { out, .. } => vec![JobOutput::new(out.clone(), 1)],
+            Self::Eq23 { settings } => {
+                vec![JobOutput::new(settings.filename(), settings.samples().max(0) as usize)]
+            }
+        }
+    }
+
+    /// Execute the job. Only the jobs that use the scan engine,
+    /// i.e., the eff jobs and measure walker, can be split into parts.
+    /// A part writes to [ScanPart::output_path] of `out`
+    pub fn run(self, part: ScanPart) -> Result<()>
+    {
+        let partial = part.shard.is_some() || part.point.is_some();
+        if partial && !self.supports_parts(){
+            return Err(
+                Error::validation(
+                    "type",
+                    "`--shard` and `--point` are only supported by EffLambda, EffTarget, EffBeta, Eff2Beta and MeasureWalker"
+                )
+            );
+        }
+        let part_out = |out: Utf8PathBuf| part.output_path(&out);
+        match self{
+            Self::ScanResetting { out, scan, walker } => scan_lambda(&walker, &scan, out),
+            Self::ScanMirrorResetting { out, scan, mirror_prob, walker } => {
+                scan_lambda_mirror(&walker, &scan, mirror_prob, out)
+            },
+            Self::ScanUni { out, scan, mode, walker } => scan_lambda_uni(&walker, &scan, mode, out),
+            Self::ScanLUni { out, scan, mode, walker } => scan_l_uni(&walker, &scan, mode, out),
+            Self::MirrorHists { out_prefix, format, settings } => mirroring_hists(&settings, &out_prefix, format),
+            Self::EffLambda { out, settings } => eff_measure_mfpt_lambda(settings, part_out(out), part),
+            Self::EffTarget { out, settings } => eff_measure_mfpt_target(settings, part_out(out), part),
+            Self::EffBeta { out, settings } => eff_measure_mfpt_beta(settings, part_out(out), part),
+            Self::Eff2Beta { out, settings } => {
+                even_more_efficient_mirror::eff_measure_mfpt_beta(settings, part_out(out), part)
+            },
+            Self::MeasureWalker { out, settings } => measure_walker(settings, part_out(out), part),
+            Self::Eq23 { settings } => exec_eq_23(settings)
+        }
+    }
+
+    fn supports_parts(&self) -> bool
+    {
+        matches!(
+            self,
+            Self::EffLambda { .. } | Self::EffTarget { .. } | Self::EffBeta { .. } 
+            | Self::Eff2Beta { .. } | Self::MeasureWalker { .. }
+        )
+    }
+}
@@ -0,0 +1,49 @@
+//! Simulations of random walkers with stochastic resetting and mirroring.
+//!
+//! The crate contains
+//! * the [walker]s, which all implement the common [walker::Walker] trait,
+//! * the [scan] engine, which measures the mean first passage time of any walker,
+//!   the statistics of the mirror and reset [events] before the first passage,
+//!   its [shard]s, which split a scan across independent processes,
+//!   and the [pool]ing of independent runs,
+//! * the [replay] of single samples of a scan,
+//! * the [cross_validation] of the walker implementations against each other
+//!   and the [benchmark] and automatic [tune]-ing of the efficient walkers,
+//! * the [extrapolation] of the mfpt to step size zero,
+//! * [plot]s of our output files as SVG, which need no external programs,
+//! * the [analytical] results and the [compare]ison of the simulations with them,
+//! * some helpers to write our output files ([misc]), in several formats ([table]), and to read them again ([dat]),
+//! * the [job]s, which describe a whole experiment in a single json file,
+//!   the [sweep] generator, which creates many of them, and the [batch] runner, which executes them,
+//! * the JSON Schemas of all job files ([schema])
+//! * and a C interface ([ffi]), such that the simulations can be used from other languages.
+//!
+//! The command line interface is a thin binary on top of this library.
+
+pub mod error;
+pub mod walker;
+pub mod parse;
+pub mod misc;
+pub mod dat;
+pub mod table;
+pub mod sync_queue;
+pub mod analytical;
+pub mod scan;
+pub mod events;
+pub mod shard;
+pub mod pool;
+pub mod ffi;
+pub mod schema;
+pub mod job;
+pub mod replay;
+pub mod plot;
+pub mod compare;
+pub mod cross_validation;
+pub mod benchmark;
+pub mod extrapolation;
+pub mod tune;
+pub mod sweep;
+pub mod batch;
+
+pub mod config;
+pub use config::*;
@@ -1,72 +1,93 @@
+use std::process::ExitCode;
+use camino::Utf8PathBuf;
 use clap::Parser;
+use resetting_rw::{
+    *,
+    error::*,
+    parse::parse_and_add_to_global
+};
 
-mod walker;
-mod parse;
-mod misc;
-mod sync_queue;
-mod analytical;
+fn main() -> ExitCode {
 
-mod config;
-pub use config::*;
-use parse::parse_and_add_to_global;
+    let opts = Exec::parse();
+    match run(opts){
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
+fn out_path(out: Option<Utf8PathBuf>) -> Result<Utf8PathBuf>
+{
+    out.ok_or_else(|| Error::Input("an output file is required, use --out".to_owned()))
+}
 
-fn main() {
-    
-    let opts = Exec::parse();
+fn run(opts: Exec) -> Result<()>
+{
     match opts{
         Exec::ScanResetting(opts) => walker::execute(opts),
         Exec::ScanMirrorResetting(opts) => walker::execute_mirror(opts),
         Exec::ScanUniMirrorReset(opts) => walker::execute_uni(opts),
-        Exec::ScanUniMirror(opts) => 
+        Exec::ScanUniMirror(opts) =>
         {
             walker::execute_uni_only_mirror(opts)
         },
-        Exec::ScanUniMirrorAdaptive(opts) => 
+        Exec::ScanUniMirrorAdaptive(opts) =>
         {
             walker::execute_uni_only_mirror_adaptive(opts)
         },
         Exec::MirrorHists(opt) => {
-            walker::exec_mirroring_hists(opt.json_path.as_ref())
+            walker::exec_mirroring_hists(opt.json_path.as_ref(), &opt.set, opt.format)
         },
         Exec::ScanLUniMirror(opt) => {
             walker::execute_pos_scan_uni_only_mirror(opt)
         },
         Exec::ScanLUniMirrorReset(opt) => walker::execute_pos_scan_uni(opt),
-        Exec::TestEffRandWalk => walker::test_eff_rand_walker(),
+        Exec::TestEffRandWalk => {
+            walker::test_eff_rand_walker();
+            Ok(())
+        },
         Exec::EffRandWalkLambda(opt) => {
-            let opts: walker::MeasureMfptOpt = parse_and_add_to_global(opt.json);
+            let opts: walker::MeasureMfptOpt = parse_and_add_to_global(opt.json, &opt.set)?;
             walker::eff_measure_mfpt_lambda(
-                opts, 
-                opt.out.unwrap()
-            );
+                opts,
+                out_path(opt.out)?,
+                opt.part
+            )
         },
         Exec::EffRandWalkTarget(opt) => {
-            let opts: walker::MeasureMfptLOpt = parse_and_add_to_global(opt.json);
+            let opts: walker::MeasureMfptLOpt = parse_and_add_to_global(opt.json, &opt.set)?;
             walker::eff_measure_mfpt_target(
-                opts, 
-                opt.out.unwrap()
-            );
+                opts,
+                out_path(opt.out)?,
+                opt.part
+            )
         },
         Exec::EffRandWalkBeta(opt) => {
-            let mut opts: walker::MeasureMfptBetaOpt = parse_and_add_to_global(opt.json);
-            if let Some(a) = opt.a{
-                opts.settimgs.a = a;
-            }
+            let opts: walker::MeasureMfptBetaOpt = parse_and_add_to_global(opt.json.as_ref(), &opt.overrides())?;
             walker::eff_measure_mfpt_beta(
-                opts, 
-                opt.out.unwrap()
-            );
+                opts,
+                out_path(opt.out)?,
+                opt.part
+            )
         },
         Exec::Eff2RandWalkBeta(opt) => {
-            let mut opts: walker::MeasureMfptBetaOpt = parse_and_add_to_global(opt.json);
-            if let Some(a) = opt.a{
-                opts.settimgs.a = a;
-            }
+            let opts: walker::MeasureMfptBetaOpt = parse_and_add_to_global(opt.json.as_ref(), &opt.overrides())?;
             walker::even_more_efficient_mirror::eff_measure_mfpt_beta(
-                opts, 
-                opt.out.unwrap()
-            );
+                opts,
+                out_path(opt.out)?,
+                opt.part
+            )
+        },
+        Exec::MeasureWalker(opt) => {
+            let opts: scan::MeasureWalkerOpt = parse_and_add_to_global(opt.json, &opt.set)?;
+            scan::measure_walker(
+                opts,
+                out_path(opt.out)?,
+                opt.part
+            )
         },
         Exec::EffBetaCreateJob(opt) => {
             walker::job_creator(opt)
@@ -76,7 +97,60 @@ fn main() {
         },
         Exec::Fig4(opt) => {
             analytical::fig4(opt)
+        },
+        Exec::Schema(opt) => {
+            schema::print_schemas(opt)
+        },
+        Exec::Run(opt) => {
+            let job: job::RunJob = parse_and_add_to_global(opt.job, &opt.set)?;
+            job.run(opt.part)
+        },
+        Exec::Sweep(opt) => {
+            sweep::sweep(opt)
+        },
+        Exec::Batch(opt) => {
+            batch::batch(opt)
+        },
+        Exec::Merge(opt) => {
+            shard::merge(opt)
+        },
+        Exec::MergeRuns(opt) => {
+            pool::merge_runs(opt)
+        },
+        Exec::Replay(opt) => {
+            replay::replay(opt)
+        },
+        Exec::Events(opt) => {
+            let opts: scan::MeasureWalkerOpt = parse_and_add_to_global(opt.json, &opt.set)?;
+            events::measure_events(
+                opts,
+                out_path(opt.out)?,
+                opt.count,
+                opt.log.as_deref()
+            )
+        },
+        Exec::Plot(opt) => {
+            plot::plot(opt)
+        },
+        Exec::Compare(opt) => {
+            compare::compare(opt)
+        },
+        Exec::CrossValidate(opt) => {
+            let job: cross_validation::CrossValidationJob = parse_and_add_to_global(opt.json, &opt.set)?;
+            cross_validation::cross_validate(job, out_path(opt.out)?, opt.threshold)
+        },
+        Exec::Benchmark(opt) => {
+            let job: benchmark::BenchmarkJob = parse_and_add_to_global(opt.json, &opt.set)?;
+            benchmark::benchmark(job, out_path(opt.out)?)
+        },
+        Exec::Extrapolate(opt) => {
+            let job: extrapolation::ExtrapolationJob = parse_and_add_to_global(opt.json, &opt.set)?;
+            extrapolation::extrapolate(job, out_path(opt.out)?)
+        },
+        Exec::Tune(opt) => {
+            let job: tune::TuneJob = parse_and_add_to_global(opt.json, &opt.set)?;
+            tune::tune(job, out_path(opt.out)?)
         }
     }
-    
-}
\ No newline at end of file
+
+}
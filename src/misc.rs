@@ -1,3 +1,4 @@
+use std::cell::RefCell;
 use std::io::{BufWriter, Write};
 use std::sync::RwLock;
 use fs_err::File;
@@ -8,6 +9,7 @@ use std::process::{Command, Output};
 use serde_json::Value;
 use num_rational::Rational64;
 use num_traits::cast::ToPrimitive;
+use crate::error::*;
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const GIT_HASH: &str = env!("GIT_HASH");
@@ -15,6 +17,25 @@ pub const BUILD_TIME_CHRONO: &str = env!("BUILD_TIME_CHRONO");
 
 pub static GLOBAL_ADDITIONS: RwLock<Option<String>> = RwLock::new(None);
 
+thread_local! {
+    /// Takes precedence over [GLOBAL_ADDITIONS] on the current thread.
+    /// Used by the batch runner, which executes several jobs in one process
+    static JOB_ADDITIONS: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Execute `f` with `additions` instead of the [GLOBAL_ADDITIONS] on the current thread.
+///
+/// Note: Files have to be created before any parallel work is started,
+/// otherwise rayon might execute other jobs on this thread in the mean time
+pub fn with_job_additions<F, R>(additions: String, f: F) -> R
+where F: FnOnce() -> R
+{
+    let previous = JOB_ADDITIONS.with(|a| a.replace(Some(additions)));
+    let result = f();
+    JOB_ADDITIONS.with(|a| a.replace(previous));
+    result
+}
+
 pub fn write_json<W: Write>(mut writer: W, json: &Value)
 {
     write!(writer, "#").unwrap();
@@ -41,18 +62,25 @@ pub fn write_commands<W: Write>(mut w: W) -> std::io::Result<()>
     writeln!(w)
 }
 
+/// Write the version, git hash, the global additions (typically the json config) and
+/// the command line as comments
 pub fn write_commands_and_version<W: Write>(mut w: W) -> std::io::Result<()>
 {
     writeln!(w, "# {VERSION}")?;
     writeln!(w, "# Git Hash: {GIT_HASH} Compile-time: {BUILD_TIME_CHRONO}")?;
-    let l = GLOBAL_ADDITIONS.read().unwrap();
-    if let Some(add) = l.as_deref(){
+    if let Some(add) = current_additions(){
         writeln!(w, "# {add}")?;
     }
-    drop(l);
     write_commands(w)
 }
 
+/// The additions of the current job, see [with_job_additions], or the [GLOBAL_ADDITIONS]
+pub fn current_additions() -> Option<String>
+{
+    JOB_ADDITIONS.with(|a| a.borrow().clone())
+        .or_else(|| GLOBAL_ADDITIONS.read().unwrap().clone())
+}
+
 #[must_use]
 pub fn create_buf_with_command_and_version<P>(path: P) -> BufWriter<File>
 where P: AsRef<Path>
@@ -87,6 +115,8 @@ where W: std::io::Write,
 }
 
 
+/// Equidistant points between start and end (both inclusive),
+/// calculated with rationals to avoid accumulating rounding errors
 pub struct RatioIter{
     start: Rational64,
     end: Rational64,
@@ -114,15 +144,22 @@ impl RatioIter{
             )
     }
 
-    pub fn get_ratio_iter(start: f64, end: f64, num_samples: i64) -> Self{
-        let start = Rational64::approximate_float(start).unwrap();
-        let end = Rational64::approximate_float(end).unwrap();
+    pub fn get_ratio_iter(start: f64, end: f64, num_samples: i64) -> Result<Self>{
+        let approximate = |name: &str, val: f64| {
+            Rational64::approximate_float(val)
+                .ok_or_else(|| Error::validation(name, format!("{val} cannot be represented as a ratio")))
+        };
+        let start = approximate("start", start)?;
+        let end = approximate("end", end)?;
+        ensure_range_samples("samples", num_samples.max(0) as usize)?;
         let num_samples_m1 = NonZeroI64::new(num_samples - 1).unwrap();
-        RatioIter{
-            start,
-            end,
-            num_samples_m1
-        }
+        Ok(
+            RatioIter{
+                start,
+                end,
+                num_samples_m1
+            }
+        )
     }
 }
 
@@ -1,101 +1,207 @@
 use std::{
     path::*,
-    fs::File,
     io::BufReader,
-    process::exit
+    process::exit,
+    str::FromStr
 };
 use serde::{Serialize, de::DeserializeOwned};
 use serde_json::Value;
+use schemars::JsonSchema;
 use super::misc::GLOBAL_ADDITIONS;
+use crate::{error::*, schema};
 
-pub fn parse<P, T>(file: Option<P>) -> (T, Value)
-where P: AsRef<Path>,
-    T: Default + Serialize + DeserializeOwned
-{
-    match file
+/// Change of a single field of a json file,
+/// given as `path.to.field=value` on the command line.
+///
+/// Array elements are selected by their index, e.g., `hist_positions.0=1.5`.
+/// The value is parsed as json. If that fails, it is used as a string
+#[derive(Debug, Clone)]
+pub struct FieldOverride{
+    pub path: Vec<String>,
+    pub value: Value
+}
+
+impl FromStr for FieldOverride{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (path, value) = s.split_once('=')
+            .ok_or_else(|| format!("expected PATH=VALUE, but got '{s}'"))?;
+        if path.is_empty() || path.split('.').any(str::is_empty){
+            return Err(format!("invalid path '{path}'"));
+        }
+        let value = serde_json::from_str(value)
+            .unwrap_or_else(|_| Value::String(value.to_owned()));
+        Ok(Self::new(path, value))
+    }
+}
+
+impl FieldOverride{
+    pub fn new<V: Into<Value>>(path: &str, value: V) -> Self
     {
-        None => {
-            let example = T::default();
-            serde_json::to_writer_pretty(
-                std::io::stdout(),
-                &example
-            ).expect("Unable to reach stdout");
-            exit(0)
-        }, 
-        Some(file) => {
-            let f = File::open(file)
-                .expect("Unable to open file");
-            let buf = BufReader::new(f);
-
-            let json_val: Value = match serde_json::from_reader(buf)
-            {
-                Ok(v) => v,
-                Err(e) => {
-                    eprintln!("json parsing error!");
-                    dbg!(e);
-                    exit(1);
-                }
-            };
+        Self{
+            path: path.split('.').map(str::to_owned).collect(),
+            value: value.into()
+        }
+    }
 
-            let opt: T = match serde_json::from_value(json_val.clone())
-            {
-                Ok(o) => o,
-                Err(e) => {
-                    eprintln!("json parsing error!");
-                    dbg!(e);
-                    exit(1);
-                }
+    /// Set the field in the json. Missing keys are added to the innermost object,
+    /// unknown keys are rejected later on, when the json is deserialized.
+    /// A key also selects its alias, e.g., `settimgs.a` sets `settings.a`, if the json uses the alias
+    pub fn apply(&self, json: &mut Value) -> Result<()>
+    {
+        let full_path = self.path.join(".");
+        let err = |msg: String| Error::Input(format!("unable to set `{full_path}`: {msg}"));
+        let (last, parents) = self.path.split_last()
+            .expect("path is never empty");
+
+        let mut current = json;
+        for (depth, key) in parents.iter().enumerate(){
+            let parent = if depth == 0 {
+                "the top level".to_owned()
+            } else {
+                format!("`{}`", self.path[..depth].join("."))
+            };
+            current = match current{
+                Value::Object(map) => {
+                    let key = resolve_alias(map, key);
+                    map.get_mut(key)
+                        .ok_or_else(|| err(format!("`{key}` does not exist in {parent}")))?
+                },
+                Value::Array(arr) => key.parse::<usize>()
+                    .ok()
+                    .and_then(|idx| arr.get_mut(idx))
+                    .ok_or_else(|| err(format!("`{key}` is not a valid index of {parent}")))?,
+                _ => return Err(err(format!("{parent} is neither an object nor an array")))
             };
+        }
 
-            (opt, json_val)    
+        match current{
+            Value::Object(map) => {
+                let last = resolve_alias(map, last).to_owned();
+                map.insert(last, self.value.clone());
+            },
+            Value::Array(arr) => {
+                let entry = last.parse::<usize>()
+                    .ok()
+                    .and_then(|idx| arr.get_mut(idx))
+                    .ok_or_else(|| err(format!("`{last}` is not a valid index")))?;
+                *entry = self.value.clone();
+            },
+            _ => return Err(err("the parent is neither an object nor an array".to_owned()))
         }
+        Ok(())
     }
 }
 
-pub fn parse_and_add_to_global<P, T>(file: Option<P>) -> T
+/// The key of `map` that stands for `key`. That is `key` itself, unless only
+/// its alias or the field it is an alias of is present, see [schema::ALIASES]
+fn resolve_alias<'a>(map: &serde_json::Map<String, Value>, key: &'a str) -> &'a str
+{
+    if map.contains_key(key){
+        return key;
+    }
+    schema::ALIASES.iter()
+        .find_map(
+            |&(alias, field)| {
+                if field == key && map.contains_key(alias){
+                    Some(alias)
+                } else if alias == key && map.contains_key(field){
+                    Some(field)
+                } else {
+                    None
+                }
+            }
+        ).unwrap_or(key)
+}
+
+/// Apply all overrides in order, i.e., later overrides win
+pub fn apply_overrides(json: &mut Value, overrides: &[FieldOverride]) -> Result<()>
+{
+    overrides.iter()
+        .try_for_each(|o| o.apply(json))
+}
+
+fn print_example<T>(overrides: &[FieldOverride]) -> Result<()>
+where T: Default + Serialize
+{
+    let mut example = serde_json::to_value(T::default())?;
+    apply_overrides(&mut example, overrides)?;
+    serde_json::to_writer_pretty(
+        std::io::stdout(),
+        &example
+    )?;
+    Ok(())
+}
+
+/// Deserialize and validate the json. If it does not fit, the differences
+/// to the schema of `T` are part of the error.
+/// `file` is only used for the error messages
+pub fn from_value<T>(json: &Value, file: Option<&Path>) -> Result<T>
+where T: DeserializeOwned + Validate + JsonSchema
+{
+    let opt = T::deserialize(json)
+        .map_err(
+            |source| {
+                let path = file.map(Path::to_owned);
+                let differences = schema::diff::<T>(json);
+                if differences.is_empty(){
+                    Error::Json { path, source }
+                } else {
+                    Error::Schema { path, source, differences }
+                }
+            }
+        )?;
+    opt.validate()?;
+    Ok(opt)
+}
+
+fn read_json<T>(file: &Path, overrides: &[FieldOverride]) -> Result<(T, Value)>
+where T: DeserializeOwned + Validate + JsonSchema
+{
+    let f = fs_err::File::open(file)?;
+    let buf = BufReader::new(f);
+
+    let json_err = |source| Error::Json { path: Some(file.to_owned()), source };
+
+    let mut json_val: Value = serde_json::from_reader(buf)
+        .map_err(json_err)?;
+    apply_overrides(&mut json_val, overrides)?;
+
+    let opt = from_value(&json_val, Some(file))?;
+
+    Ok((opt, json_val))
+}
+
+/// Parse and validate the json file after applying the overrides. Unknown keys are rejected.
+/// If no file is given, the default is printed and the program exits.
+///
+/// The returned json contains the overrides
+pub fn parse<P, T>(file: Option<P>, overrides: &[FieldOverride]) -> Result<(T, Value)>
 where P: AsRef<Path>,
-    T: Default + Serialize + DeserializeOwned
+    T: Default + Serialize + DeserializeOwned + Validate + JsonSchema
 {
     match file
     {
         None => {
-            let example = T::default();
-            serde_json::to_writer_pretty(
-                std::io::stdout(),
-                &example
-            ).expect("Unable to reach stdout");
+            print_example::<T>(overrides)?;
             exit(0)
-        }, 
-        Some(file) => {
-            let f = File::open(file)
-                .expect("Unable to open file");
-            let buf = BufReader::new(f);
-
-            let json_val: Value = match serde_json::from_reader(buf)
-            {
-                Ok(v) => v,
-                Err(e) => {
-                    eprintln!("json parsing error!");
-                    dbg!(e);
-                    exit(1);
-                }
-            };
+        },
+        Some(file) => read_json(file.as_ref(), overrides)
+    }
+}
 
-            let opt: T = match serde_json::from_value(json_val.clone())
-            {
-                Ok(o) => o,
-                Err(e) => {
-                    eprintln!("json parsing error!");
-                    dbg!(e);
-                    exit(1);
-                }
-            };
-            let s = serde_json::to_string(&opt).unwrap();
-            let mut w = GLOBAL_ADDITIONS.write().unwrap();
-            *w = Some(s);
-            drop(w);
+/// Like [parse], but the parsed options are also stored in [GLOBAL_ADDITIONS],
+/// such that they are written into the header of the output files
+pub fn parse_and_add_to_global<P, T>(file: Option<P>, overrides: &[FieldOverride]) -> Result<T>
+where P: AsRef<Path>,
+    T: Default + Serialize + DeserializeOwned + Validate + JsonSchema
+{
+    let (opt, _) = parse(file, overrides)?;
+    let s = serde_json::to_string(&opt)?;
+    let mut w = GLOBAL_ADDITIONS.write().unwrap();
+    *w = Some(s);
+    drop(w);
 
-            opt  
-        }
-    }
-}
\ No newline at end of file
+    Ok(opt)
+}
@@ -0,0 +1,570 @@
+//! Render our output files as SVG, without external programs like gnuplot.
+//!
+//! Supported are the results of scans, with error bars and optionally the analytic mfpt,
+//! the samples written by `mirror-hists` as histogram and the trajectories written by `replay`.
+//! Only the `.dat` format can be read, see [crate::dat].
+use std::fmt::Write;
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{Parser, Subcommand};
+
+use crate::{
+    compare::{analytic_mfpt, walker_parameters, ANALYTIC_CUTOFF},
+    dat::DatFile,
+    error::*
+};
+
+const WIDTH: f64 = 800.0;
+const HEIGHT: f64 = 560.0;
+const LEFT: f64 = 90.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 20.0;
+const BOTTOM: f64 = 60.0;
+const COLORS: [&str; 8] = ["#1f77b4", "#d62728", "#2ca02c", "#ff7f0e", "#9467bd", "#8c564b", "#e377c2", "#7f7f7f"];
+/// Number of points of the analytic curve
+const ANALYTIC_POINTS: usize = 200;
+
+#[derive(Parser, Debug)]
+pub struct PlotOpt{
+    #[command(subcommand)]
+    pub command: PlotSub
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PlotSub{
+    /// Plot the results of scans, e.g., of eff-rand-walk-beta, one curve per file
+    Scan(ScanPlotOpt),
+    /// Plot a histogram of one column, e.g., of the positions written by mirror-hists
+    Hist(HistPlotOpt),
+    /// Plot a trajectory written by replay
+    Trajectory(TrajectoryPlotOpt)
+}
+
+#[derive(Parser, Debug)]
+pub struct ScanPlotOpt{
+    #[arg(required = true)]
+    /// Output files of the scans
+    pub files: Vec<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of the svg file
+    pub out: Utf8PathBuf,
+
+    #[arg(long)]
+    /// Column of the x values, defaults to the first column
+    pub x: Option<String>,
+
+    #[arg(long)]
+    /// Column of the y values, defaults to `mfpt` or the second column
+    pub y: Option<String>,
+
+    #[arg(long)]
+    /// Column of the error bars. Defaults to the standard error sqrt(variance / samples),
+    /// if the file contains these columns and y is the mfpt
+    pub err: Option<String>,
+
+    #[arg(long)]
+    /// Add the analytic mfpt of a scan of β, with a and the target taken from the configuration in the file
+    pub analytic: bool,
+
+    #[arg(long, default_value_t = 1.0)]
+    /// Dimensionless mfpt at the boundary, only used by the analytic mfpt for negative a
+    pub boundary: f64,
+
+    #[arg(long)]
+    /// Logarithmic x axis
+    pub log_x: bool,
+
+    #[arg(long)]
+    /// Logarithmic y axis
+    pub log_y: bool
+}
+
+#[derive(Parser, Debug)]
+pub struct HistPlotOpt{
+    /// File containing the samples
+    pub file: Utf8PathBuf,
+
+    #[arg(long, short)]
+    /// Name of the svg file
+    pub out: Utf8PathBuf,
+
+    #[arg(long)]
+    /// Column of the samples, defaults to the first column
+    pub column: Option<String>,
+
+    #[arg(long, default_value_t = 50)]
+    /// Number of bins
+    pub bins: usize,
+
+    #[arg(long)]
+    /// Logarithmic y axis
+    pub log_y: bool
+}
+
+#[derive(Parser, Debug)]
+pub struct TrajectoryPlotOpt{
+    /// File written by replay
+    pub file: Utf8PathBuf,
+
+    #[arg(long, short)]
+    /// Name of the svg file
+    pub out: Utf8PathBuf
+}
+
+pub fn plot(opt: PlotOpt) -> Result<()>
+{
+    match opt.command{
+        PlotSub::Scan(opt) => plot_scan(&opt),
+        PlotSub::Hist(opt) => plot_hist(&opt),
+        PlotSub::Trajectory(opt) => plot_trajectory(&opt)
+    }
+}
+
+/// Name of the column `name`, or of the column with index `default`
+fn column_name(file: &DatFile, name: Option<&str>, default: usize) -> Result<String>
+{
+    match name{
+        Some(name) => Ok(name.to_owned()),
+        None => file.header
+            .columns
+            .get(default)
+            .cloned()
+            .ok_or_else(|| Error::Input(format!("{} has less than {} columns", file.path.display(), default + 1)))
+    }
+}
+
+fn plot_scan(opt: &ScanPlotOpt) -> Result<()>
+{
+    let files = opt.files
+        .iter()
+        .map(DatFile::read_dat_only)
+        .collect::<Result<Vec<_>>>()?;
+
+    let first = &files[0];
+    let x_name = column_name(first, opt.x.as_deref(), 0)?;
+    let y_name = match &opt.y{
+        Some(y) => y.clone(),
+        None if first.header.column("mfpt").is_some() => "mfpt".to_owned(),
+        None => column_name(first, None, 1)?
+    };
+
+    let mut series = Vec::new();
+    for file in files.iter(){
+        let x = file.column(&x_name)?;
+        let y = file.column(&y_name)?;
+        let err = match &opt.err{
+            Some(err) => Some(file.column(err)?),
+            None if y_name == "mfpt" => file.standard_error()?,
+            None => None
+        };
+        let points: Vec<_> = match err{
+            Some(err) => x.into_iter()
+                .zip(y)
+                .zip(err)
+                .map(|((x, y), err)| (x, y, err))
+                .collect(),
+            None => x.into_iter()
+                .zip(y)
+                .map(|(x, y)| (x, y, 0.0))
+                .collect()
+        };
+        let label = file.path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        series.push((label, points));
+    }
+
+    let mut analytic = Vec::new();
+    if opt.analytic{
+        if x_name != "β"{
+            return Err(Error::Input(format!("the analytic mfpt is only known for scans of β, not of {x_name}")));
+        }
+        for (file, (_, points)) in files.iter().zip(series.iter()){
+            let (a, target) = walker_parameters(file)?;
+            let (min, max) = points.iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| (min.min(p.0), max.max(p.0)));
+            let delta = (max - min) / (ANALYTIC_POINTS - 1) as f64;
+            let curve: Vec<_> = (0..ANALYTIC_POINTS)
+                .map(|i| delta.mul_add(i as f64, min))
+                .map(|beta| (beta, analytic_mfpt(beta, a, target, opt.boundary, ANALYTIC_CUTOFF)))
+                .collect();
+            analytic.push((format!("analytic a={a}"), curve));
+        }
+    }
+
+    let xs = series.iter()
+        .flat_map(|(_, points)| points.iter().map(|p| p.0));
+    let ys = series.iter()
+        .flat_map(|(_, points)| points.iter().flat_map(|p| [p.1 - p.2, p.1 + p.2]))
+        .chain(analytic.iter().flat_map(|(_, curve)| curve.iter().map(|p| p.1)));
+    let mut figure = Figure::new(
+        Axis::new(xs, opt.log_x)?,
+        Axis::new(ys, opt.log_y)?,
+        &x_name,
+        &y_name
+    );
+    for (i, (label, points)) in series.iter().enumerate(){
+        let color = COLORS[i % COLORS.len()];
+        figure.error_bars(points, color);
+        let line: Vec<_> = points.iter().map(|p| (p.0, p.1)).collect();
+        figure.line(&line, color, false, None);
+        figure.markers(&line, color, Some(label));
+    }
+    for (i, (label, curve)) in analytic.iter().enumerate(){
+        let color = COLORS[(series.len() + i) % COLORS.len()];
+        figure.line(curve, color, true, Some(label));
+    }
+    figure.write(&opt.out)
+}
+
+fn plot_hist(opt: &HistPlotOpt) -> Result<()>
+{
+    if opt.bins == 0{
+        return Err(Error::validation("bins", "has to be at least 1"));
+    }
+    let file = DatFile::read_dat_only(&opt.file)?;
+    let name = column_name(&file, opt.column.as_deref(), 0)?;
+    let samples: Vec<f64> = file.column(&name)?
+        .into_iter()
+        .filter(|val| val.is_finite())
+        .collect();
+    if samples.is_empty(){
+        return Err(Error::Input(format!("{} contains no samples", opt.file)));
+    }
+    let (min, max) = samples.iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), val| (min.min(*val), max.max(*val)));
+    let width = if max > min { (max - min) / opt.bins as f64 } else { 1.0 };
+    let mut counts = vec![0_usize; opt.bins];
+    for val in samples.iter(){
+        let bin = (((val - min) / width) as usize).min(opt.bins - 1);
+        counts[bin] += 1;
+    }
+    // normalized, such that the histogram is a probability density
+    let norm = (samples.len() as f64 * width).recip();
+    let bars: Vec<_> = counts.iter()
+        .enumerate()
+        .map(|(i, count)| {
+            let left = width.mul_add(i as f64, min);
+            (left, left + width, *count as f64 * norm)
+        }).collect();
+
+    let xs = [min, min + width * opt.bins as f64];
+    let ys = bars.iter()
+        .map(|bar| bar.2)
+        .filter(|density| !opt.log_y || *density > 0.0)
+        .chain((!opt.log_y).then_some(0.0));
+    let mut figure = Figure::new(
+        Axis::new(xs, false)?,
+        Axis::new(ys, opt.log_y)?,
+        &name,
+        "probability density"
+    );
+    figure.bars(&bars, COLORS[0]);
+    figure.write(&opt.out)
+}
+
+fn plot_trajectory(opt: &TrajectoryPlotOpt) -> Result<()>
+{
+    let file = DatFile::read_dat_only(&opt.file)?;
+    let time = file.column("time")?;
+    let position = file.column("position")?;
+    let event = file.column("event")?;
+
+    let mut path = Vec::new();
+    let mut mirrors = Vec::new();
+    let mut hits = Vec::new();
+    // the rows are sorted by time, every refinement only adds points to the path
+    for ((t, x), kind) in time.iter().zip(position.iter()).zip(event.iter()){
+        match *kind as u8{
+            1 => {
+                path.push((*t, *x));
+                mirrors.push((*t, *x));
+            },
+            2 => hits.push((*t, *x)),
+            _ => path.push((*t, *x))
+        }
+    }
+    path.extend(hits.iter().copied());
+
+    let xs = path.iter().map(|p| p.0);
+    let ys = path.iter().map(|p| p.1);
+    let mut figure = Figure::new(
+        Axis::new(xs, false)?,
+        Axis::new(ys, false)?,
+        "time",
+        "position"
+    );
+    if let Some(hit) = hits.first(){
+        figure.hline(hit.1, COLORS[7]);
+    }
+    figure.line(&path, COLORS[0], false, Some("walk"));
+    figure.markers(&mirrors, COLORS[1], Some("mirror"));
+    figure.markers(&hits, COLORS[2], Some("first passage"));
+    figure.write(&opt.out)
+}
+
+/// Range of one axis of a [Figure]
+struct Axis{
+    min: f64,
+    max: f64,
+    log: bool
+}
+
+impl Axis{
+    /// Covers all finite `values`, for a logarithmic axis only the positive ones
+    fn new<I>(values: I, log: bool) -> Result<Self>
+    where I: IntoIterator<Item = f64>
+    {
+        let (mut min, mut max) = values.into_iter()
+            .filter(|val| val.is_finite() && (!log || *val > 0.0))
+            .map(|val| if log { val.log10() } else { val })
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), val| (min.min(val), max.max(val)));
+        if min > max{
+            return Err(Error::Input("there is nothing to plot".to_owned()));
+        }
+        if min == max{
+            min -= 1.0;
+            max += 1.0;
+        }
+        let pad = (max - min) * 0.03;
+        Ok(Self { min: min - pad, max: max + pad, log })
+    }
+
+    /// Fraction of the axis at which `val` is drawn
+    fn fraction(&self, val: f64) -> f64
+    {
+        let val = if self.log { val.log10() } else { val };
+        (val - self.min) / (self.max - self.min)
+    }
+
+    fn ticks(&self) -> Vec<(f64, String)>
+    {
+        if self.log{
+            // short axes also get ticks at 2 and 5 times the powers of ten
+            let mantissas: &[f64] = if self.max - self.min < 2.0 { &[1.0, 2.0, 5.0] } else { &[1.0] };
+            return (self.min.floor() as i32..=self.max.ceil() as i32)
+                .flat_map(|exp| mantissas.iter().map(move |m| (*m, exp)))
+                .filter(|(m, exp)| (self.min..=self.max).contains(&(m.log10() + *exp as f64)))
+                .map(|(m, exp)| (m * 10_f64.powi(exp), format!("{m}e{exp}")))
+                .collect();
+        }
+        let rough = (self.max - self.min) / 6.0;
+        let magnitude = 10_f64.powf(rough.log10().floor());
+        let step = [1.0, 2.0, 5.0, 10.0]
+            .into_iter()
+            .map(|factor| factor * magnitude)
+            .find(|step| *step >= rough)
+            .unwrap_or(10.0 * magnitude);
+        let decimals = (-step.log10().floor()).max(0.0) as usize;
+        let first = (self.min / step).ceil() as i64;
+        let last = (self.max / step).floor() as i64;
+        (first..=last)
+            .map(|i| {
+                let val = i as f64 * step;
+                (val, format!("{val:.decimals$}"))
+            }).collect()
+    }
+}
+
+fn escape(text: &str) -> String
+{
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// A single plot, which is written as SVG
+struct Figure{
+    x: Axis,
+    y: Axis,
+    x_label: String,
+    y_label: String,
+    body: String,
+    legend: Vec<(String, &'static str)>
+}
+
+impl Figure{
+    fn new(x: Axis, y: Axis, x_label: &str, y_label: &str) -> Self
+    {
+        Self {
+            x,
+            y,
+            x_label: escape(x_label),
+            y_label: escape(y_label),
+            body: String::new(),
+            legend: Vec::new()
+        }
+    }
+
+    fn px(&self, x: f64) -> f64
+    {
+        LEFT + self.x.fraction(x) * (WIDTH - LEFT - RIGHT)
+    }
+
+    fn py(&self, y: f64) -> f64
+    {
+        HEIGHT - BOTTOM - self.y.fraction(y) * (HEIGHT - TOP - BOTTOM)
+    }
+
+    /// Points that can be drawn on the axes
+    fn visible(&self, x: f64, y: f64) -> bool
+    {
+        x.is_finite() && y.is_finite() && (!self.x.log || x > 0.0) && (!self.y.log || y > 0.0)
+    }
+
+    fn line(&mut self, points: &[(f64, f64)], color: &'static str, dashed: bool, label: Option<&str>)
+    {
+        let mut coords = String::new();
+        for (x, y) in points.iter().filter(|(x, y)| self.visible(*x, *y)){
+            let _ = write!(coords, "{:.2},{:.2} ", self.px(*x), self.py(*y));
+        }
+        let dash = if dashed { r#" stroke-dasharray="6,4""# } else { "" };
+        let _ = writeln!(
+            self.body,
+            r#"<polyline points="{}" fill="none" stroke="{color}" stroke-width="1.5"{dash}/>"#,
+            coords.trim_end()
+        );
+        if let Some(label) = label{
+            self.legend.push((escape(label), color));
+        }
+    }
+
+    fn markers(&mut self, points: &[(f64, f64)], color: &'static str, label: Option<&str>)
+    {
+        let mut circles = String::new();
+        for (x, y) in points.iter().filter(|(x, y)| self.visible(*x, *y)){
+            let _ = writeln!(
+                circles,
+                r#"<circle cx="{:.2}" cy="{:.2}" r="3" fill="{color}"/>"#,
+                self.px(*x),
+                self.py(*y)
+            );
+        }
+        self.body.push_str(&circles);
+        if let Some(label) = label{
+            self.legend.push((escape(label), color));
+        }
+    }
+
+    /// Vertical error bars, the points are (x, y, error)
+    fn error_bars(&mut self, points: &[(f64, f64, f64)], color: &'static str)
+    {
+        for (x, y, err) in points.iter(){
+            if *err <= 0.0 || !err.is_finite() || !self.visible(*x, *y){
+                continue;
+            }
+            let px = self.px(*x);
+            let top = self.py(y + err);
+            // on a logarithmic axis the lower end might not be drawable
+            let bottom = if self.y.log && y - err <= 0.0 { HEIGHT - BOTTOM } else { self.py(y - err) };
+            let _ = writeln!(
+                self.body,
+                r#"<path d="M{px:.2},{top:.2}V{bottom:.2}M{:.2},{top:.2}h6M{:.2},{bottom:.2}h6" stroke="{color}" fill="none"/>"#,
+                px - 3.0,
+                px - 3.0
+            );
+        }
+    }
+
+    /// Bars of a histogram, given as (left, right, height)
+    fn bars(&mut self, bars: &[(f64, f64, f64)], color: &'static str)
+    {
+        for (left, right, height) in bars.iter(){
+            if !self.visible(*left, *height){
+                continue;
+            }
+            let x = self.px(*left);
+            let y = self.py(*height);
+            let base = if self.y.log { HEIGHT - BOTTOM } else { self.py(0.0) };
+            let _ = writeln!(
+                self.body,
+                r#"<rect x="{x:.2}" y="{y:.2}" width="{:.2}" height="{:.2}" fill="{color}" fill-opacity="0.7" stroke="{color}"/>"#,
+                self.px(*right) - x,
+                base - y
+            );
+        }
+    }
+
+    fn hline(&mut self, y: f64, color: &'static str)
+    {
+        let py = self.py(y);
+        let _ = writeln!(
+            self.body,
+            r#"<line x1="{LEFT}" y1="{py:.2}" x2="{}" y2="{py:.2}" stroke="{color}" stroke-dasharray="4,4"/>"#,
+            WIDTH - RIGHT
+        );
+    }
+
+    fn write(self, path: &Utf8Path) -> Result<()>
+    {
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{WIDTH}" height="{HEIGHT}" viewBox="0 0 {WIDTH} {HEIGHT}" font-family="sans-serif" font-size="14">"#
+        );
+        let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+        let plot_width = WIDTH - LEFT - RIGHT;
+        let plot_height = HEIGHT - TOP - BOTTOM;
+        let _ = writeln!(
+            svg,
+            r#"<clipPath id="area"><rect x="{LEFT}" y="{TOP}" width="{plot_width}" height="{plot_height}"/></clipPath>"#
+        );
+
+        for (val, label) in self.x.ticks(){
+            let px = self.px(val);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{px:.2}" y1="{TOP}" x2="{px:.2}" y2="{}" stroke="#e0e0e0"/><text x="{px:.2}" y="{}" text-anchor="middle">{label}</text>"##,
+                HEIGHT - BOTTOM,
+                HEIGHT - BOTTOM + 20.0
+            );
+        }
+        for (val, label) in self.y.ticks(){
+            let py = self.py(val);
+            let _ = writeln!(
+                svg,
+                r##"<line x1="{LEFT}" y1="{py:.2}" x2="{}" y2="{py:.2}" stroke="#e0e0e0"/><text x="{}" y="{:.2}" text-anchor="end">{label}</text>"##,
+                WIDTH - RIGHT,
+                LEFT - 6.0,
+                py + 5.0
+            );
+        }
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{LEFT}" y="{TOP}" width="{plot_width}" height="{plot_height}" fill="none" stroke="black"/>"#
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{}" y="{}" text-anchor="middle">{}</text>"#,
+            LEFT + plot_width / 2.0,
+            HEIGHT - 15.0,
+            self.x_label
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text transform="translate(20,{}) rotate(-90)" text-anchor="middle">{}</text>"#,
+            TOP + plot_height / 2.0,
+            self.y_label
+        );
+
+        let _ = writeln!(svg, r#"<g clip-path="url(#area)">"#);
+        svg.push_str(&self.body);
+        let _ = writeln!(svg, "</g>");
+
+        for (i, (label, color)) in self.legend.iter().enumerate(){
+            let y = TOP + 20.0 + 20.0 * i as f64;
+            let x = WIDTH - RIGHT - 200.0;
+            let _ = writeln!(
+                svg,
+                r#"<rect x="{x}" y="{}" width="14" height="4" fill="{color}"/><text x="{}" y="{y}">{label}</text>"#,
+                y - 6.0,
+                x + 20.0
+            );
+        }
+        svg.push_str("</svg>\n");
+        fs_err::write(path, svg)?;
+        Ok(())
+    }
+}
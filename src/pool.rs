@@ -0,0 +1,183 @@
+//! Pool the results of independent runs of the same configuration.
+//!
+//! Runs that only differ in their seed sample the same distribution, so their
+//! points can be combined, weighted by their number of samples.
+//! The configuration is taken from the json line in the header of the result files.
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use serde_json::Value;
+
+use crate::{
+    error::*,
+    dat::DatFile,
+    misc::with_job_additions,
+    scan::PointStats,
+    shard::{PointFormat, PointWriter, DIAGNOSTICS_HEADER, SHARD_PREFIX}
+};
+
+/// Keys that may differ between the pooled runs.
+/// The output files and the number of threads do not change what is measured
+const IGNORED_KEYS: [&str; 3] = ["out", "out_prefix", "j"];
+
+#[derive(Parser, Debug)]
+pub struct MergeRunsOpt{
+    #[arg(long, short)]
+    /// Name of the pooled output file
+    pub out: Utf8PathBuf,
+
+    #[arg(required = true, num_args = 2..)]
+    /// Result files of runs that only differ in their seed, e.g., from eff-rand-walk-beta
+    pub files: Vec<Utf8PathBuf>
+}
+
+/// Content of one result file
+struct RunFile{
+    /// The json config with the seed and the ignored keys removed
+    config: Value,
+    seeds: Vec<Value>,
+    format: PointFormat,
+    points: Vec<(f64, PointStats)>
+}
+
+/// Remove the seed and the [IGNORED_KEYS] everywhere in the config, the seeds are returned
+fn strip_config(json: &mut Value, seeds: &mut Vec<Value>)
+{
+    match json{
+        Value::Object(map) => {
+            match map.remove("seed"){
+                Some(Value::Array(pooled)) => seeds.extend(pooled),
+                Some(seed) => seeds.push(seed),
+                None => ()
+            }
+            for key in IGNORED_KEYS{
+                map.remove(key);
+            }
+            map.values_mut()
+                .for_each(|val| strip_config(val, seeds));
+        },
+        Value::Array(arr) => arr.iter_mut()
+            .for_each(|val| strip_config(val, seeds)),
+        _ => ()
+    }
+}
+
+/// Replace the first seed in the config by all pooled seeds
+fn insert_seeds(json: &mut Value, seeds: &[Value]) -> bool
+{
+    match json{
+        Value::Object(map) => {
+            if let Some(seed) = map.get_mut("seed"){
+                *seed = Value::Array(seeds.to_vec());
+                return true;
+            }
+            map.values_mut()
+                .any(|val| insert_seeds(val, seeds))
+        },
+        Value::Array(arr) => arr.iter_mut()
+            .any(|val| insert_seeds(val, seeds)),
+        _ => false
+    }
+}
+
+impl RunFile{
+    fn read(path: &Utf8Path) -> Result<(Self, Value)>
+    {
+        let file = DatFile::read(path)?;
+        if file.header.other_with_prefix(SHARD_PREFIX).is_some(){
+            return Err(Error::Input(format!("{path} is the output of a shard, use `merge` to combine the shards first")));
+        }
+        if file.header.columns.ends_with(&DIAGNOSTICS_HEADER.map(str::to_owned)){
+            return Err(Error::Input(format!("{path} contains bisection diagnostics, only runs without them can be pooled")));
+        }
+        let json = file.header
+            .config
+            .ok_or_else(|| Error::Input(format!("{path} contains no json configuration")))?;
+        let format = PointFormat::from_columns(&file.header.columns)
+            .ok_or_else(
+                || Error::Input(
+                    format!("{path} has no variance and sample count, only files written by this version can be pooled")
+                )
+            )?;
+        let points = file.rows
+            .iter()
+            .zip(1..)
+            .map(
+                |(row, nr)| format.parse_row(row)
+                    .ok_or_else(|| Error::Input(format!("{path}: row {nr} does not match the columns")))
+            ).collect::<Result<_>>()?;
+
+        let mut config = json.clone();
+        let mut seeds = Vec::new();
+        strip_config(&mut config, &mut seeds);
+        if seeds.is_empty(){
+            return Err(Error::Input(format!("the configuration in {path} contains no seed")));
+        }
+        Ok((Self{ config, seeds, format, points }, json))
+    }
+}
+
+/// Pool the points of several runs that only differ in their seed.
+///
+/// The pooled file contains the mean, variance and total number of samples of every point.
+/// Its json line is the configuration of the first run, with the seed replaced by all pooled seeds
+pub fn merge_runs(opt: MergeRunsOpt) -> Result<()>
+{
+    let mut runs = opt.files
+        .iter()
+        .map(|path| RunFile::read(path).map(|(run, json)| (path, run, json)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (first_path, first, _) = &runs[0];
+    let mut seeds: Vec<Value> = Vec::new();
+    for (path, run, _) in runs.iter(){
+        let mismatch = |what: &str| Error::Input(format!("{path} and {first_path} cannot be pooled, their {what} differ"));
+        if run.config != first.config{
+            return Err(mismatch("configurations"));
+        }
+        if run.format != first.format{
+            return Err(mismatch("output formats"));
+        }
+        let same_points = run.points.len() == first.points.len()
+            && run.points.iter()
+                .zip(first.points.iter())
+                .all(|((x, _), (first_x, _))| x.to_bits() == first_x.to_bits());
+        if !same_points{
+            return Err(mismatch("points"));
+        }
+        for seed in run.seeds.iter(){
+            if seeds.contains(seed){
+                return Err(Error::Input(format!("the seed {seed} is used more than once, see {path}")));
+            }
+            seeds.push(seed.clone());
+        }
+    }
+
+    let points: Vec<_> = (0..first.points.len())
+        .map(
+            |i|
+            {
+                let mut stats = PointStats::default();
+                for (_, run, _) in runs.iter(){
+                    stats.merge(&run.points[i].1);
+                }
+                (first.points[i].0, stats)
+            }
+        ).collect();
+
+    let format = first.format.clone();
+    let mut json = runs.swap_remove(0).2;
+    insert_seeds(&mut json, &seeds);
+
+    with_job_additions(
+        json.to_string(),
+        || -> Result<()> {
+            let mut writer = PointWriter::create(&opt.out, format, None)?;
+            for (x, stats) in points.iter(){
+                writer.write(*x, stats)?;
+            }
+            writer.finish()
+        }
+    )?;
+    println!("pooled {} runs with {} seeds into {}", opt.files.len(), seeds.len(), opt.out);
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Regenerate the trajectory of a single sample of a scan.
+//!
+//! The rng of every packet of a point only depends on the seed and the indices of
+//! the point and the packet, see [crate::scan::PointSeed]. Thus a sample is regenerated
+//! by creating the walker of its packet and drawing all samples of the packet up to it.
+use std::num::NonZeroUsize;
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use rand_pcg::Pcg64;
+
+use crate::{
+    error::*,
+    job::RunJob,
+    parse::{parse_and_add_to_global, FieldOverride},
+    scan::{build_global_pool, locate_sample, PointSeed},
+    table::TableWriter,
+    walker::{EffRandWalk, Walker}
+};
+
+#[derive(Parser, Debug)]
+pub struct ReplayOpt{
+    /// Job file of the scan, see `run`. Only EffLambda, EffTarget and EffBeta are supported
+    pub job: Utf8PathBuf,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set settings.seed=4`. Can be repeated
+    pub set: Vec<FieldOverride>,
+
+    #[arg(long)]
+    /// Seed of the scan, short for `--set settings.seed=SEED`
+    pub seed: Option<u64>,
+
+    #[arg(long, default_value_t = 0)]
+    /// Index of the point of the scan, counting from 0
+    pub point: usize,
+
+    #[arg(long)]
+    /// Index of the sample of the point, counting from 0
+    pub sample: usize,
+
+    #[arg(long, short)]
+    /// Name of the output file, the extension selects the format
+    pub out: Utf8PathBuf
+}
+
+/// Write the time-sorted trajectory of one sample of a scan:
+/// the rough walk, all bisected refinements, the mirror events and the first passage
+pub fn replay(opt: ReplayOpt) -> Result<()>
+{
+    let mut overrides = opt.set.clone();
+    if let Some(seed) = opt.seed{
+        overrides.push(FieldOverride::new("settings.seed", seed));
+    }
+    let job: RunJob = parse_and_add_to_global(Some(&opt.job), &overrides)?;
+    let (x, settings, seed, samples, points) = match &job{
+        RunJob::EffLambda { settings, .. } => {
+            let (x, walker) = settings.point(opt.point);
+            (x, walker, settings.seed, settings.samples_per_point, settings.lambda_samples)
+        },
+        RunJob::EffTarget { settings, .. } => {
+            let (x, walker) = settings.point(opt.point);
+            (x, walker, settings.seed, settings.samples_per_point, settings.target_samples)
+        },
+        RunJob::EffBeta { settings, .. } => {
+            let (x, walker) = settings.point(opt.point);
+            (x, walker, settings.seed, settings.samples_per_point, settings.beta_samples)
+        },
+        _ => return Err(Error::validation("type", "only EffLambda, EffTarget and EffBeta can be replayed"))
+    };
+    if opt.point >= points.get(){
+        return Err(Error::Input(format!("the scan has {points} points, there is no point {}", opt.point)));
+    }
+    let (packet, offset) = locate_sample(samples, opt.sample)
+        .ok_or_else(|| Error::Input(format!("the point has {samples} samples, there is no sample {}", opt.sample)))?;
+
+    // the replay is sequential, such that the metadata reports a single thread
+    build_global_pool(NonZeroUsize::MIN)?;
+    let seed = PointSeed::new(seed, opt.point);
+    let mut walker = EffRandWalk::<Pcg64>::create(&settings, seed.packet_rng(packet));
+    for _ in 0..offset{
+        walker.sample();
+        walker.recycle();
+    }
+    let sample = walker.sample();
+    let events = walker.trajectory();
+
+    let comments = vec![
+        "event: 0 = start of an interval, 1 = mirror event, 2 = first passage".to_owned(),
+        format!("point: {} x: {x} sample: {} fpt: {}", opt.point, opt.sample, sample.fpt)
+    ];
+    let mut table = TableWriter::create_with_comments(&opt.out, comments, ["time", "position", "level", "event"])?;
+    for event in events.iter(){
+        table.write_row(&[event.time, event.position, event.level as f64, event.kind as u8 as f64])?;
+    }
+    table.record_point(x, &seed)?;
+    table.finish()?;
+
+    println!(
+        "fpt {} after {} mirror events, {} rows written to {}",
+        sample.fpt,
+        sample.mirrors,
+        events.len(),
+        opt.out
+    );
+    Ok(())
+}
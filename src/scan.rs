@@ -0,0 +1,488 @@
+use std::{num::NonZeroUsize, ops::Range, sync::OnceLock};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use derivative::Derivative;
+use kahan::KahanSum;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    error::*,
+    shard::{PointFormat, PointWriter, Shard},
+    walker::{AnyWalker, AnyWalkerSettings, Sample, WalkCost, Walker}
+};
+
+/// Accumulated statistics of all samples drawn for one scan point
+#[derive(Debug, Clone, Default)]
+pub struct PointStats{
+    pub samples: u64,
+    pub sum_fpt: KahanSum<f64>,
+    pub sum_fpt_sq: KahanSum<f64>,
+    pub sum_resets: u64,
+    pub sum_mirrors: u64
+}
+
+impl PointStats{
+    #[inline]
+    pub fn add(&mut self, sample: &Sample)
+    {
+        self.samples += 1;
+        self.sum_fpt += sample.fpt;
+        self.sum_fpt_sq += sample.fpt * sample.fpt;
+        self.sum_resets += sample.resets;
+        self.sum_mirrors += sample.mirrors;
+    }
+
+    pub fn merge(&mut self, other: &Self)
+    {
+        self.samples += other.samples;
+        self.sum_fpt += &other.sum_fpt;
+        self.sum_fpt_sq += &other.sum_fpt_sq;
+        self.sum_resets += other.sum_resets;
+        self.sum_mirrors += other.sum_mirrors;
+    }
+
+    /// Combine the statistics of the packets of a point, in the given order.
+    /// The Kahan sums depend on the order, so a point has to be combined in the order of its packets,
+    /// like [measure_point] does, to be reproduced exactly
+    pub fn merged<'a, I>(packets: I) -> Self
+    where I: IntoIterator<Item = &'a Self>
+    {
+        packets.into_iter()
+            .fold(
+                Self::default(),
+                |mut acc, stats| {
+                    acc.merge(stats);
+                    acc
+                }
+            )
+    }
+
+    pub fn mean_fpt(&self) -> f64
+    {
+        self.sum_fpt.sum() / self.samples as f64
+    }
+
+    /// Population variance of the first passage times
+    pub fn variance_fpt(&self) -> f64
+    {
+        let mean = self.mean_fpt();
+        self.sum_fpt_sq.sum() / self.samples as f64 - mean * mean
+    }
+
+    pub fn mean_resets(&self) -> f64
+    {
+        self.sum_resets as f64 / self.samples as f64
+    }
+
+    pub fn mean_mirrors(&self) -> f64
+    {
+        self.sum_mirrors as f64 / self.samples as f64
+    }
+}
+
+/// Accumulated refinement of the bisecting walkers for one scan point, see [WalkCost].
+/// Tells whether the results of a point are limited by `max_depth`
+#[derive(Debug, Clone, Default)]
+pub struct BisectionStats{
+    pub samples: u64,
+    pub sum_depth: u64,
+    pub sum_bisections: u64,
+    /// samples whose hitting interval is on the deepest level
+    pub depth_limited: u64,
+    pub sum_missed_crossings: KahanSum<f64>
+}
+
+impl BisectionStats{
+    #[inline]
+    pub fn add(&mut self, cost: &WalkCost)
+    {
+        self.samples += 1;
+        self.sum_depth += cost.hit_depth as u64;
+        self.sum_bisections += cost.bisections as u64;
+        self.depth_limited += u64::from(cost.depth_limited);
+        self.sum_missed_crossings += cost.missed_crossings;
+    }
+
+    pub fn merge(&mut self, other: &Self)
+    {
+        self.samples += other.samples;
+        self.sum_depth += other.sum_depth;
+        self.sum_bisections += other.sum_bisections;
+        self.depth_limited += other.depth_limited;
+        self.sum_missed_crossings += &other.sum_missed_crossings;
+    }
+
+    /// Average refinement level of the hitting interval
+    pub fn mean_depth(&self) -> f64
+    {
+        self.sum_depth as f64 / self.samples as f64
+    }
+
+    /// Fraction of the samples whose hitting interval could not be refined further
+    pub fn depth_limited_fraction(&self) -> f64
+    {
+        self.depth_limited as f64 / self.samples as f64
+    }
+
+    pub fn mean_bisections(&self) -> f64
+    {
+        self.sum_bisections as f64 / self.samples as f64
+    }
+
+    /// Average expected number of crossings before the hit that were missed because of `max_depth`
+    pub fn mean_missed_crossings(&self) -> f64
+    {
+        self.sum_missed_crossings.sum() / self.samples as f64
+    }
+}
+
+/// Number of threads of the global rayon pool, once it is built
+static GLOBAL_POOL_THREADS: OnceLock<NonZeroUsize> = OnceLock::new();
+
+/// Use `threads` threads for all following rayon operations.
+///
+/// Only the first call builds the pool, later calls reuse it,
+/// such that several jobs can share one process, see [crate::batch]
+pub fn build_global_pool(threads: NonZeroUsize) -> Result<()>
+{
+    if GLOBAL_POOL_THREADS.get().is_some(){
+        return Ok(());
+    }
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads.get())
+        .build_global()
+        .map_err(|e| Error::Input(format!("unable to build thread pool: {e}")))?;
+    let _ = GLOBAL_POOL_THREADS.set(threads);
+    Ok(())
+}
+
+/// Number of packets every point is split into.
+/// It does not depend on the number of threads, such that neither do the results
+pub const PACKETS_PER_POINT: usize = 240;
+
+/// Seed of one scan point.
+///
+/// The rng of every packet is derived from the seed of the scan, the index of the point
+/// and the index of the packet. Thus every point can be measured on its own,
+/// see `--point`, and gives the same result for any number of threads
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PointSeed{
+    pub seed: u64,
+    pub point: u64
+}
+
+impl PointSeed{
+    pub fn new(seed: u64, point: usize) -> Self
+    {
+        Self { seed, point: point as u64 }
+    }
+
+    /// The rng of packet `packet` of this point
+    pub fn packet_rng(&self, packet: usize) -> Pcg64
+    {
+        let mut state = splitmix64(splitmix64(self.seed) ^ self.point);
+        state = splitmix64(state ^ packet as u64);
+        let mut seed = [0; 32];
+        for chunk in seed.chunks_exact_mut(8){
+            state = splitmix64(state);
+            chunk.copy_from_slice(&state.to_le_bytes());
+        }
+        Pcg64::from_seed(seed)
+    }
+}
+
+/// Finalizer of SplitMix64, decorrelates neighbouring inputs
+fn splitmix64(x: u64) -> u64
+{
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Number of samples of every packet of a point with `samples` samples
+fn packet_sizes(samples: usize) -> impl Iterator<Item = usize>
+{
+    let packets = samples.min(PACKETS_PER_POINT);
+    let per_packet = samples / packets;
+    let remainder = samples % packets;
+    (0..packets).map(move |packet| per_packet + usize::from(packet < remainder))
+}
+
+/// Packet and index within the packet of sample `sample` of a point with `samples` samples
+pub fn locate_sample(samples: NonZeroUsize, sample: usize) -> Option<(usize, usize)>
+{
+    let mut first = 0;
+    for (packet, amount) in packet_sizes(samples.get()).enumerate(){
+        if sample < first + amount{
+            return Some((packet, sample - first));
+        }
+        first += amount;
+    }
+    None
+}
+
+/// Draws `samples` samples of the walker `W` with the given settings.
+///
+/// The work is split into [PACKETS_PER_POINT] packets, each packet gets its own walker
+/// whose rng is derived from the `seed`, see [PointSeed::packet_rng].
+/// The packets are merged in order, so the result is bit-identical for any number of threads.
+/// Runs in the current rayon thread pool
+pub fn measure_point<W>(
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize
+) -> PointStats
+where W: Walker
+{
+    measure_point_shard::<W>(settings, seed, samples, Shard::WHOLE)
+}
+
+/// Like [measure_point], but only the packets belonging to the `shard` are sampled
+pub fn measure_point_shard<W>(
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize,
+    shard: Shard
+) -> PointStats
+where W: Walker
+{
+    let packets = measure_packets::<W>(settings, seed, samples, shard);
+    PointStats::merged(packets.iter().map(|(_, stats)| stats))
+}
+
+/// Statistics of every packet of a point that belongs to the `shard`, together with the index of the packet.
+/// The packets are not combined, such that the shards of a scan can be
+/// [merged](crate::shard::merge) in the same order as a single run combines them
+pub fn measure_packets<W>(
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize,
+    shard: Shard
+) -> Vec<(usize, PointStats)>
+where W: Walker
+{
+    let stats = sample_packets::<W, PointStats, _>(
+        settings,
+        seed,
+        samples,
+        shard,
+        false,
+        |stats, _, sample| stats.add(sample)
+    );
+    (shard.index..)
+        .step_by(shard.count.get())
+        .zip(stats)
+        .collect()
+}
+
+/// Like [measure_point], but also collects the [BisectionStats] of the samples.
+/// They stay empty for walkers that do not report their [cost](Walker::cost)
+pub fn measure_point_bisection<W>(
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize
+) -> (PointStats, BisectionStats)
+where W: Walker
+{
+    sample_packets::<W, (PointStats, BisectionStats), _>(
+        settings,
+        seed,
+        samples,
+        Shard::WHOLE,
+        false,
+        |(stats, bisection), walker, sample| {
+            stats.add(sample);
+            if let Some(cost) = walker.cost(){
+                bisection.add(&cost);
+            }
+        }
+    ).iter()
+        .fold(
+            (PointStats::default(), BisectionStats::default()),
+            |(mut stats, mut bisection), (packet_stats, packet_bisection)| {
+                stats.merge(packet_stats);
+                bisection.merge(packet_bisection);
+                (stats, bisection)
+            }
+        )
+}
+
+/// Measure point `x` of a scan and write it. A shard writes the packets of the point,
+/// see [measure_packets]. If the writer has [diagnostics](PointWriter::create_with_diagnostics),
+/// the [BisectionStats] are measured and written as well
+pub fn measure_and_write<W>(
+    writer: &mut PointWriter,
+    x: f64,
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize
+) -> Result<()>
+where W: Walker
+{
+    match writer.shard(){
+        Some(shard) => {
+            let packets = measure_packets::<W>(settings, seed, samples, shard);
+            writer.write_packets(x, seed.point, &packets)?;
+        },
+        None if writer.has_diagnostics() => {
+            let (stats, bisection) = measure_point_bisection::<W>(settings, seed, samples);
+            writer.write_with_diagnostics(x, &stats, &bisection)?;
+        },
+        None => {
+            let stats = measure_point::<W>(settings, seed, samples);
+            writer.write(x, &stats)?;
+        }
+    }
+    writer.record_point(x, &seed)
+}
+
+/// Draws the samples of the packets of a point that belong to the `shard`, like [measure_point_shard].
+///
+/// Every sample is passed to `fun`, together with the walker that drew it,
+/// which accumulates it into the value of its packet.
+/// If `record_events` is true, the walkers record their [events](Walker::events).
+/// The values of the packets are returned in the order of the packets
+pub fn sample_packets<W, A, F>(
+    settings: &W::Settings,
+    seed: PointSeed,
+    samples: NonZeroUsize,
+    shard: Shard,
+    record_events: bool,
+    fun: F
+) -> Vec<A>
+where W: Walker,
+    A: Default + Send,
+    F: Fn(&mut A, &W, &Sample) + Sync
+{
+    let packets: Vec<_> = packet_sizes(samples.get())
+        .enumerate()
+        .filter(|(packet, _)| shard.contains(*packet))
+        .collect();
+    packets
+        .into_par_iter()
+        .map(
+            |(packet, amount)|
+            {
+                let mut walker = W::create(settings, seed.packet_rng(packet));
+                if record_events{
+                    walker.record_events();
+                }
+                let mut acc = A::default();
+                for _ in 0..amount{
+                    let sample = walker.sample();
+                    fun(&mut acc, &walker, &sample);
+                    walker.recycle();
+                }
+                acc
+            }
+        ).collect()
+}
+
+// Selects the part of a scan that is measured.
+// Not a doc comment, it would become the about text of the subcommands that flatten it
+#[derive(Parser, Debug, Clone, Copy, Default)]
+pub struct ScanPart{
+    #[arg(long, value_name = "INDEX/COUNT")]
+    /// Only compute shard INDEX of COUNT, e.g., `--shard 0/4`.
+    /// The raw sums are written, combine all shards with `merge`
+    pub shard: Option<Shard>,
+
+    #[arg(long, value_name = "INDEX")]
+    /// Only measure the point INDEX of the scan, counting from 0.
+    /// The result is bit-identical to the same line of the complete scan
+    pub point: Option<usize>
+}
+
+impl ScanPart{
+    pub fn shard(&self) -> Shard
+    {
+        self.shard.unwrap_or(Shard::WHOLE)
+    }
+
+    /// Indices of the points of a scan with `count` points that are measured
+    pub fn points(&self, count: usize) -> Result<Range<usize>>
+    {
+        match self.point{
+            None => Ok(0..count),
+            Some(point) if point < count => Ok(point..point + 1),
+            Some(point) => Err(
+                Error::Input(format!("the scan has {count} points, there is no point {point}"))
+            )
+        }
+    }
+
+    /// Output of this part of a scan whose complete output is `out`, e.g.,
+    /// `{stem}_shard0of4_point3.{extension}`
+    pub fn output_path(&self, out: &Utf8Path) -> Utf8PathBuf
+    {
+        let mut out = match self.shard{
+            Some(shard) => shard.output_path(out),
+            None => out.to_owned()
+        };
+        if let Some(point) = self.point{
+            let name = match out.extension(){
+                Some(ext) => format!("{}_point{point}.{ext}", out.file_stem().unwrap_or_default()),
+                None => format!("{}_point{point}", out.file_name().unwrap_or_default())
+            };
+            out.set_file_name(name);
+        }
+        out
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+pub struct MeasureWalkerOpt
+{
+    pub walker: AnyWalkerSettings,
+    #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
+    pub samples: NonZeroUsize,
+    /// Number of threads. 
+    #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
+    pub j: NonZeroUsize,
+    pub seed: u64,
+    /// Also write the refinement of the bisection, see [BisectionStats].
+    /// Only for the walkers Eff and Eff2, not possible for shards
+    #[serde(default)]
+    pub diagnostics: bool
+}
+
+impl Validate for MeasureWalkerOpt{
+    fn validate(&self) -> Result<()> {
+        self.walker.validate().map_err(|e| e.within("walker"))?;
+        let bisects = matches!(self.walker, AnyWalkerSettings::Eff(_) | AnyWalkerSettings::Eff2(_));
+        if self.diagnostics && !bisects{
+            return Err(Error::validation("diagnostics", "only the walkers Eff and Eff2 bisect"));
+        }
+        Ok(())
+    }
+}
+
+/// Measure the mfpt of an arbitrary walker for a single configuration
+pub fn measure_walker(
+    opt: MeasureWalkerOpt,
+    file_name: Utf8PathBuf,
+    part: ScanPart
+) -> Result<()>
+{
+    build_global_pool(opt.j)?;
+    // the only point is 0
+    part.points(1)?;
+
+    let mut writer = PointWriter::create_with_diagnostics(file_name, PointFormat::Walker, part.shard, opt.diagnostics)?;
+
+    measure_and_write::<AnyWalker>(
+        &mut writer,
+        0.0,
+        &opt.walker,
+        PointSeed::new(opt.seed, 0),
+        opt.samples
+    )?;
+    writer.finish()
+}
@@ -0,0 +1,370 @@
+//! JSON Schemas of the job files.
+//!
+//! The schemas are printed by the `schema` subcommand. They are also used to
+//! explain why a job file was rejected, see [diff].
+use clap::{Parser, ValueEnum};
+use schemars::{
+    schema::{RootSchema, Schema, SchemaObject, SingleOrVec},
+    JsonSchema,
+    Map
+};
+use serde_json::Value;
+use std::path::Path;
+
+use crate::{
+    error::*,
+    parse::from_value,
+    job::RunJob,
+    cross_validation::CrossValidationJob,
+    benchmark::BenchmarkJob,
+    extrapolation::ExtrapolationJob,
+    tune::TuneJob,
+    scan::MeasureWalkerOpt,
+    walker::*
+};
+
+/// Keys that serde accepts in addition to the field names, as (alias, field)
+pub(crate) const ALIASES: &[(&str, &str)] = &[
+    ("settings", "settimgs")
+];
+
+/// All job files that are read by the subcommands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Job{
+    /// used by run
+    Run,
+    /// used by scan-resetting
+    ResettingWalker,
+    /// used by scan-mirror-resetting
+    ResettingMirrorWalker,
+    /// used by scan-uni-mirror-reset, scan-uni-mirror, scan-uni-mirror-adaptive,
+    /// scan-l-uni-mirror and scan-l-uni-mirror-reset
+    ResettingUniWalker,
+    /// used by mirror-hists
+    MirrorHists,
+    /// used by eff-rand-walk-lambda
+    MeasureMfptLambda,
+    /// used by eff-rand-walk-target
+    MeasureMfptTarget,
+    /// used by eff-rand-walk-beta, eff2-rand-walk-beta and eff-beta-create-job
+    MeasureMfptBeta,
+    /// used by measure-walker
+    MeasureWalker,
+    /// used by cross-validate
+    CrossValidation,
+    /// used by benchmark
+    Benchmark,
+    /// used by extrapolate
+    Extrapolation,
+    /// used by tune
+    Tune,
+    /// walker settings as expected by the C ABI
+    AnyWalker
+}
+
+impl Job{
+    /// Name of the job as used on the command line
+    pub fn name(self) -> String
+    {
+        self.to_possible_value()
+            .expect("no job is skipped")
+            .get_name()
+            .to_owned()
+    }
+
+    pub fn schema(self) -> RootSchema
+    {
+        match self{
+            Self::Run => schemars::schema_for!(RunJob),
+            Self::ResettingWalker => schemars::schema_for!(ResettingWalkerHusk),
+            Self::ResettingMirrorWalker => schemars::schema_for!(ResettingMirrorWalkerHusk),
+            Self::ResettingUniWalker => schemars::schema_for!(ResettingUniWalkerHusk),
+            Self::MirrorHists => schemars::schema_for!(MirroringWalkerHistJob),
+            Self::MeasureMfptLambda => schemars::schema_for!(MeasureMfptOpt),
+            Self::MeasureMfptTarget => schemars::schema_for!(MeasureMfptLOpt),
+            Self::MeasureMfptBeta => schemars::schema_for!(MeasureMfptBetaOpt),
+            Self::MeasureWalker => schemars::schema_for!(MeasureWalkerOpt),
+            Self::CrossValidation => schemars::schema_for!(CrossValidationJob),
+            Self::Benchmark => schemars::schema_for!(BenchmarkJob),
+            Self::Extrapolation => schemars::schema_for!(ExtrapolationJob),
+            Self::Tune => schemars::schema_for!(TuneJob),
+            Self::AnyWalker => schemars::schema_for!(AnyWalkerSettings)
+        }
+    }
+}
+
+impl Job{
+    /// Check that the json is a valid job of this type
+    pub fn check(self, json: &Value, file: Option<&Path>) -> Result<()>
+    {
+        match self{
+            Self::Run => from_value::<RunJob>(json, file).map(drop),
+            Self::ResettingWalker => from_value::<ResettingWalkerHusk>(json, file).map(drop),
+            Self::ResettingMirrorWalker => from_value::<ResettingMirrorWalkerHusk>(json, file).map(drop),
+            Self::ResettingUniWalker => from_value::<ResettingUniWalkerHusk>(json, file).map(drop),
+            Self::MirrorHists => from_value::<MirroringWalkerHistJob>(json, file).map(drop),
+            Self::MeasureMfptLambda => from_value::<MeasureMfptOpt>(json, file).map(drop),
+            Self::MeasureMfptTarget => from_value::<MeasureMfptLOpt>(json, file).map(drop),
+            Self::MeasureMfptBeta => from_value::<MeasureMfptBetaOpt>(json, file).map(drop),
+            Self::MeasureWalker => from_value::<MeasureWalkerOpt>(json, file).map(drop),
+            Self::CrossValidation => from_value::<CrossValidationJob>(json, file).map(drop),
+            Self::Benchmark => from_value::<BenchmarkJob>(json, file).map(drop),
+            Self::Extrapolation => from_value::<ExtrapolationJob>(json, file).map(drop),
+            Self::Tune => from_value::<TuneJob>(json, file).map(drop),
+            Self::AnyWalker => from_value::<AnyWalkerSettings>(json, file).map(drop)
+        }
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct SchemaOpt{
+    /// Print only the schema of this job.
+    /// If omitted, the schemas of all jobs are printed, keyed by the job name
+    pub job: Option<Job>
+}
+
+/// Print the requested schema(s) to stdout
+pub fn print_schemas(opt: SchemaOpt) -> Result<()>
+{
+    let value = match opt.job{
+        Some(job) => serde_json::to_value(job.schema())?,
+        None => {
+            let all: serde_json::Map<String, Value> = Job::value_variants()
+                .iter()
+                .map(|job| Ok((job.name(), serde_json::to_value(job.schema())?)))
+                .collect::<Result<_>>()?;
+            Value::Object(all)
+        }
+    };
+    serde_json::to_writer_pretty(std::io::stdout(), &value)?;
+    println!();
+    Ok(())
+}
+
+/// Compare a parsed json file with the schema of `T`.
+///
+/// Returns one line for every unknown key, missing key or unknown variant.
+/// Unknown keys come with a suggestion if a known key with a similar name exists.
+/// The types of the values are not checked, serde already reports them well enough
+pub fn diff<T: JsonSchema>(json: &Value) -> Vec<String>
+{
+    let root = schemars::schema_for!(T);
+    let mut differences = Vec::new();
+    compare(json, &root.schema, &root.definitions, "", &mut differences);
+    differences
+}
+
+fn compare(
+    json: &Value,
+    schema: &SchemaObject,
+    definitions: &Map<String, Schema>,
+    path: &str,
+    differences: &mut Vec<String>
+)
+{
+    if let Some(schema) = resolve(schema, definitions){
+        return compare(json, schema, definitions, path, differences);
+    }
+
+    if let (Some(variants), Value::String(val)) = (&schema.enum_values, json){
+        let known: Vec<&str> = variants.iter()
+            .filter_map(Value::as_str)
+            .collect();
+        if !known.contains(&val.as_str()){
+            differences.push(
+                format!("unknown variant `{val}` at {}{}", display_path(path), suggestion(val, &known))
+            );
+        }
+    }
+
+    if let Some(sub) = &schema.subschemas{
+        if let Some(all) = &sub.all_of{
+            for schema in all.iter().filter_map(as_object){
+                compare(json, schema, definitions, path, differences);
+            }
+        }
+        let variants = sub.one_of.as_ref().or(sub.any_of.as_ref());
+        if let (Some(variants), Value::Object(_)) = (variants, json){
+            let variants: Vec<_> = variants.iter().filter_map(as_object).collect();
+            match variants.iter().find(|variant| selects(json, variant, definitions)){
+                Some(variant) => compare(json, variant, definitions, path, differences),
+                None => {
+                    let expected = variants.iter()
+                        .map(|variant| describe_variant(variant, definitions))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    // internally tagged: suggest the tag closest to the one that was given
+                    let known_tags: Vec<&str> = variants.iter()
+                        .flat_map(|variant| tags(variant))
+                        .map(|(_, tag)| tag)
+                        .collect();
+                    let hint = variants.iter()
+                        .flat_map(|variant| tags(variant))
+                        .find_map(|(key, _)| json.get(key)?.as_str())
+                        .map_or_else(String::new, |given| suggestion(given, &known_tags));
+                    differences.push(
+                        format!("{} matches none of the variants, expected one of: {expected}{hint}", display_path(path))
+                    );
+                }
+            }
+        }
+    }
+
+    let (Some(object), Value::Object(map)) = (&schema.object, json) else {
+        return;
+    };
+    let known: Vec<&str> = object.properties
+        .keys()
+        .map(String::as_str)
+        .collect();
+    let present = |field: &str| {
+        map.contains_key(field)
+            || ALIASES.iter().any(|(alias, f)| *f == field && map.contains_key(*alias))
+    };
+
+    for (key, val) in map.iter(){
+        let field = ALIASES.iter()
+            .find(|(alias, field)| alias == key && !known.contains(&key.as_str()) && known.contains(field))
+            .map_or(key.as_str(), |(_, field)| field);
+        match object.properties.get(field).and_then(as_object){
+            Some(schema) => compare(val, schema, definitions, &join(path, key), differences),
+            // a boolean schema, nothing to compare
+            None if object.properties.contains_key(field) => (),
+            None => differences.push(
+                format!("unknown key `{}`{}", join(path, key), suggestion(key, &known))
+            )
+        }
+    }
+
+    for field in object.required.iter(){
+        if !present(field){
+            differences.push(format!("missing key `{}`", join(path, field)));
+        }
+    }
+}
+
+/// Follow a `$ref` into the definitions
+fn resolve<'a>(schema: &SchemaObject, definitions: &'a Map<String, Schema>) -> Option<&'a SchemaObject>
+{
+    let name = schema.reference.as_ref()?
+        .strip_prefix("#/definitions/")?;
+    definitions.get(name).and_then(as_object)
+}
+
+fn as_object(schema: &Schema) -> Option<&SchemaObject>
+{
+    match schema{
+        Schema::Object(obj) => Some(obj),
+        Schema::Bool(_) => None
+    }
+}
+
+/// Properties of a variant that only allow a single string, i.e., the tag of internally tagged enums
+fn tags(variant: &SchemaObject) -> Vec<(&str, &str)>
+{
+    variant.object.iter()
+        .flat_map(|object| object.properties.iter())
+        .filter_map(
+            |(key, schema)| {
+                let values = as_object(schema)?.enum_values.as_ref()?;
+                match values.as_slice(){
+                    [Value::String(tag)] => Some((key.as_str(), tag.as_str())),
+                    _ => None
+                }
+            }
+        ).collect()
+}
+
+/// Does the json object belong to this variant?
+/// Internally tagged variants are selected by their tag,
+/// externally tagged variants by their single required key
+fn selects(json: &Value, variant: &SchemaObject, definitions: &Map<String, Schema>) -> bool
+{
+    if let Some(resolved) = resolve(variant, definitions){
+        return selects(json, resolved, definitions);
+    }
+    let tags = tags(variant);
+    if !tags.is_empty(){
+        return tags.iter()
+            .all(|(key, tag)| json.get(key).and_then(Value::as_str) == Some(tag));
+    }
+    match &variant.object{
+        Some(object) => object.required.iter().all(|key| json.get(key).is_some()),
+        None => false
+    }
+}
+
+fn describe_variant(variant: &SchemaObject, definitions: &Map<String, Schema>) -> String
+{
+    if let Some(resolved) = resolve(variant, definitions){
+        return describe_variant(resolved, definitions);
+    }
+    let tags = tags(variant);
+    if !tags.is_empty(){
+        return tags.iter()
+            .map(|(key, tag)| format!("{key}: \"{tag}\""))
+            .collect::<Vec<_>>()
+            .join(" ");
+    }
+    let keys = variant.object.iter()
+        .flat_map(|object| object.required.iter())
+        .map(|key| format!("`{key}`"))
+        .collect::<Vec<_>>()
+        .join(" and ");
+    if keys.is_empty(){
+        match &variant.instance_type{
+            Some(SingleOrVec::Single(ty)) => format!("{ty:?}").to_lowercase(),
+            _ => "unknown".to_owned()
+        }
+    } else {
+        format!("{{{keys}}}")
+    }
+}
+
+fn join(path: &str, key: &str) -> String
+{
+    if path.is_empty(){
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
+
+fn display_path(path: &str) -> String
+{
+    if path.is_empty(){
+        "top level".to_owned()
+    } else {
+        format!("`{path}`")
+    }
+}
+
+fn suggestion(key: &str, known: &[&str]) -> String
+{
+    let max_distance = (key.chars().count() / 3).max(2);
+    known.iter()
+        .map(|candidate| (levenshtein(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map_or_else(String::new, |(_, candidate)| format!(", did you mean `{candidate}`?"))
+}
+
+fn levenshtein(a: &str, b: &str) -> usize
+{
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate(){
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate(){
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                diagonal
+            } else {
+                1 + diagonal.min(above).min(row[j])
+            };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
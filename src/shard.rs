@@ -0,0 +1,467 @@
+//! Split a scan into shards, which run as independent processes, and merge their outputs.
+//!
+//! Every point of a scan is sampled in packets, see [crate::scan::measure_point_shard].
+//! Each shard only samples the packets `index`, `index + count`, `index + 2 count`, ... of every point.
+//! The rng of a packet only depends on the seed and the indices of the point and the packet,
+//! see [crate::scan::PointSeed], thus the shards are disjoint and use the same seeds as a single run.
+//! Instead of the mfpt a shard writes the raw sums of every packet, see [crate::scan::measure_packets].
+//! The `merge` subcommand combines the packets of every point in the order of their indices,
+//! exactly like a single run, and thus writes the same output.
+use std::{
+    collections::BTreeMap,
+    fmt,
+    num::NonZeroUsize,
+    str::FromStr
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use kahan::KahanSum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::*,
+    dat::DatFile,
+    misc::with_job_additions,
+    scan::{BisectionStats, PointStats},
+    table::{OutputFormat, TableWriter}
+};
+
+/// Prefix of the comment line that identifies a shard output, after the `# `
+pub(crate) const SHARD_PREFIX: &str = "shard: ";
+
+/// Columns of a shard output, one row per packet
+const RAW_HEADER: [&str; 10] = [
+    "x",
+    "point",
+    "packet",
+    "samples",
+    "sum_fpt",
+    "sum_fpt_err",
+    "sum_fpt_sq",
+    "sum_fpt_sq_err",
+    "sum_resets",
+    "sum_mirrors"
+];
+
+/// Shard `index` of `count`, given as `INDEX/COUNT` on the command line. Indices start at 0
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard{
+    pub index: usize,
+    pub count: NonZeroUsize
+}
+
+impl Shard{
+    /// The only shard of an unsharded run
+    pub const WHOLE: Self = Self{ index: 0, count: NonZeroUsize::MIN };
+
+    /// Does the packet with index `packet` belong to this shard?
+    #[inline]
+    pub fn contains(&self, packet: usize) -> bool
+    {
+        packet % self.count.get() == self.index
+    }
+
+    /// Output of this shard for a job whose output is `path`,
+    /// e.g., `mfpt_shard1of4.dat` for `mfpt.dat`
+    pub fn output_path(&self, path: &Utf8Path) -> Utf8PathBuf
+    {
+        let name = format!("shard{}of{}", self.index, self.count);
+        match (path.file_stem(), path.extension()){
+            (Some(stem), Some(ext)) => path.with_file_name(format!("{stem}_{name}.{ext}")),
+            _ => Utf8PathBuf::from(format!("{path}_{name}"))
+        }
+    }
+}
+
+impl FromStr for Shard{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (index, count) = s.split_once('/')
+            .ok_or_else(|| format!("expected INDEX/COUNT, but got '{s}'"))?;
+        let index: usize = index.parse()
+            .map_err(|e| format!("invalid shard index '{index}': {e}"))?;
+        let count: NonZeroUsize = count.parse()
+            .map_err(|e| format!("invalid number of shards '{count}': {e}"))?;
+        if index >= count.get(){
+            return Err(format!("shard index {index} is out of range, the indices go from 0 to {}", count.get() - 1));
+        }
+        Ok(Self{ index, count })
+    }
+}
+
+impl fmt::Display for Shard{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+/// Columns of [PointFormat::Walker]
+const WALKER_HEADER: [&str; 5] = ["mfpt", "variance", "average_resets", "average_mirrors", "samples"];
+
+/// Columns appended by [PointWriter::create_with_diagnostics], see [BisectionStats]
+pub(crate) const DIAGNOSTICS_HEADER: [&str; 4] = ["depth", "max_depth_hits", "bisections", "missed_crossings"];
+
+/// How the points are written by an unsharded run
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PointFormat{
+    /// One line `x mfpt variance samples` per point, the name of `x` is given
+    Mfpt{ x: String },
+    /// A single line with the mfpt, its variance, the average number of resets and mirrors and the number of samples
+    Walker
+}
+
+impl PointFormat{
+    fn header(&self) -> Vec<&str>
+    {
+        match self{
+            Self::Mfpt { x } => vec![x.as_str(), "mfpt", "variance", "samples"],
+            Self::Walker => WALKER_HEADER.to_vec()
+        }
+    }
+
+    /// Recognize the format from the column names, see [crate::dat::DatHeader::columns]
+    pub fn from_columns(columns: &[String]) -> Option<Self>
+    {
+        let names: Vec<&str> = columns.iter()
+            .map(String::as_str)
+            .collect();
+        match names.as_slice(){
+            [x, "mfpt", "variance", "samples"] => Some(Self::Mfpt { x: x.to_string() }),
+            cols if cols == WALKER_HEADER => Some(Self::Walker),
+            _ => None
+        }
+    }
+
+    /// Parse a row written by [PointWriter] for an unsharded run.
+    ///
+    /// The sums are recovered from the averages, which is exact up to rounding
+    pub fn parse_row(&self, row: &[f64]) -> Option<(f64, PointStats)>
+    {
+        let (x, mfpt, variance, resets, mirrors, samples) = match (self, row){
+            (Self::Mfpt { .. }, &[x, mfpt, variance, samples]) => (x, mfpt, variance, 0.0, 0.0, samples),
+            (Self::Walker, &[mfpt, variance, resets, mirrors, samples]) => (0.0, mfpt, variance, resets, mirrors, samples),
+            _ => return None
+        };
+        let stats = PointStats{
+            samples: samples as u64,
+            sum_fpt: KahanSum::new_with_value(mfpt * samples),
+            sum_fpt_sq: KahanSum::new_with_value((variance + mfpt * mfpt) * samples),
+            sum_resets: (resets * samples).round() as u64,
+            sum_mirrors: (mirrors * samples).round() as u64
+        };
+        Some((x, stats))
+    }
+}
+
+/// First line of every shard output after the command and version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ShardInfo{
+    #[serde(flatten)]
+    shard: Shard,
+    format: PointFormat
+}
+
+/// Writes the measured points of a scan, in any [crate::table::OutputFormat].
+///
+/// For a shard the raw sums of every packet are written instead of the final values,
+/// shards are always written as `.dat` table
+pub struct PointWriter{
+    table: TableWriter,
+    format: PointFormat,
+    shard: Option<Shard>,
+    diagnostics: bool
+}
+
+impl PointWriter{
+    /// Create the file and write the header
+    pub fn create<P>(path: P, format: PointFormat, shard: Option<Shard>) -> Result<Self>
+    where P: AsRef<Utf8Path>
+    {
+        Self::create_with_diagnostics(path, format, shard, false)
+    }
+
+    /// Like [PointWriter::create], but if `diagnostics` is true, the [DIAGNOSTICS_HEADER] columns are appended,
+    /// see [PointWriter::write_with_diagnostics]. Shards only write the raw sums of the mfpt,
+    /// thus they can not have diagnostics
+    pub fn create_with_diagnostics<P>(path: P, format: PointFormat, shard: Option<Shard>, diagnostics: bool) -> Result<Self>
+    where P: AsRef<Utf8Path>
+    {
+        let path = path.as_ref();
+        if diagnostics && shard.is_some(){
+            return Err(Error::Input("the bisection diagnostics can not be written by shards, run without --shard".to_owned()));
+        }
+        let table = match shard{
+            Some(shard) => {
+                if OutputFormat::from_path(path) != OutputFormat::Dat{
+                    return Err(Error::Input(format!("shards are written as .dat tables, but the output is {path}")));
+                }
+                let info = ShardInfo{ shard, format: format.clone() };
+                let comment = format!("{SHARD_PREFIX}{}", serde_json::to_string(&info)?);
+                TableWriter::create_with_comments(path, vec![comment], RAW_HEADER)?
+            },
+            None => {
+                let mut header = format.header();
+                if diagnostics{
+                    header.extend(DIAGNOSTICS_HEADER);
+                }
+                TableWriter::create(path, header)?
+            }
+        };
+        Ok(Self{ table, format, shard, diagnostics })
+    }
+
+    /// Were the diagnostics columns requested?
+    pub fn has_diagnostics(&self) -> bool
+    {
+        self.diagnostics
+    }
+
+    /// The shard that is written, None for an unsharded run
+    pub fn shard(&self) -> Option<Shard>
+    {
+        self.shard
+    }
+
+    /// Write the raw sums of the packets of point number `point` of a shard, see [crate::scan::measure_packets]
+    pub fn write_packets(&mut self, x: f64, point: u64, packets: &[(usize, PointStats)]) -> Result<()>
+    {
+        if self.shard.is_none(){
+            return Err(Error::Input("only shards write the packets of a point".to_owned()));
+        }
+        for (packet, stats) in packets{
+            self.table.write_row(
+                &[
+                    x,
+                    point as f64,
+                    *packet as f64,
+                    stats.samples as f64,
+                    stats.sum_fpt.sum(),
+                    stats.sum_fpt.err(),
+                    stats.sum_fpt_sq.sum(),
+                    stats.sum_fpt_sq.err(),
+                    stats.sum_resets as f64,
+                    stats.sum_mirrors as f64
+                ]
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Write one point of an unsharded run. `x` is ignored by [PointFormat::Walker]
+    pub fn write(&mut self, x: f64, stats: &PointStats) -> Result<()>
+    {
+        if self.shard.is_some(){
+            return Err(Error::Input("a shard writes the packets of a point".to_owned()));
+        }
+        if self.diagnostics{
+            return Err(Error::Input("the point needs its bisection diagnostics".to_owned()));
+        }
+        self.table.write_row(&self.row(x, stats))
+    }
+
+    /// Write one point of an unsharded run together with its [BisectionStats],
+    /// the writer has to be [created with diagnostics](PointWriter::create_with_diagnostics)
+    pub fn write_with_diagnostics(&mut self, x: f64, stats: &PointStats, bisection: &BisectionStats) -> Result<()>
+    {
+        if !self.diagnostics{
+            return Err(Error::Input("the output has no columns for the bisection diagnostics".to_owned()));
+        }
+        let mut row = self.row(x, stats);
+        row.extend(
+            [
+                bisection.mean_depth(),
+                bisection.depth_limited_fraction(),
+                bisection.mean_bisections(),
+                bisection.mean_missed_crossings()
+            ]
+        );
+        self.table.write_row(&row)
+    }
+
+    /// Row of an unsharded run, without diagnostics
+    fn row(&self, x: f64, stats: &PointStats) -> Vec<f64>
+    {
+        match self.format{
+            PointFormat::Mfpt { .. } => vec![x, stats.mean_fpt(), stats.variance_fpt(), stats.samples as f64],
+            PointFormat::Walker => vec![
+                stats.mean_fpt(),
+                stats.variance_fpt(),
+                stats.mean_resets(),
+                stats.mean_mirrors(),
+                stats.samples as f64
+            ]
+        }
+    }
+
+    /// See [TableWriter::record_point]
+    pub fn record_point<S: Serialize>(&mut self, x: f64, seed: &S) -> Result<()>
+    {
+        self.table.record_point(x, seed)
+    }
+
+    pub fn finish(self) -> Result<()>
+    {
+        self.table.finish()
+    }
+}
+
+/// Rebuild a Kahan sum from its written sum and error.
+///
+/// The error is the rounding error of the last addition, thus below half an ulp of the sum.
+/// Adding its negative leaves the sum unchanged and sets the error, which restores the state exactly.
+/// None, if it could not be restored
+fn kahan_from_parts(sum: f64, err: f64) -> Option<KahanSum<f64>>
+{
+    let mut kahan = KahanSum::new_with_value(sum);
+    if err != 0.0{
+        kahan += -err;
+    }
+    (kahan.sum().to_bits() == sum.to_bits() && kahan.err() == err).then_some(kahan)
+}
+
+/// One row of a shard output
+struct PacketRow{
+    x: f64,
+    point: usize,
+    packet: usize,
+    stats: PointStats
+}
+
+fn parse_raw_row(row: &[f64]) -> Option<PacketRow>
+{
+    let &[x, point, packet, samples, sum_fpt, sum_fpt_err, sum_fpt_sq, sum_fpt_sq_err, sum_resets, sum_mirrors] = row else {
+        return None;
+    };
+    let stats = PointStats{
+        samples: samples as u64,
+        sum_fpt: kahan_from_parts(sum_fpt, sum_fpt_err)?,
+        sum_fpt_sq: kahan_from_parts(sum_fpt_sq, sum_fpt_sq_err)?,
+        sum_resets: sum_resets as u64,
+        sum_mirrors: sum_mirrors as u64
+    };
+    Some(PacketRow { x, point: point as usize, packet: packet as usize, stats })
+}
+
+/// Content of one shard output
+struct ShardFile{
+    info: ShardInfo,
+    /// The json of the job, if the file contains it
+    additions: Option<String>,
+    packets: Vec<PacketRow>
+}
+
+impl ShardFile{
+    fn read(path: &Utf8Path) -> Result<Self>
+    {
+        let file = DatFile::read(path)?;
+        let info = file.header
+            .other_with_prefix(SHARD_PREFIX)
+            .ok_or_else(|| Error::Input(format!("{path} is not the output of a shard")))?;
+        let info: ShardInfo = serde_json::from_str(info)
+            .map_err(|source| Error::Json { path: Some(path.into()), source })?;
+        let packets = file.rows
+            .iter()
+            .zip(1..)
+            .map(
+                |(row, nr)| parse_raw_row(row)
+                    .ok_or_else(|| Error::Input(format!("{path}: row {nr} is not a valid shard row")))
+            ).collect::<Result<_>>()?;
+        Ok(
+            Self{
+                info,
+                additions: file.header.config.map(|config| config.to_string()),
+                packets
+            }
+        )
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct MergeOpt{
+    #[arg(long, short)]
+    /// Name of the merged output file
+    pub out: Utf8PathBuf,
+
+    #[arg(required = true)]
+    /// Outputs of all shards of one scan, in any order
+    pub shards: Vec<Utf8PathBuf>
+}
+
+/// Combine the outputs of all shards of a scan into the output of a single run
+pub fn merge(opt: MergeOpt) -> Result<()>
+{
+    let mut files = opt.shards
+        .iter()
+        .map(|path| ShardFile::read(path).map(|file| (path, file)))
+        .collect::<Result<Vec<_>>>()?;
+    files.sort_by_key(|(_, file)| file.info.shard.index);
+
+    let (first_path, first) = &files[0];
+    let count = first.info.shard.count;
+    for (path, file) in files.iter(){
+        let mismatch = |what: &str| Error::Input(format!("{path} and {first_path} belong to different scans, their {what} differ"));
+        if file.info.shard.count != count{
+            return Err(mismatch("numbers of shards"));
+        }
+        if file.info.format != first.info.format{
+            return Err(mismatch("output formats"));
+        }
+        if file.additions != first.additions{
+            return Err(mismatch("job settings"));
+        }
+    }
+    for (expected, (path, file)) in files.iter().enumerate(){
+        if file.info.shard.index != expected{
+            let problem = if file.info.shard.index < expected {
+                format!("shard {} is given more than once, see {path}", file.info.shard)
+            } else {
+                format!("shard {expected}/{count} is missing")
+            };
+            return Err(Error::Input(problem));
+        }
+    }
+    if files.len() != count.get(){
+        return Err(Error::Input(format!("shard {}/{count} is missing", files.len())));
+    }
+
+    // the packets of every point, by the index of the point
+    let mut packets: BTreeMap<usize, (f64, Vec<(usize, PointStats)>)> = BTreeMap::new();
+    for (path, file) in files.iter(){
+        for row in file.packets.iter(){
+            let (x, point_packets) = packets.entry(row.point)
+                .or_insert_with(|| (row.x, Vec::new()));
+            if x.to_bits() != row.x.to_bits(){
+                return Err(
+                    Error::Input(format!("{path} and {first_path} belong to different scans, the values of point {} differ", row.point))
+                );
+            }
+            point_packets.push((row.packet, row.stats.clone()));
+        }
+    }
+    let mut points = Vec::with_capacity(packets.len());
+    for (point, (x, mut point_packets)) in packets{
+        // combined in the same order as by a single run
+        point_packets.sort_by_key(|(packet, _)| *packet);
+        if let Some(packet) = point_packets.iter()
+            .zip(0..)
+            .find_map(|((packet, _), expected)| (*packet != expected).then_some(expected))
+        {
+            return Err(Error::Input(format!("packet {packet} of point {point} is missing or given more than once")));
+        }
+        points.push((x, PointStats::merged(point_packets.iter().map(|(_, stats)| stats))));
+    }
+
+    let write = || -> Result<()> {
+        let mut writer = PointWriter::create(&opt.out, first.info.format.clone(), None)?;
+        for (x, stats) in points.iter(){
+            writer.write(*x, stats)?;
+        }
+        writer.finish()
+    };
+    match first.additions.clone(){
+        Some(additions) => with_job_additions(additions, write),
+        None => write()
+    }
+}
@@ -0,0 +1,303 @@
+//! Generate job files by sweeping over arbitrary fields of a base job file.
+//!
+//! Every axis changes one json path. The axes are either combined as cartesian
+//! product or element wise (zipped). For every combination a job file is written,
+//! named after the base file and the changed values, and all of them are listed
+//! in a manifest.
+use std::{collections::BTreeSet, io::{BufReader, BufWriter}, str::FromStr};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::Parser;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::*,
+    misc::RatioIter,
+    parse::{apply_overrides, FieldOverride},
+    schema::Job
+};
+
+/// Output keys of the job files. If the base file contains them and they are not swept,
+/// the name of the job is appended, such that the jobs do not overwrite each others results
+const OUTPUT_KEYS: [&str; 2] = ["out", "out_prefix"];
+
+/// One parameter axis of the sweep, given as `path.to.field=SPEC` on the command line.
+///
+/// SPEC is one of
+/// * `lin:START:END:N` N equidistant values, both ends included
+/// * `log:START:END:N` N logarithmically spaced values, both ends included
+/// * `list:V1,V2,...` explicit values, each is parsed as json or used as string if that fails
+/// * `[V1, V2, ...]` explicit values as json array
+#[derive(Debug, Clone)]
+pub struct Axis{
+    pub path: String,
+    pub values: Vec<Value>
+}
+
+impl FromStr for Axis{
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (path, spec) = s.split_once('=')
+            .ok_or_else(|| format!("expected PATH=SPEC, but got '{s}'"))?;
+        if path.is_empty() || path.split('.').any(str::is_empty){
+            return Err(format!("invalid path '{path}'"));
+        }
+
+        let range = |rest: &str| -> std::result::Result<(f64, f64, i64), String> {
+            let parts: Vec<_> = rest.split(':').collect();
+            let [start, end, n] = parts.as_slice() else {
+                return Err(format!("expected START:END:N, but got '{rest}'"));
+            };
+            let float = |v: &str| v.parse::<f64>().map_err(|e| format!("invalid number '{v}': {e}"));
+            let n = n.parse::<i64>().map_err(|e| format!("invalid number of values '{n}': {e}"))?;
+            Ok((float(start)?, float(end)?, n))
+        };
+
+        let values: Vec<Value> = if let Some(rest) = spec.strip_prefix("lin:"){
+            let (start, end, n) = range(rest)?;
+            RatioIter::get_ratio_iter(start, end, n)
+                .map_err(|e| e.to_string())?
+                .float_iter()
+                .map(Value::from)
+                .collect()
+        } else if let Some(rest) = spec.strip_prefix("log:"){
+            let (start, end, n) = range(rest)?;
+            if n < 2 {
+                return Err(format!("a range needs at least 2 values, but {n} were requested"));
+            }
+            if !(start > 0.0 && end > 0.0) {
+                return Err("log ranges need positive start and end".to_owned());
+            }
+            let factor = (end / start).ln() / (n - 1) as f64;
+            (0..n)
+                .map(|i| if i == n - 1 { end } else { start * (factor * i as f64).exp() })
+                .map(Value::from)
+                .collect()
+        } else if let Some(rest) = spec.strip_prefix("list:"){
+            rest.split(',')
+                .map(|v| serde_json::from_str(v).unwrap_or_else(|_| Value::String(v.to_owned())))
+                .collect()
+        } else if spec.starts_with('['){
+            serde_json::from_str(spec)
+                .map_err(|e| format!("invalid json array '{spec}': {e}"))?
+        } else {
+            return Err(format!("unknown axis '{spec}', expected lin:, log:, list: or a json array"));
+        };
+
+        if values.is_empty(){
+            return Err(format!("axis {path} has no values"));
+        }
+        Ok(Self{ path: path.to_owned(), values })
+    }
+}
+
+impl Axis{
+    /// Short name used in the file names: the last part of the path,
+    /// or the whole path if the last part is not unique
+    fn short_name(&self, axes: &[Axis]) -> String
+    {
+        let last = |path: &str| path.rsplit('.').next().unwrap_or(path).to_owned();
+        let name = last(&self.path);
+        let ambiguous = axes.iter()
+            .filter(|axis| last(&axis.path) == name)
+            .count() > 1;
+        if ambiguous {
+            self.path.replace('.', "-")
+        } else {
+            name
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct SweepOpt{
+    /// The base job file. Every generated job is a copy of it with the swept fields changed
+    pub base: Utf8PathBuf,
+
+    #[arg(long = "axis", short, value_name = "PATH=SPEC", required = true)]
+    /// Field to sweep, e.g., `--axis settings.settimgs.a=lin:0.1:0.9:9`.
+    /// SPEC is `lin:START:END:N`, `log:START:END:N`, `list:V1,V2,...` or a json array.
+    /// Can be repeated
+    pub axes: Vec<Axis>,
+
+    #[arg(long)]
+    /// Combine the axes element wise instead of using the cartesian product.
+    /// All axes need the same number of values
+    pub zip: bool,
+
+    #[arg(long, short, default_value = ".")]
+    /// Directory for the job files and the manifest
+    pub out_dir: Utf8PathBuf,
+
+    #[arg(long, short, value_enum, default_value_t = Job::Run)]
+    /// Type of the job. Every generated job is checked against it
+    pub job: Job
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestAxis{
+    pub path: String,
+    pub values: Vec<Value>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEntry{
+    /// Path of the job file, relative to the directory of the manifest
+    pub file: Utf8PathBuf,
+    /// Values of all axes, keyed by their path
+    pub values: serde_json::Map<String, Value>
+}
+
+/// Lists all generated jobs, see the `batch` subcommand to run them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest{
+    pub base: Utf8PathBuf,
+    pub zip: bool,
+    pub axes: Vec<ManifestAxis>,
+    pub jobs: Vec<ManifestEntry>
+}
+
+impl Manifest{
+    pub fn read(path: &Utf8Path) -> Result<Self>
+    {
+        let reader = BufReader::new(fs_err::File::open(path)?);
+        serde_json::from_reader(reader)
+            .map_err(|source| Error::Json { path: Some(path.into()), source })
+    }
+}
+
+fn value_for_name(value: &Value) -> String
+{
+    let raw = match value{
+        Value::String(s) => s.clone(),
+        other => other.to_string()
+    };
+    raw.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+') { c } else { '_' })
+        .collect()
+}
+
+/// Append the name of the job to the output keys that are not swept
+fn rename_outputs(json: &mut Value, axes: &[Axis], suffix: &str)
+{
+    let Value::Object(map) = json else {
+        return;
+    };
+    for key in OUTPUT_KEYS{
+        if axes.iter().any(|axis| axis.path == key){
+            continue;
+        }
+        if let Some(Value::String(out)) = map.get_mut(key){
+            let path = Utf8Path::new(out.as_str());
+            let renamed = match (path.file_stem(), path.extension()){
+                (Some(stem), Some(ext)) => path.with_file_name(format!("{stem}_{suffix}.{ext}")),
+                _ => Utf8PathBuf::from(format!("{out}_{suffix}"))
+            };
+            *out = renamed.into_string();
+        }
+    }
+}
+
+/// All combinations of the values of the axes, each combination contains one value per axis
+fn combinations(axes: &[Axis], zip: bool) -> Result<Vec<Vec<&Value>>>
+{
+    if zip{
+        let len = axes[0].values.len();
+        if let Some(axis) = axes.iter().find(|axis| axis.values.len() != len){
+            return Err(
+                Error::validation(
+                    "axis",
+                    format!(
+                        "zipped axes need the same number of values, but {} has {} and {} has {len}",
+                        axis.path,
+                        axis.values.len(),
+                        axes[0].path
+                    )
+                )
+            );
+        }
+        Ok(
+            (0..len)
+                .map(|i| axes.iter().map(|axis| &axis.values[i]).collect())
+                .collect()
+        )
+    } else {
+        Ok(
+            axes.iter()
+                .map(|axis| axis.values.iter())
+                .multi_cartesian_product()
+                .collect()
+        )
+    }
+}
+
+/// Write one job file per combination of the axes and the manifest
+pub fn sweep(opt: SweepOpt) -> Result<()>
+{
+    let base: Value = {
+        let reader = BufReader::new(fs_err::File::open(&opt.base)?);
+        serde_json::from_reader(reader)
+            .map_err(|source| Error::Json { path: Some(opt.base.clone().into()), source })?
+    };
+    let stem = opt.base.file_stem().unwrap_or("job");
+    let names: Vec<_> = opt.axes.iter()
+        .map(|axis| axis.short_name(&opt.axes))
+        .collect();
+
+    let mut used_names = BTreeSet::new();
+    let mut jobs = Vec::new();
+    for combination in combinations(&opt.axes, opt.zip)?{
+        let suffix = names.iter()
+            .zip(combination.iter())
+            .map(|(name, value)| format!("{name}{}", value_for_name(value)))
+            .join("_");
+        let file = Utf8PathBuf::from(format!("{stem}_{suffix}.json"));
+        if !used_names.insert(file.clone()){
+            return Err(Error::Input(format!("the sweep creates the job file {file} more than once")));
+        }
+
+        let overrides: Vec<_> = opt.axes.iter()
+            .zip(combination.iter())
+            .map(|(axis, &value)| FieldOverride::new(&axis.path, value.clone()))
+            .collect();
+        let mut json = base.clone();
+        apply_overrides(&mut json, &overrides)?;
+        rename_outputs(&mut json, &opt.axes, &suffix);
+        opt.job.check(&json, Some(opt.out_dir.join(&file).as_std_path()))?;
+
+        let values = opt.axes.iter()
+            .zip(combination)
+            .map(|(axis, value)| (axis.path.clone(), value.clone()))
+            .collect();
+        jobs.push((json, ManifestEntry{ file, values }));
+    }
+
+    // nothing is written before all jobs passed the check
+    fs_err::create_dir_all(&opt.out_dir)?;
+    let jobs = jobs.into_iter()
+        .map(
+            |(json, entry)|
+            {
+                let writer = BufWriter::new(fs_err::File::create(opt.out_dir.join(&entry.file))?);
+                serde_json::to_writer_pretty(writer, &json)?;
+                Ok(entry)
+            }
+        ).collect::<Result<Vec<_>>>()?;
+
+    let manifest = Manifest{
+        base: opt.base.clone(),
+        zip: opt.zip,
+        axes: opt.axes.iter()
+            .map(|axis| ManifestAxis{ path: axis.path.clone(), values: axis.values.clone() })
+            .collect(),
+        jobs
+    };
+    let manifest_path = opt.out_dir.join(format!("{stem}_manifest.json"));
+    let writer = BufWriter::new(fs_err::File::create(&manifest_path)?);
+    serde_json::to_writer_pretty(writer, &manifest)?;
+    println!("wrote {} jobs, see {manifest_path}", manifest.jobs.len());
+    Ok(())
+}
@@ -2,6 +2,7 @@ use std::{collections::VecDeque, num::NonZeroUsize, sync::Mutex};
 
 
 
+/// Queue that can be shared between threads
 pub struct SyncQueue<T>{
     queue: Mutex<VecDeque<T>>
 }
@@ -46,6 +47,7 @@ impl<T> SyncQueue<T>
 
 impl SyncQueue<usize>
 {
+    /// Split `samples` into roughly `desired_package_amount` packages
     pub fn create_work_queue(samples: usize, desired_package_amount: NonZeroUsize) -> Self
     {
         let mut remaining = samples;
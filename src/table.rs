@@ -0,0 +1,538 @@
+//! Output formats of the scans and histograms.
+//!
+//! The format is selected by the extension of the output file:
+//! * `.csv` comma separated values with a header row
+//! * `.jsonl` one json object per row, keyed by the column names
+//! * `.npy` a single 2d float64 NumPy array, rows times columns
+//! * `.npz` a NumPy archive with one float64 array per column and the metadata as string `metadata`
+//! * everything else is written as our commented `.dat` table, see [crate::dat]
+//!
+//! The `.dat` tables contain the version, the configuration and the command line as comments.
+//! Every output also gets a json sidecar `{file}.meta.json`, see [RunMetadata],
+//! which additionally records the host, the threads, the run time and the seed
+//! and wall time of every point. `.npz` archives contain it as string `metadata`, too.
+//! The NumPy formats are written by [TableWriter::finish], all other formats row by row.
+use std::{
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    time::{Instant, SystemTime}
+};
+
+use clap::ValueEnum;
+use fs_err::File;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    error::*,
+    misc::{current_additions, write_commands_and_version, write_slice_head, BUILD_TIME_CHRONO, GIT_HASH, VERSION}
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize, JsonSchema)]
+pub enum OutputFormat{
+    #[default]
+    Dat,
+    Csv,
+    Jsonl,
+    Npy,
+    Npz
+}
+
+impl OutputFormat{
+    pub fn extension(self) -> &'static str
+    {
+        match self{
+            Self::Dat => "dat",
+            Self::Csv => "csv",
+            Self::Jsonl => "jsonl",
+            Self::Npy => "npy",
+            Self::Npz => "npz"
+        }
+    }
+
+    /// Format selected by the extension of `path`, unknown extensions are [OutputFormat::Dat]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Self
+    {
+        let ext = path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("");
+        match ext{
+            "csv" => Self::Csv,
+            "jsonl" => Self::Jsonl,
+            "npy" => Self::Npy,
+            "npz" => Self::Npz,
+            _ => Self::Dat
+        }
+    }
+}
+
+/// Seed and wall time of one point of a scan
+#[derive(Debug, Clone, Serialize)]
+pub struct PointRecord{
+    pub x: f64,
+    /// Everything needed to reproduce the point, e.g., the state of the seeding rng before the point
+    pub seed: Value,
+    /// Wall time since the previous point or the creation of the file
+    pub seconds: f64
+}
+
+/// Content of the json sidecar and of the `metadata` entry of `.npz` files
+#[derive(Debug, Clone, Serialize)]
+pub struct RunMetadata{
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub compile_time: &'static str,
+    pub host: Option<String>,
+    pub command: Vec<String>,
+    pub config: Option<Value>,
+    pub columns: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub comments: Vec<String>,
+    /// Number of threads of the rayon pool the run used, known once the run finished
+    pub threads: Option<usize>,
+    /// RFC 3339
+    pub started: String,
+    /// RFC 3339, `None` while the run is not finished
+    pub finished: Option<String>,
+    pub seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub points: Vec<PointRecord>
+}
+
+impl RunMetadata{
+    fn new(columns: Vec<String>, comments: Vec<String>) -> Result<Self>
+    {
+        let config = current_additions()
+            .map(|add| serde_json::from_str(&add))
+            .transpose()?;
+        Ok(
+            Self{
+                version: VERSION,
+                git_hash: GIT_HASH,
+                compile_time: BUILD_TIME_CHRONO,
+                host: host_name(),
+                command: std::env::args().collect(),
+                config,
+                columns,
+                comments,
+                threads: None,
+                started: now(),
+                finished: None,
+                seconds: None,
+                points: Vec::new()
+            }
+        )
+    }
+}
+
+fn now() -> String
+{
+    humantime::format_rfc3339_seconds(SystemTime::now()).to_string()
+}
+
+fn host_name() -> Option<String>
+{
+    fs_err::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|name| name.trim().to_owned())
+        .filter(|name| !name.is_empty())
+}
+
+/// Path of the json sidecar of `path`
+pub fn sidecar_path(path: &Path) -> PathBuf
+{
+    let mut name = path.as_os_str().to_owned();
+    name.push(".meta.json");
+    PathBuf::from(name)
+}
+
+enum Sink{
+    Text(BufWriter<File>),
+    /// Rows of the NumPy formats, row major
+    Buffered(Vec<f64>)
+}
+
+/// Writes a table of floats in the format selected by the file extension.
+///
+/// [TableWriter::finish] has to be called, otherwise the NumPy formats are not written
+/// and the sidecar does not contain the run time
+pub struct TableWriter{
+    path: PathBuf,
+    format: OutputFormat,
+    scientific: bool,
+    sink: Sink,
+    metadata: RunMetadata,
+    start: Instant,
+    last_point: Instant
+}
+
+impl TableWriter{
+    /// Create the file and write the header
+    pub fn create<P, S, D>(path: P, columns: S) -> Result<Self>
+    where P: AsRef<Path>,
+        S: IntoIterator<Item = D>,
+        D: ToString
+    {
+        Self::create_with_comments(path, Vec::new(), columns)
+    }
+
+    /// Like [TableWriter::create], the `comments` are written into the header
+    /// of `.dat` tables and into the metadata
+    pub fn create_with_comments<P, S, D>(path: P, comments: Vec<String>, columns: S) -> Result<Self>
+    where P: AsRef<Path>,
+        S: IntoIterator<Item = D>,
+        D: ToString
+    {
+        let path = path.as_ref().to_owned();
+        let format = OutputFormat::from_path(&path);
+        let columns: Vec<String> = columns.into_iter()
+            .map(|col| col.to_string())
+            .collect();
+        let sink = match format{
+            OutputFormat::Dat => {
+                let mut buf = BufWriter::new(File::create(&path)?);
+                write_commands_and_version(&mut buf)?;
+                for comment in comments.iter(){
+                    writeln!(buf, "# {comment}")?;
+                }
+                write_slice_head(&mut buf, &columns)?;
+                Sink::Text(buf)
+            },
+            OutputFormat::Csv | OutputFormat::Jsonl => {
+                let mut buf = BufWriter::new(File::create(&path)?);
+                if format == OutputFormat::Csv{
+                    let header: Vec<_> = columns.iter()
+                        .map(|col| csv_field(col))
+                        .collect();
+                    writeln!(buf, "{}", header.join(","))?;
+                }
+                Sink::Text(buf)
+            },
+            OutputFormat::Npy | OutputFormat::Npz => Sink::Buffered(Vec::new())
+        };
+        let now = Instant::now();
+        let table = Self{
+            path,
+            format,
+            scientific: false,
+            sink,
+            metadata: RunMetadata::new(columns, comments)?,
+            start: now,
+            last_point: now
+        };
+        table.write_sidecar()?;
+        Ok(table)
+    }
+
+    fn write_sidecar(&self) -> Result<()>
+    {
+        let writer = BufWriter::new(File::create(sidecar_path(&self.path))?);
+        serde_json::to_writer_pretty(writer, &self.metadata)?;
+        Ok(())
+    }
+
+    /// Record the seed of the point `x` and the wall time since the previous point
+    pub fn record_point<S: Serialize>(&mut self, x: f64, seed: &S) -> Result<()>
+    {
+        let now = Instant::now();
+        self.metadata.points.push(
+            PointRecord{
+                x,
+                seed: serde_json::to_value(seed)?,
+                seconds: (now - self.last_point).as_secs_f64()
+            }
+        );
+        self.last_point = now;
+        Ok(())
+    }
+
+    /// Write the values of `.dat` tables in scientific notation, e.g., `1.5e-3`
+    pub fn scientific(mut self) -> Self
+    {
+        self.scientific = true;
+        self
+    }
+
+    pub fn write_row(&mut self, row: &[f64]) -> Result<()>
+    {
+        debug_assert_eq!(row.len(), self.metadata.columns.len());
+        let buf = match &mut self.sink{
+            Sink::Buffered(values) => {
+                values.extend_from_slice(row);
+                return Ok(());
+            },
+            Sink::Text(buf) => buf
+        };
+        match self.format{
+            OutputFormat::Jsonl => {
+                let obj: serde_json::Map<String, Value> = self.metadata
+                    .columns
+                    .iter()
+                    .zip(row)
+                    .map(|(col, &val)| (col.clone(), Value::from(val)))
+                    .collect();
+                serde_json::to_writer(&mut *buf, &obj)?;
+                writeln!(buf)?;
+            },
+            _ => {
+                let separator = if self.format == OutputFormat::Csv { "," } else { " " };
+                for (i, val) in row.iter().enumerate(){
+                    if i > 0 {
+                        write!(buf, "{separator}")?;
+                    }
+                    if self.scientific && self.format == OutputFormat::Dat {
+                        write!(buf, "{val:e}")?;
+                    } else {
+                        write!(buf, "{val}")?;
+                    }
+                }
+                writeln!(buf)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush the text formats or write the NumPy formats
+    /// and complete the sidecar with the run time
+    pub fn finish(mut self) -> Result<()>
+    {
+        self.metadata.finished = Some(now());
+        self.metadata.seconds = Some(self.start.elapsed().as_secs_f64());
+        // querying the pool earlier would initialize it before the scan builds it
+        self.metadata.threads = Some(rayon::current_num_threads());
+        self.write_sidecar()?;
+
+        let values = match self.sink{
+            Sink::Text(mut buf) => {
+                buf.flush()?;
+                return Ok(());
+            },
+            Sink::Buffered(values) => values
+        };
+        let columns = &self.metadata.columns;
+        let cols = columns.len();
+        let rows = values.len().checked_div(cols).unwrap_or(0);
+        let mut buf = BufWriter::new(File::create(&self.path)?);
+        if self.format == OutputFormat::Npy {
+            buf.write_all(&npy_f64(&values, &[rows, cols]))?;
+        } else {
+            let mut zip = ZipWriter::default();
+            for (i, col) in columns.iter().enumerate(){
+                let column: Vec<f64> = values.iter()
+                    .skip(i)
+                    .step_by(cols)
+                    .copied()
+                    .collect();
+                zip.add(&format!("{col}.npy"), npy_f64(&column, &[rows]))?;
+            }
+            let metadata = serde_json::to_string_pretty(&self.metadata)?;
+            zip.add("metadata.npy", npy_str(&metadata))?;
+            buf.write_all(&zip.finish()?)?;
+        }
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_field(field: &str) -> String
+{
+    if field.contains([',', '"', '\n']){
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// `.npy` file with the given header dictionary and data
+fn npy(descr: &str, shape: &[usize], data: &[u8]) -> Vec<u8>
+{
+    let shape = match shape{
+        [n] => format!("({n},)"),
+        dims => format!("({})", dims.iter().map(usize::to_string).collect::<Vec<_>>().join(", "))
+    };
+    let mut header = format!("{{'descr': '{descr}', 'fortran_order': False, 'shape': {shape}, }}");
+    // magic, version and header length take 10 bytes, the data has to be aligned to 64 bytes
+    let padding = (64 - (10 + header.len() + 1) % 64) % 64;
+    header.extend(std::iter::repeat_n(' ', padding));
+    header.push('\n');
+
+    let mut out = Vec::with_capacity(10 + header.len() + data.len());
+    out.extend_from_slice(b"\x93NUMPY\x01\x00");
+    out.extend_from_slice(&(header.len() as u16).to_le_bytes());
+    out.extend_from_slice(header.as_bytes());
+    out.extend_from_slice(data);
+    out
+}
+
+fn npy_f64(values: &[f64], shape: &[usize]) -> Vec<u8>
+{
+    let data: Vec<u8> = values.iter()
+        .flat_map(|val| val.to_le_bytes())
+        .collect();
+    npy("<f8", shape, &data)
+}
+
+/// 0d unicode array, read it with `np.load(file)["metadata"].item()`
+fn npy_str(s: &str) -> Vec<u8>
+{
+    let mut data: Vec<u8> = s.chars()
+        .flat_map(|c| (c as u32).to_le_bytes())
+        .collect();
+    if data.is_empty(){
+        // the smallest unicode type holds one character
+        data.resize(4, 0);
+    }
+    npy(&format!("<U{}", s.chars().count().max(1)), &[], &data)
+}
+
+/// Number of rows of an `.npy` file, read from its header
+fn npy_rows(header: &[u8]) -> Option<usize>
+{
+    let header = std::str::from_utf8(header).ok()?;
+    let shape = header.split("'shape': (").nth(1)?;
+    let first = shape.split([',', ')']).next()?.trim();
+    if first.is_empty() {
+        // 0d array
+        return Some(1);
+    }
+    first.parse().ok()
+}
+
+/// Writes an uncompressed zip archive, which is all `np.load` needs
+#[derive(Default)]
+struct ZipWriter{
+    data: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16
+}
+
+impl ZipWriter{
+    const UTF8_NAMES: u16 = 1 << 11;
+    /// 1980-01-01, the earliest date of the format
+    const DATE: u16 = 0x21;
+
+    fn add(&mut self, name: &str, content: Vec<u8>) -> Result<()>
+    {
+        let too_large = || Error::Input("the npz file is too large, use npy instead".to_owned());
+        let offset = u32::try_from(self.data.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(content.len()).map_err(|_| too_large())?;
+        let crc = crc32(&content);
+        let name_len = name.len() as u16;
+
+        let mut local = Vec::new();
+        local.extend_from_slice(&0x04034b50_u32.to_le_bytes());
+        for field in [20, Self::UTF8_NAMES, 0, 0, Self::DATE]{
+            local.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size]{
+            local.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [name_len, 0]{
+            local.extend_from_slice(&field.to_le_bytes());
+        }
+
+        self.central.extend_from_slice(&0x02014b50_u32.to_le_bytes());
+        for field in [20, 20, Self::UTF8_NAMES, 0, 0, Self::DATE]{
+            self.central.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [crc, size, size]{
+            self.central.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [name_len, 0, 0, 0, 0]{
+            self.central.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [0, offset]{
+            self.central.extend_from_slice(&u32::to_le_bytes(field));
+        }
+        self.central.extend_from_slice(name.as_bytes());
+
+        self.data.extend_from_slice(&local);
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.extend_from_slice(&content);
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>>
+    {
+        let too_large = || Error::Input("the npz file is too large, use npy instead".to_owned());
+        let offset = u32::try_from(self.data.len()).map_err(|_| too_large())?;
+        let size = u32::try_from(self.central.len()).map_err(|_| too_large())?;
+        self.data.append(&mut self.central);
+        self.data.extend_from_slice(&0x06054b50_u32.to_le_bytes());
+        for field in [0, 0, self.entries, self.entries]{
+            self.data.extend_from_slice(&u16::to_le_bytes(field));
+        }
+        for field in [size, offset]{
+            self.data.extend_from_slice(&field.to_le_bytes());
+        }
+        self.data.extend_from_slice(&0_u16.to_le_bytes());
+        Ok(self.data)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32
+{
+    let mut crc = !0_u32;
+    for &byte in data{
+        crc ^= byte as u32;
+        for _ in 0..8{
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Number of rows in an output file of any format, `None` if the file does not exist.
+///
+/// The NumPy formats are only written once they are complete,
+/// so for them the number of rows is read from the header
+pub fn count_rows<P: AsRef<Path>>(path: P) -> Result<Option<usize>>
+{
+    let path = path.as_ref();
+    let mut file = match File::open(path){
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into())
+    };
+    let format = OutputFormat::from_path(path);
+    let rows = match format{
+        OutputFormat::Npy | OutputFormat::Npz => {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            // the first entry of an npz archive is the first column, the npy file starts after the local header
+            let start = if format == OutputFormat::Npz {
+                let len = |at: usize| bytes.get(at..at + 2).map_or(0, |b| u16::from_le_bytes([b[0], b[1]]) as usize);
+                30 + len(26) + len(28)
+            } else {
+                0
+            };
+            let header_len = bytes.get(start + 8..start + 10)
+                .map_or(0, |b| u16::from_le_bytes([b[0], b[1]]) as usize);
+            bytes.get(start + 10..start + 10 + header_len)
+                .and_then(npy_rows)
+                .unwrap_or(0)
+        },
+        _ => {
+            let mut rows: usize = 0;
+            for line in BufReader::new(file).lines(){
+                let line = line?;
+                if !line.is_empty() && !line.starts_with('#'){
+                    rows += 1;
+                }
+            }
+            // the header row
+            if format == OutputFormat::Csv {
+                rows.saturating_sub(1)
+            } else {
+                rows
+            }
+        }
+    };
+    Ok(Some(rows))
+}
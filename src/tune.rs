@@ -0,0 +1,286 @@
+//! Automatic choice of the bisection threshold, `max_depth` and `rough_step_size` of the efficient walkers.
+//!
+//! Every candidate of a grid is measured by a pilot run. Its bias is estimated by the difference
+//! of its mfpt to a reference and the cheapest candidate, i.e., the one with the most samples per second,
+//! whose bias is below the tolerance, even when enlarged by some standard errors, is chosen.
+use std::num::NonZeroUsize;
+
+use camino::Utf8PathBuf;
+use clap::Parser;
+use derivative::Derivative;
+use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+
+use crate::{
+    benchmark::{analytic_reference, measure, BenchmarkWalker, Measurement},
+    error::*,
+    parse::FieldOverride,
+    scan::{build_global_pool, PointSeed},
+    table::TableWriter,
+    walker::{AnyWalkerSettings, Bisect, EffWalkerSettings, RadomWalkSettings}
+};
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
+#[derivative(Default)]
+#[serde(deny_unknown_fields)]
+pub struct TuneJob{
+    /// rough_step_size and max_depth are replaced by the values of the candidates
+    #[derivative(Default(value="RadomWalkSettings{ a: 0.5, lambda_mirror: 4.0, ..Default::default() }"))]
+    pub settings: RadomWalkSettings,
+    #[derivative(Default(value="BenchmarkWalker::Eff2"))]
+    pub walker: BenchmarkWalker,
+    #[derivative(Default(value="vec![1e-2, 1e-4, 1e-6, 1e-8]"))]
+    pub thresholds: Vec<f64>,
+    #[derivative(Default(value="vec![10, 20, 30, 40]"))]
+    pub max_depths: Vec<usize>,
+    #[derivative(Default(value="vec![0.1, 0.03, 0.01]"))]
+    pub rough_step_sizes: Vec<f64>,
+    /// largest accepted bias, relative to the reference mfpt.
+    /// The standard errors of the pilot runs have to be well below it
+    #[derivative(Default(value="0.05"))]
+    pub tolerance: f64,
+    /// the bias plus this many of its standard errors has to be below the tolerance
+    #[derivative(Default(value="2.0"))]
+    pub sigmas: f64,
+    /// mfpt the candidates are compared with. If omitted, the analytic mfpt is used,
+    /// which is only known for positive a and origin 0, otherwise the finest candidate,
+    /// which is assumed to be unbiased
+    pub reference: Option<f64>,
+    /// samples of the finest candidate, if it is the reference
+    #[derivative(Default(value="NonZeroUsize::new(100000).unwrap()"))]
+    pub reference_samples: NonZeroUsize,
+    /// samples of the pilot run of every candidate
+    #[derivative(Default(value="NonZeroUsize::new(10000).unwrap()"))]
+    pub samples: NonZeroUsize,
+    /// Number of threads
+    #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
+    pub j: NonZeroUsize,
+    pub seed: u64
+}
+
+impl TuneJob{
+    fn candidate(&self, threshold: f64, max_depth: usize, rough_step_size: f64) -> EffWalkerSettings
+    {
+        let mut settings = self.settings.clone();
+        settings.max_depth = max_depth;
+        settings.rough_step_size = rough_step_size;
+        EffWalkerSettings { settings, bisection: Bisect::Threshold(threshold) }
+    }
+
+    /// All candidates, in the order threshold, depth, rough step size
+    fn candidates(&self) -> Vec<EffWalkerSettings>
+    {
+        let mut candidates = Vec::new();
+        for &threshold in self.thresholds.iter(){
+            for &max_depth in self.max_depths.iter(){
+                for &rough_step_size in self.rough_step_sizes.iter(){
+                    candidates.push(self.candidate(threshold, max_depth, rough_step_size));
+                }
+            }
+        }
+        candidates
+    }
+
+    /// The candidate with the smallest threshold, largest depth and smallest rough step size
+    fn finest(&self) -> EffWalkerSettings
+    {
+        let min = |values: &[f64]| values.iter().copied().fold(f64::INFINITY, f64::min);
+        self.candidate(
+            min(&self.thresholds),
+            self.max_depths.iter().copied().max().unwrap_or_default(),
+            min(&self.rough_step_sizes)
+        )
+    }
+
+    fn walker_settings(&self, settings: EffWalkerSettings) -> AnyWalkerSettings
+    {
+        match self.walker{
+            BenchmarkWalker::Eff => AnyWalkerSettings::Eff(settings),
+            BenchmarkWalker::Eff2 => AnyWalkerSettings::Eff2(settings)
+        }
+    }
+}
+
+impl Validate for TuneJob{
+    fn validate(&self) -> Result<()> {
+        for (name, empty) in [
+            ("thresholds", self.thresholds.is_empty()),
+            ("max_depths", self.max_depths.is_empty()),
+            ("rough_step_sizes", self.rough_step_sizes.is_empty())
+        ]{
+            if empty{
+                return Err(Error::validation(name, "at least one value is required"));
+            }
+        }
+        ensure_positive("tolerance", self.tolerance)?;
+        if !(self.sigmas >= 0.0 && self.sigmas.is_finite()){
+            return Err(Error::validation("sigmas", format!("has to be non-negative and finite, but is {}", self.sigmas)));
+        }
+        if let Some(reference) = self.reference{
+            ensure_positive("reference", reference)?;
+        }
+        for threshold in self.thresholds.iter(){
+            if !(*threshold >= 0.0 && threshold.is_finite()){
+                return Err(Error::validation("thresholds", format!("have to be non-negative and finite, but one is {threshold}")));
+            }
+        }
+        for candidate in self.candidates(){
+            candidate.settings
+                .validate()
+                .map_err(|e| e.within("settings"))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Parser, Debug)]
+pub struct TuneOpt{
+    #[arg(long, short, requires("out"))]
+    /// Path to json file. If omitted, an example is printed
+    pub json: Option<Utf8PathBuf>,
+
+    #[arg(long, short)]
+    /// Name of output file, the extension selects the format
+    pub out: Option<Utf8PathBuf>,
+
+    #[arg(long = "set", value_name = "PATH=VALUE")]
+    /// Override a field of the json file, e.g., `--set samples=1000`. Can be repeated
+    pub set: Vec<FieldOverride>
+}
+
+/// Reference mfpt, its standard error and where it comes from
+fn reference(job: &TuneJob, points: usize) -> (f64, f64, String)
+{
+    if let Some(reference) = job.reference{
+        return (reference, 0.0, "given".to_owned());
+    }
+    if let Some(analytic) = analytic_reference(&job.settings){
+        return (analytic, 0.0, "analytic".to_owned());
+    }
+    println!("measuring the reference");
+    let finest = job.finest();
+    // the point after all candidates, such that the seeds differ
+    let measurement = measure(job.walker, &finest, PointSeed::new(job.seed, points), job.reference_samples);
+    (measurement.stats.mean_fpt(), measurement.standard_error(), "finest candidate".to_owned())
+}
+
+/// Candidate that passed the pilot run
+struct Choice{
+    settings: EffWalkerSettings,
+    measurement: Measurement,
+    bias: f64,
+    bias_error: f64
+}
+
+/// Measure all candidates, choose the cheapest one whose bias is below the tolerance
+/// and write all of them, together with the choice
+pub fn tune(job: TuneJob, file_name: Utf8PathBuf) -> Result<()>
+{
+    build_global_pool(job.j)?;
+    let candidates = job.candidates();
+    let (reference, reference_error, source) = reference(&job, candidates.len());
+    let limit = job.tolerance * reference.abs();
+    println!("reference mfpt {reference} ± {reference_error} ({source})");
+
+    let mut rows = Vec::with_capacity(candidates.len());
+    let mut choice: Option<Choice> = None;
+    // smallest bias that could be told apart from the statistical errors
+    let mut resolution = f64::INFINITY;
+    for (i, settings) in candidates.into_iter().enumerate(){
+        let measurement = measure(job.walker, &settings, PointSeed::new(job.seed, i), job.samples);
+        let bias = measurement.stats.mean_fpt() - reference;
+        let bias_error = measurement.standard_error().hypot(reference_error);
+        let accepted = bias.abs() + job.sigmas * bias_error <= limit;
+        resolution = resolution.min(job.sigmas * bias_error);
+        let threshold = settings.bisection.threshold().expect("only thresholds are tuned");
+        rows.push(
+            [
+                threshold,
+                settings.settings.max_depth as f64,
+                settings.settings.rough_step_size,
+                measurement.stats.mean_fpt(),
+                measurement.standard_error(),
+                bias,
+                bias_error,
+                bias / reference,
+                measurement.samples_per_second(),
+                measurement.mean(measurement.bisections),
+                f64::from(u8::from(accepted))
+            ]
+        );
+        println!(
+            "threshold {threshold:e} depth {} rough step {}: bias {bias:.3e} ± {bias_error:.1e}, {:.0} samples/s{}",
+            settings.settings.max_depth,
+            settings.settings.rough_step_size,
+            measurement.samples_per_second(),
+            if accepted { ", accepted" } else { "" }
+        );
+        let cheaper = choice.as_ref()
+            .is_none_or(|chosen| measurement.samples_per_second() > chosen.measurement.samples_per_second());
+        if accepted && cheaper{
+            choice = Some(Choice { settings, measurement, bias, bias_error });
+        }
+    }
+
+    let mut comments = vec![
+        format!("reference: {reference} ± {reference_error} ({source})"),
+        format!("tolerance: {} relative, i.e., {limit}, sigmas: {}", job.tolerance, job.sigmas)
+    ];
+    match &choice{
+        Some(chosen) => {
+            let threshold = chosen.settings.bisection.threshold().expect("only thresholds are tuned");
+            let walk = &chosen.settings.settings;
+            comments.push(
+                format!(
+                    "chosen: threshold {threshold} max_depth {} rough_step_size {} bias {} ± {} relative bias {} samples per second {}",
+                    walk.max_depth,
+                    walk.rough_step_size,
+                    chosen.bias,
+                    chosen.bias_error,
+                    chosen.bias / reference,
+                    chosen.measurement.samples_per_second()
+                )
+            );
+            comments.push(format!("chosen settings: {}", serde_json::to_string(&job.walker_settings(chosen.settings.clone()))?));
+        },
+        None => comments.push("chosen: none, no candidate reached the tolerance".to_owned())
+    }
+
+    let mut table = TableWriter::create_with_comments(
+        &file_name,
+        comments.clone(),
+        [
+            "threshold",
+            "max_depth",
+            "rough_step_size",
+            "mfpt",
+            "standard_error",
+            "bias",
+            "bias_error",
+            "relative_bias",
+            "samples_per_second",
+            "bisections",
+            "accepted"
+        ]
+    )?;
+    for row in rows.iter(){
+        table.write_row(row)?;
+    }
+    table.finish()?;
+
+    for line in comments.iter().skip(2){
+        println!("{line}");
+    }
+    match choice{
+        Some(_) => Ok(()),
+        None => Err(
+            Error::Input(
+                format!(
+                    "no candidate has a bias below the tolerance {limit}, use finer candidates or more samples: \
+                    their standard errors alone need a relative tolerance of at least {}",
+                    resolution / reference.abs()
+                )
+            )
+        )
+    }
+}
@@ -1,10 +1,16 @@
+//! All walker implementations.
+//!
+//! Every walker implements the [Walker] trait, such that it can be used by
+//! the [scan engine](crate::scan).
 mod simple;
 mod simple_mirror;
 mod uniform_mirror;
 mod efficient_mirror;
 pub mod even_more_efficient_mirror;
+mod traits;
 
 pub use simple::*;
 pub use simple_mirror::*;
 pub use uniform_mirror::*;
-pub use efficient_mirror::*;
\ No newline at end of file
+pub use efficient_mirror::*;
+pub use traits::*;
\ No newline at end of file
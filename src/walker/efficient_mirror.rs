@@ -1,27 +1,29 @@
 use std::{
-    collections::BinaryHeap, f64::consts::SQRT_2, io::{BufRead, BufReader, BufWriter, Write}, num::*, sync::Mutex
+    collections::BinaryHeap, f64::consts::SQRT_2, io::{BufWriter, Write}, num::*
 };
 use camino::Utf8PathBuf;
 use indicatif::{ProgressIterator, ProgressStyle};
-use kahan::KahanSum;
 use ordered_float::OrderedFloat;
 use rand::{Rng, SeedableRng};
 use rand_distr::{Distribution, Exp, StandardNormal};
-use rand_pcg::{Pcg32, Pcg64, Pcg64Mcg};
+use rand_pcg::{Pcg64, Pcg64Mcg};
 use serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
 use derivative::Derivative;
-use rayon::prelude::*;
-use num_rational::Rational64;
-use num_traits::cast::ToPrimitive;
 use std::path::Path;
 
 use crate::{
-    misc::{create_buf, create_buf_with_command_and_version, write_slice_head}, parse_and_add_to_global, sync_queue::SyncQueue, BetaJob, BetaJobSub, Refine
+    dat::DatFile, misc::{create_buf, create_buf_with_command_and_version, RatioIter}, parse::{apply_overrides, parse_and_add_to_global}, scan::{build_global_pool, measure_and_write, PointSeed, ScanPart}, shard::{PointFormat, PointWriter}, BetaJob, BetaJobSub, Refine
 };
+use super::{EffWalkerSettings, Sample, WalkEvent, WalkEventKind, Walker};
+use crate::error::*;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// How the rough walk is refined
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, JsonSchema)]
 pub enum Bisect{
+    /// Bisect the intervals with the highest crossing probability this many times
     Steps(NonZeroUsize),
+    /// Bisect every interval whose crossing probability is above the threshold
     Threshold(f64)
 }
 
@@ -36,56 +38,114 @@ impl Bisect{
     }
 }
 
+impl Validate for Bisect{
+    fn validate(&self) -> Result<()> {
+        match self{
+            Self::Steps(_) => Ok(()),
+            Self::Threshold(th) => {
+                if *th >= 0.0 && th.is_finite(){
+                    Ok(())
+                } else {
+                    Err(Error::validation("Threshold", format!("has to be non-negative and finite, but is {th}")))
+                }
+            }
+        }
+    }
+}
+
+/// [EffRandWalk2](super::even_more_efficient_mirror::EffRandWalk2) can only bisect with a threshold
+pub fn ensure_threshold(bisection: &Bisect) -> Result<()>
+{
+    match bisection{
+        Bisect::Threshold(_) => Ok(()),
+        Bisect::Steps(_) => Err(Error::validation("", "only bisection with threshold allowed here"))
+    }
+}
+
 impl Default for Bisect{
     fn default() -> Self {
         Self::Threshold(1e-6)
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+/// Work, memory and refinement of the last sample of an efficient walker, see [EffRandWalk::cost]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WalkCost{
+    /// intervals of the rough walk
+    pub rough_steps: usize,
+    /// intervals that were bisected
+    pub bisections: usize,
+    /// largest memory of the stored intervals of all refinement levels
+    pub walk_bytes: usize,
+    /// largest memory of the queue of intervals that still have to be bisected
+    pub queue_bytes: usize,
+    /// refinement level of the interval in which the target was hit, 0 is the rough walk
+    pub hit_depth: usize,
+    /// the hitting interval is on the deepest level, i.e., `max_depth` limited its refinement
+    pub depth_limited: bool,
+    /// Sum of the crossing probabilities of the intervals before the hit that were only
+    /// not refined because of `max_depth`, i.e., the expected number of missed crossings.
+    /// The walk is self-similar, so it does not vanish for deeper refinements,
+    /// but the missed crossings are closer to the hit
+    pub missed_crossings: f64
+}
+
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
 #[derivative(Default)]
+#[serde(deny_unknown_fields)]
 pub struct MeasureMfptOpt
 {
-    settimgs: RadomWalkSettings,
+    #[serde(alias = "settings")]
+    pub settimgs: RadomWalkSettings,
     #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
-    samples_per_point: NonZeroUsize,
+    pub samples_per_point: NonZeroUsize,
     #[derivative(Default(value="0.1"))]
-    lambda_left: f64,
+    pub lambda_left: f64,
     #[derivative(Default(value="5.0"))]
-    lambda_right: f64,
+    pub lambda_right: f64,
     #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
-    lambda_samples: NonZeroUsize,
+    pub lambda_samples: NonZeroUsize,
     /// Number of threads. 
     #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
-    j: NonZeroUsize,
-    seed: u64,
-    bisection: Bisect
+    pub j: NonZeroUsize,
+    pub seed: u64,
+    pub bisection: Bisect,
+    /// Also write the refinement of the bisection, see [crate::scan::BisectionStats]. Not possible for shards
+    #[serde(default)]
+    pub diagnostics: bool
 }
 
-#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
 #[derivative(Default)]
+#[serde(deny_unknown_fields)]
 pub struct MeasureMfptLOpt
 {
-    settimgs: RadomWalkSettings,
+    #[serde(alias = "settings")]
+    pub settimgs: RadomWalkSettings,
     #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
-    samples_per_point: NonZeroUsize,
+    pub samples_per_point: NonZeroUsize,
     #[derivative(Default(value="0.1"))]
-    target_left: f64,
+    pub target_left: f64,
     #[derivative(Default(value="5.0"))]
-    target_right: f64,
+    pub target_right: f64,
     #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
-    target_samples: NonZeroUsize,
+    pub target_samples: NonZeroUsize,
     /// Number of threads. 
     #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
-    j: NonZeroUsize,
-    seed: u64,
-    bisection: Bisect
+    pub j: NonZeroUsize,
+    pub seed: u64,
+    pub bisection: Bisect,
+    /// Also write the refinement of the bisection, see [crate::scan::BisectionStats]. Not possible for shards
+    #[serde(default)]
+    pub diagnostics: bool
 }
 
-#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
 #[derivative(Default)]
+#[serde(deny_unknown_fields)]
 pub struct MeasureMfptBetaOpt
 {
+    #[serde(alias = "settings")]
     pub settimgs: RadomWalkSettings,
     #[derivative(Default(value="NonZeroUsize::new(100).unwrap()"))]
     pub samples_per_point: NonZeroUsize,
@@ -99,282 +159,213 @@ pub struct MeasureMfptBetaOpt
     #[derivative(Default(value="NonZeroUsize::new(1).unwrap()"))]
     pub j: NonZeroUsize,
     pub seed: u64,
-    pub bisection: Bisect
+    pub bisection: Bisect,
+    /// Also write the refinement of the bisection, see [crate::scan::BisectionStats]. Not possible for shards
+    #[serde(default)]
+    pub diagnostics: bool
+}
+
+/// Validate the settings for the largest rate and the smallest target of a scan
+fn validate_scan_settings(
+    settings: &RadomWalkSettings,
+    max_lambda: f64,
+    min_target: f64
+) -> Result<()>
+{
+    let mut settings = settings.clone();
+    settings.lambda_mirror = max_lambda;
+    settings.target = min_target;
+    settings.validate().map_err(|e| e.within("settimgs"))
+}
+
+fn validate_range(name: &str, left: f64, right: f64, samples: NonZeroUsize) -> Result<()>
+{
+    ensure_finite(&format!("{name}_left"), left)?;
+    ensure_finite(&format!("{name}_right"), right)?;
+    ensure_range_samples(&format!("{name}_samples"), samples.get())
+}
+
+impl Validate for MeasureMfptOpt{
+    fn validate(&self) -> Result<()> {
+        validate_range("lambda", self.lambda_left, self.lambda_right, self.lambda_samples)?;
+        ensure_positive("lambda_left", self.lambda_left)?;
+        ensure_positive("lambda_right", self.lambda_right)?;
+        validate_scan_settings(
+            &self.settimgs, 
+            self.lambda_left.max(self.lambda_right), 
+            self.settimgs.target
+        )?;
+        self.bisection.validate().map_err(|e| e.within("bisection"))
+    }
+}
+
+impl Validate for MeasureMfptLOpt{
+    fn validate(&self) -> Result<()> {
+        validate_range("target", self.target_left, self.target_right, self.target_samples)?;
+        validate_scan_settings(
+            &self.settimgs, 
+            self.settimgs.lambda_mirror, 
+            self.target_left.min(self.target_right)
+        )?;
+        self.bisection.validate().map_err(|e| e.within("bisection"))
+    }
+}
+
+impl Validate for MeasureMfptBetaOpt{
+    fn validate(&self) -> Result<()> {
+        validate_range("beta", self.beta_left, self.beta_right, self.beta_samples)?;
+        ensure_positive("beta_left", self.beta_left.abs())?;
+        ensure_positive("beta_right", self.beta_right.abs())?;
+        let max_beta = self.beta_left.abs().max(self.beta_right.abs()) / self.settimgs.target;
+        validate_scan_settings(
+            &self.settimgs, 
+            max_beta * max_beta, 
+            self.settimgs.target
+        )?;
+        self.bisection.validate().map_err(|e| e.within("bisection"))
+    }
+}
+
+impl MeasureMfptOpt{
+    /// Lambda and the walker settings of point `i` of the scan
+    pub fn point(&self, i: usize) -> (f64, EffWalkerSettings)
+    {
+        let delta = (self.lambda_right - self.lambda_left) / (self.lambda_samples.get() - 1) as f64;
+        let lambda = delta.mul_add(i as f64, self.lambda_left);
+        let mut settings = self.settimgs.clone();
+        settings.lambda_mirror = lambda;
+        (lambda, EffWalkerSettings { settings, bisection: self.bisection })
+    }
+}
+
+impl MeasureMfptLOpt{
+    /// Target and the walker settings of point `i` of the scan
+    pub fn point(&self, i: usize) -> (f64, EffWalkerSettings)
+    {
+        let delta = (self.target_right - self.target_left) / (self.target_samples.get() - 1) as f64;
+        let target = delta.mul_add(i as f64, self.target_left);
+        let mut settings = self.settimgs.clone();
+        settings.target = target;
+        (target, EffWalkerSettings { settings, bisection: self.bisection })
+    }
+}
+
+impl MeasureMfptBetaOpt{
+    /// Beta and the walker settings of point `i` of the scan
+    pub fn point(&self, i: usize) -> (f64, EffWalkerSettings)
+    {
+        let delta = (self.beta_right - self.beta_left) / (self.beta_samples.get() - 1) as f64;
+        let beta = delta.mul_add(i as f64, self.beta_left);
+        let b2 = beta / self.settimgs.target;
+        let mut settings = self.settimgs.clone();
+        settings.lambda_mirror = b2 * b2;
+        (beta, EffWalkerSettings { settings, bisection: self.bisection })
+    }
 }
 
 pub fn eff_measure_mfpt_beta(
     opt: MeasureMfptBetaOpt,
-    file_name: Utf8PathBuf
-)
+    file_name: Utf8PathBuf,
+    part: ScanPart
+) -> Result<()>
 {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(opt.j.get())
-        .build_global()
-        .unwrap();
-
-    let delta = (opt.beta_right - opt.beta_left) / (opt.beta_samples.get() - 1) as f64;
+    build_global_pool(opt.j)?;
 
-    let mut seeding_rng = Pcg32::seed_from_u64(opt.seed);
-    let samples_per_packet = (opt.samples_per_point.get() / (opt.j.get() * 12)).max(1);
+    let points = part.points(opt.beta_samples.get())?;
 
-    let header = [
-        "β",
-        "mfpt"
-    ];
-    let mut buf = create_buf_with_command_and_version(file_name);
-    write_slice_head(&mut buf, header).unwrap();
-    let mut settings = opt.settimgs.clone();
+    let mut writer = PointWriter::create_with_diagnostics(
+        file_name,
+        PointFormat::Mfpt{ x: "β".to_owned() },
+        part.shard,
+        opt.diagnostics
+    )?;
 
     let style = ProgressStyle::default_bar()
         .template("{msg} [{elapsed_precise} - {eta_precise}] {wide_bar}")
         .unwrap();
-    let threshold = opt.bisection.threshold();
     
-    for i in (0..opt.beta_samples.get()).progress_with_style(style)
+    for i in points.progress_with_style(style)
     {
-        let beta = delta.mul_add(i as f64, opt.beta_left);
-        let b2 = beta / settings.target;
-        settings.lambda_mirror = b2 * b2;
-        let queue = SyncQueue::create_work_queue(
-            opt.samples_per_point.get(), 
-            NonZeroUsize::new(opt.j.get() * 3).unwrap()
-        );
-        let queue = queue.map(
-            |amount|
-            {
-                let rng = Pcg64::from_rng(&mut seeding_rng).unwrap();
-                let walk = EffRandWalk::new(
-                    settings.clone(), 
-                    rng,
-                    threshold
-                );
-                (walk, amount)
-            }
-        );
-        let global_sum_fpt = Mutex::new(KahanSum::new());
-        (0..opt.j.get())
-            .into_par_iter()
-            .for_each(
-                |_|
-                {
-                    let mut sum_fpt = KahanSum::new();
-                    while let Some((mut walker, amount)) = queue.pop() {
-                        let work = amount.min(samples_per_packet);
-                        let left = amount - work;
-
-                        for _ in 0..work{
-                            walker.bisect(opt.bisection);
-                            let (i,j) = walker.delta_fpt;
-                            let delta = &walker.walk[i][j];
-                            let fpt = delta.interpolate(walker.settings.target);
-                            sum_fpt += fpt;
-                            walker.recycle(threshold);
-                        }
-                        
-                        if left > 0{
-                            queue.push(
-                                (walker, left)
-                            );
-                        }
-                    }
-                    let mut lock = global_sum_fpt
-                        .lock()
-                        .unwrap();
-                    *lock += sum_fpt;
-                    drop(lock);
-                }
-            );
-        let mfpt = global_sum_fpt.into_inner().unwrap().sum() / opt.samples_per_point.get() as f64;
-        writeln!(
-            buf,
-            "{beta} {mfpt}"
-        ).unwrap();
+        let (beta, settings) = opt.point(i);
+        measure_and_write::<EffRandWalk<Pcg64>>(
+            &mut writer,
+            beta,
+            &settings,
+            PointSeed::new(opt.seed, i),
+            opt.samples_per_point
+        )?;
     }
-    
-    
+    writer.finish()
 }
 
 pub fn eff_measure_mfpt_lambda(
     opt: MeasureMfptOpt,
-    file_name: Utf8PathBuf
-)
+    file_name: Utf8PathBuf,
+    part: ScanPart
+) -> Result<()>
 {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(opt.j.get())
-        .build_global()
-        .unwrap();
+    build_global_pool(opt.j)?;
 
-    let delta = (opt.lambda_right - opt.lambda_left) / (opt.lambda_samples.get() - 1) as f64;
+    let points = part.points(opt.lambda_samples.get())?;
 
-    let mut seeding_rng = Pcg32::seed_from_u64(opt.seed);
-    let samples_per_packet = (opt.samples_per_point.get() / (opt.j.get() * 12)).max(1);
-
-    let header = [
-        "lambda",
-        "mfpt"
-    ];
-    let mut buf = create_buf_with_command_and_version(file_name);
-    write_slice_head(&mut buf, header).unwrap();
-    let mut settings = opt.settimgs.clone();
+    let mut writer = PointWriter::create_with_diagnostics(
+        file_name,
+        PointFormat::Mfpt{ x: "lambda".to_owned() },
+        part.shard,
+        opt.diagnostics
+    )?;
 
     let style = ProgressStyle::default_bar()
         .template("{msg} [{elapsed_precise} - {eta_precise}] {wide_bar}")
         .unwrap();
-
-    let threshold = opt.bisection.threshold();
     
-    for i in (0..opt.lambda_samples.get()).progress_with_style(style)
+    for i in points.progress_with_style(style)
     {
-        let lambda = delta.mul_add(i as f64, opt.lambda_left);
-        settings.lambda_mirror = lambda;
-        let queue = SyncQueue::create_work_queue(
-            opt.samples_per_point.get(), 
-            NonZeroUsize::new(opt.j.get() * 3).unwrap()
-        );
-        let queue = queue.map(
-            |amount|
-            {
-                let rng = Pcg64::from_rng(&mut seeding_rng).unwrap();
-                let walk = EffRandWalk::new(
-                    settings.clone(), 
-                    rng,
-                    threshold
-                );
-                (walk, amount)
-            }
-        );
-        let global_sum_fpt = Mutex::new(KahanSum::new());
-        (0..opt.j.get())
-            .into_par_iter()
-            .for_each(
-                |_|
-                {
-                    let mut sum_fpt = KahanSum::new();
-                    while let Some((mut walker, amount)) = queue.pop() {
-                        let work = amount.min(samples_per_packet);
-                        let left = amount - work;
-
-                        for _ in 0..work{
-                            walker.bisect(opt.bisection);
-                            let (i,j) = walker.delta_fpt;
-                            let delta = &walker.walk[i][j];
-                            let fpt = delta.interpolate(walker.settings.target);
-                            sum_fpt += fpt;
-                            walker.recycle(threshold);
-                        }
-                        
-                        if left > 0{
-                            queue.push(
-                                (walker, left)
-                            );
-                        }
-                    }
-                    let mut lock = global_sum_fpt
-                        .lock()
-                        .unwrap();
-                    *lock += sum_fpt;
-                    drop(lock);
-                }
-            );
-        let mfpt = global_sum_fpt.into_inner().unwrap().sum() / opt.samples_per_point.get() as f64;
-        writeln!(
-            buf,
-            "{lambda} {mfpt}"
-        ).unwrap();
+        let (lambda, settings) = opt.point(i);
+        measure_and_write::<EffRandWalk<Pcg64>>(
+            &mut writer,
+            lambda,
+            &settings,
+            PointSeed::new(opt.seed, i),
+            opt.samples_per_point
+        )?;
     }
-    
-    
+    writer.finish()
 }
 
 pub fn eff_measure_mfpt_target(
     opt: MeasureMfptLOpt,
-    file_name: Utf8PathBuf
-)
+    file_name: Utf8PathBuf,
+    part: ScanPart
+) -> Result<()>
 {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(opt.j.get())
-        .build_global()
-        .unwrap();
+    build_global_pool(opt.j)?;
 
-    let delta = (opt.target_right - opt.target_left) / (opt.target_samples.get() - 1) as f64;
+    let points = part.points(opt.target_samples.get())?;
 
-    let mut seeding_rng = Pcg32::seed_from_u64(opt.seed);
-    let samples_per_packet = (opt.samples_per_point.get() / (opt.j.get() * 12)).max(1);
-
-    let header = [
-        "L",
-        "mfpt"
-    ];
-    let mut buf = create_buf_with_command_and_version(file_name);
-    write_slice_head(&mut buf, header).unwrap();
-    let mut settings = opt.settimgs.clone();
+    let mut writer = PointWriter::create_with_diagnostics(
+        file_name,
+        PointFormat::Mfpt{ x: "L".to_owned() },
+        part.shard,
+        opt.diagnostics
+    )?;
 
     let style = ProgressStyle::default_bar()
         .template("{msg} [{elapsed_precise} - {eta_precise}] {wide_bar}")
         .unwrap();
-    let threshold = opt.bisection.threshold();
     
-    for i in (0..opt.target_samples.get()).progress_with_style(style)
+    for i in points.progress_with_style(style)
     {
-        let target = delta.mul_add(i as f64, opt.target_left);
-        settings.target = target;
-        let queue = SyncQueue::create_work_queue(
-            opt.samples_per_point.get(), 
-            NonZeroUsize::new(opt.j.get() * 3).unwrap()
-        );
-        let queue = queue.map(
-            |amount|
-            {
-                let rng = Pcg64::from_rng(&mut seeding_rng).unwrap();
-                let walk = EffRandWalk::new(
-                    settings.clone(), 
-                    rng,
-                    threshold
-                );
-                (walk, amount)
-            }
-        );
-        let global_sum_fpt = Mutex::new(KahanSum::new());
-        (0..opt.j.get())
-            .into_par_iter()
-            .for_each(
-                |_|
-                {
-                    let mut sum_fpt = KahanSum::new();
-                    while let Some((mut walker, amount)) = queue.pop() {
-                        let work = amount.min(samples_per_packet);
-                        let left = amount - work;
-
-                        for _ in 0..work{
-                            walker.bisect(opt.bisection);
-                            let (i,j) = walker.delta_fpt;
-                            let delta = &walker.walk[i][j];
-                            let fpt = delta.interpolate(walker.settings.target);
-                            sum_fpt += fpt;
-                            walker.recycle(threshold);
-                        }
-                        
-                        if left > 0{
-                            queue.push(
-                                (walker, left)
-                            );
-                        }
-                    }
-                    let mut lock = global_sum_fpt
-                        .lock()
-                        .unwrap();
-                    *lock += sum_fpt;
-                    drop(lock);
-                }
-            );
-        let mfpt = global_sum_fpt.into_inner().unwrap().sum() / opt.samples_per_point.get() as f64;
-        writeln!(
-            buf,
-            "{target} {mfpt}"
-        ).unwrap();
+        let (target, settings) = opt.point(i);
+        measure_and_write::<EffRandWalk<Pcg64>>(
+            &mut writer,
+            target,
+            &settings,
+            PointSeed::new(opt.seed, i),
+            opt.samples_per_point
+        )?;
     }
-    
-    
+    writer.finish()
 }
 
 
@@ -392,11 +383,9 @@ pub fn test_eff_rand_walker()
     let mut walker = EffRandWalk::new(
         settings,
         rng,
-        None
+        Bisect::Steps(NonZeroUsize::new(100000).unwrap())
     );
-    for _ in 0..100000{
-        walker.bisection_step();
-    }
+    walker.bisect();
     for (idx, walk) in walker.walk.iter_mut().enumerate(){
         let name = format!("walker{idx}");
         let mut buf = create_buf_with_command_and_version(name);
@@ -415,6 +404,9 @@ pub struct NextItem{
     idx: usize
 }
 
+/// Walker that creates a rough walk and afterwards refines the intervals
+/// where the target might have been crossed via brownian bridges.
+/// All refinement levels are kept in memory
 #[derive(Debug)]
 pub struct EffRandWalk<R>
 {
@@ -424,9 +416,18 @@ pub struct EffRandWalk<R>
     prob_queue_stack: Vec<NextItem>,
     fpt: f64,
     delta_fpt: (usize, usize),
+    /// Mirror events of the rough walk
+    mirrors: Vec<WalkEvent>,
+    /// Number of mirror events before the first passage of the last sample
+    mirrors_before: usize,
     seeding_rng: R,
     rng: R,
-    settings: RadomWalkSettings
+    settings: RadomWalkSettings,
+    bisection: Bisect,
+    /// Intervals bisected for the last sample
+    bisections: usize,
+    /// Largest length of the heap or stack queue during the last sample
+    peak_queue: usize
 }
 
 #[derive(Debug)]
@@ -457,30 +458,54 @@ impl Ord for NextProb{
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, Derivative, Clone)]
+/// Settings of the efficient walkers
+#[derive(Debug, Serialize, Deserialize, Derivative, Clone, JsonSchema)]
 #[derivative(Default)]
+#[serde(deny_unknown_fields)]
 pub struct RadomWalkSettings{
+    /// rate of the mirror events
     #[derivative(Default(value="0.1"))]
     pub lambda_mirror: f64,
+    /// step size of the initial (rough) walk
     #[derivative(Default(value="1.0"))]
     pub rough_step_size: f64,
+    /// position of the target
     #[derivative(Default(value="1.0"))]
     pub target: f64,
+    /// at a mirror event the position x is mapped to a*x
     pub a: f64,
+    /// maximum number of refinement levels, including the rough walk
     #[derivative(Default(value="40"))]
     pub max_depth: usize,
+    /// starting position of the walker
     #[derivative(Default(value="0.0"))]
     pub origin: f64
 }
 
+impl Validate for RadomWalkSettings{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("lambda_mirror", self.lambda_mirror)?;
+        ensure_finite("target", self.target)?;
+        ensure_finite("a", self.a)?;
+        ensure_finite("origin", self.origin)?;
+        ensure_below_target("origin", self.origin, self.target)?;
+        if self.max_depth == 0{
+            return Err(Error::validation("max_depth", "has to be at least 1"));
+        }
+        Ok(())
+    }
+}
+
 fn create_initial_walk<R>(
     settings: &RadomWalkSettings,
     mut rng: R,
-    walk: &mut Vec<Delta>
+    walk: &mut Vec<Delta>,
+    mirrors: &mut Vec<WalkEvent>
 ) -> (f64, (usize, usize))
 where R: Rng
 {
     walk.clear();
+    mirrors.clear();
     let mirror_dist = Exp::new(settings.lambda_mirror)
         .unwrap();
     let mut next_mirror_time = mirror_dist.sample(&mut rng);
@@ -508,8 +533,10 @@ where R: Rng
             walk.push(delta);
             if contained
             {
-                // TODO in here I could linerarly interpolate to get a more accurate result
-                let fpt = settings.rough_step_size.mul_add((i + 1) as f64, current_time);
+                // the end of the hitting interval, the fpt itself is interpolated by the sample.
+                // It has to be computed like the end of the interval, as the bisection skips
+                // all intervals that end after it, otherwise rounding can skip the hitting interval
+                let fpt = delta.left_time + delta.delta_t;
                 delta_fpt.1 = walk.len() - 1;
                 return (fpt, delta_fpt);
             }
@@ -529,12 +556,21 @@ where R: Rng
         walk.push(delta);
         if (left_pos..=current_pos).contains(&settings.target)
         {
-            // TODO in here I could linerarly interpolate to get a more accurate result
+            // the end of the hitting interval, see above
             let fpt = current_time;
             delta_fpt.1 = walk.len() - 1;
             return (fpt, delta_fpt);
         }
+        let before = current_pos;
         current_pos *= settings.a;
+        mirrors.push(
+            WalkEvent{
+                time: current_time,
+                kind: WalkEventKind::Mirror,
+                before,
+                after: current_pos
+            }
+        );
         next_mirror_time = mirror_dist.sample(&mut rng);
     }
 }
@@ -601,15 +637,18 @@ where R: Rng + SeedableRng
     pub fn new(
         settings: RadomWalkSettings,
         mut rng: R,
-        threshold: Option<f64>
+        bisection: Bisect
     ) -> Self
     {
+        let threshold = bisection.threshold();
         let mut initial_walk = Vec::with_capacity(1024*1024);
         let mut walker_rng = R::from_rng(&mut rng).unwrap();
+        let mut mirrors = Vec::new();
         let (fpt, delta_fpt) = create_initial_walk(
             &settings, 
             &mut walker_rng,
-            &mut initial_walk
+            &mut initial_walk,
+            &mut mirrors
         );
         let mut heap = BinaryHeap::new();
         let mut stack_queue = Vec::new();
@@ -618,6 +657,7 @@ where R: Rng + SeedableRng
             Some(th) => calc_stack(settings.target, &initial_walk, &mut stack_queue, th)
         }
         
+        let peak_queue = heap.len().max(stack_queue.len());
         let mut walk = vec![initial_walk];
         walk.extend(
             (1..settings.max_depth).map(|_| Vec::new())
@@ -630,13 +670,19 @@ where R: Rng + SeedableRng
             settings,
             rng: walker_rng,
             delta_fpt,
-            prob_queue_stack: stack_queue
+            mirrors,
+            mirrors_before: 0,
+            prob_queue_stack: stack_queue,
+            bisection,
+            bisections: 0,
+            peak_queue
         }
     }
 
     
-    pub fn recycle(&mut self, threshold: Option<f64>)
+    pub fn recycle(&mut self)
     {
+        let threshold = self.bisection.threshold();
         self.walk[1..]
             .iter_mut()
             .for_each(|walk| walk.clear());
@@ -644,7 +690,8 @@ where R: Rng + SeedableRng
         let (fpt, delta_fpt) = create_initial_walk(
             &self.settings, 
             &mut self.rng, 
-            &mut self.walk[0]
+            &mut self.walk[0],
+            &mut self.mirrors
         );
         self.fpt = fpt;
         self.delta_fpt = delta_fpt;
@@ -655,6 +702,7 @@ where R: Rng + SeedableRng
                     &self.walk[0], 
                     &mut self.prob
                 );
+                self.peak_queue = self.prob.len();
             },
             Some(th) => {
                 calc_stack(
@@ -663,14 +711,15 @@ where R: Rng + SeedableRng
                     &mut self.prob_queue_stack, 
                     th
                 );
+                self.peak_queue = self.prob_queue_stack.len();
             }
         }
-        
+        self.bisections = 0;
     }
 
-    fn bisect(&mut self, bisection: Bisect)
+    fn bisect(&mut self)
     {
-        match bisection{
+        match self.bisection{
             Bisect::Steps(s) => {
                 for _ in 0..s.get()
                 {
@@ -716,9 +765,11 @@ where R: Rng + SeedableRng
                 self.prob.push(
                     NextProb { which_vec: next_vec_id, index: idx + 1, prob: OrderedFloat(prob_right) }
                 );
+                self.peak_queue = self.peak_queue.max(self.prob.len());
             }
             walk.push(left);
             walk.push(right);
+            self.bisections += 1;
             
             break;
         }
@@ -761,15 +812,162 @@ where R: Rng + SeedableRng
                         NextItem { which: next_vec_id, idx }
                     );
                 }
-
+                self.peak_queue = self.peak_queue.max(self.prob_queue_stack.len());
             }
             walk.push(left);
             walk.push(right);
+            self.bisections += 1;
             
         }
     }
 }
 
+/// Kind of a [TrajectoryEvent]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind{
+    /// Start of an interval
+    Position = 0,
+    /// Mirror event of the rough walk, the position is the one before the mirroring
+    Mirror = 1,
+    /// The target is reached
+    Hit = 2
+}
+
+/// Point of the trajectory of a single sample, see [EffRandWalk::trajectory]
+#[derive(Debug, Clone, Copy)]
+pub struct TrajectoryEvent{
+    pub time: f64,
+    pub position: f64,
+    /// Refinement level, 0 is the rough walk
+    pub level: usize,
+    pub kind: EventKind
+}
+
+impl<R> EffRandWalk<R>
+{
+    /// All events of the last sample, sorted by time: the start of every interval
+    /// of the rough walk and of all bisected refinements, the mirror events and the first passage
+    pub fn trajectory(&self) -> Vec<TrajectoryEvent>
+    {
+        let mut events: Vec<_> = self.mirrors
+            .iter()
+            .map(
+                |mirror| TrajectoryEvent{
+                    time: mirror.time,
+                    position: mirror.before,
+                    level: 0,
+                    kind: EventKind::Mirror
+                }
+            ).collect();
+
+        let positions = self.walk
+            .iter()
+            .enumerate()
+            .flat_map(
+                |(level, walk)| walk.iter()
+                    .map(
+                        move |delta| TrajectoryEvent{
+                            time: delta.left_time,
+                            position: delta.left_pos,
+                            level,
+                            kind: EventKind::Position
+                        }
+                    )
+            );
+        events.extend(positions);
+
+        let (level, idx) = self.delta_fpt;
+        events.push(
+            TrajectoryEvent{
+                time: self.walk[level][idx].interpolate(self.settings.target),
+                position: self.settings.target,
+                level,
+                kind: EventKind::Hit
+            }
+        );
+        // stable, such that a mirror event comes before the interval starting at the same time
+        events.sort_by(|a, b| a.time.total_cmp(&b.time).then(a.level.cmp(&b.level)));
+        events
+    }
+
+    /// Work, memory and refinement of the last sample. All refinement levels are kept,
+    /// so the stored intervals only grow while a sample is drawn
+    /// and the missed crossings can be summed afterwards
+    pub fn cost(&self) -> WalkCost
+    {
+        let intervals: usize = self.walk
+            .iter()
+            .map(Vec::len)
+            .sum();
+        let entry = match self.bisection{
+            Bisect::Steps(_) => std::mem::size_of::<NextProb>(),
+            Bisect::Threshold(_) => std::mem::size_of::<NextItem>()
+        };
+        let (hit_depth, idx) = self.delta_fpt;
+        let deepest = self.walk.len() - 1;
+        let hit_start = self.walk[hit_depth][idx].left_time;
+        let threshold = self.bisection.threshold().unwrap_or(0.0);
+        let missed_crossings = self.walk[deepest]
+            .iter()
+            .filter(|delta| delta.left_time + delta.delta_t <= hit_start)
+            .map(|delta| delta.calc_prob(self.settings.target))
+            .filter(|prob| *prob > threshold)
+            .map(|prob| prob.min(1.0))
+            .sum();
+        WalkCost{
+            rough_steps: self.walk[0].len(),
+            bisections: self.bisections,
+            walk_bytes: intervals * std::mem::size_of::<Delta>(),
+            queue_bytes: self.peak_queue * entry,
+            hit_depth,
+            depth_limited: hit_depth == deepest,
+            missed_crossings
+        }
+    }
+}
+
+impl Walker for EffRandWalk<Pcg64>
+{
+    type Settings = EffWalkerSettings;
+
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self
+    {
+        Self::new(
+            settings.settings.clone(), 
+            rng, 
+            settings.bisection
+        )
+    }
+
+    fn sample(&mut self) -> Sample
+    {
+        self.bisect();
+        let (i, j) = self.delta_fpt;
+        let hit = &self.walk[i][j];
+        let fpt = hit.interpolate(self.settings.target);
+        // a mirror event at the start of the interval happened before the walker crossed the target
+        self.mirrors_before = self.mirrors.partition_point(|mirror| mirror.time <= hit.left_time);
+        Sample { fpt, mirrors: self.mirrors_before as u64, ..Default::default() }
+    }
+
+    fn recycle(&mut self)
+    {
+        EffRandWalk::recycle(self)
+    }
+
+    /// Nothing to do, the mirror events are always recorded, as they are counted
+    fn record_events(&mut self) {}
+
+    fn events(&self) -> &[WalkEvent] {
+        &self.mirrors[..self.mirrors_before]
+    }
+
+    fn cost(&self) -> Option<WalkCost> {
+        Some(EffRandWalk::cost(self))
+    }
+}
+
+/// Interval of a walk, i.e., the positions at the beginning and end of a time interval
 #[derive(Debug, Clone, Copy)]
 pub struct Delta{
     pub left_pos: f64,
@@ -780,6 +978,7 @@ pub struct Delta{
 
 impl Delta{
 
+    /// Time at which the linear interpolation of the interval reaches the target
     pub fn interpolate(&self, target: f64) -> f64
     {
         if self.left_pos == target{
@@ -790,6 +989,7 @@ impl Delta{
         self.left_time + frac * self.delta_t
     }
 
+    /// Probability that a brownian bridge in this interval crosses the target:
     /// $$
     ///     \exp[- (2L-x_1-x_2)^2/{4Dt}]/\exp[- (x_2-x_1)^2/{4Dt}/]= \exp[- (L-x_1)(L-x_2)/{Dt}]
     /// $$
@@ -807,7 +1007,8 @@ impl Delta{
         (self.left_pos..=self.right_pos).contains(target)
     }
 
-    // Uses a brownian bridge
+    /// Split the interval into two halves. 
+    /// The midpoint is drawn from the corresponding brownian bridge
     #[inline]
     pub fn bisect<R: Rng>(
         &self, 
@@ -841,100 +1042,58 @@ impl Delta{
     }
 }
 
-pub struct RatioRange{
-    start: Rational64,
-    end: Rational64,
-    // number of samples minus 1
-    num_samples_m1: NonZeroI64
-}
-
-impl RatioRange{
-    pub fn float_iter(&self) -> impl Iterator<Item=f64>
-    {
-        self.ratio_iter()
-            .map(|r| r.to_f64().unwrap())
-    }
-
-    pub fn ratio_iter(&self) -> impl Iterator<Item=Rational64>
-    {
-        let delta = (self.end - self.start) / self.num_samples_m1.get();
-        let start = self.start;
-        (0..=self.num_samples_m1.get())
-            .map(
-                move |i| 
-                {
-                    start + delta * i
-                }
-            )
-    }
-}
-
-pub fn job_creator(opt: BetaJob)
+pub fn job_creator(opt: BetaJob) -> Result<()>
 {
 
     match opt.command{
         BetaJobSub::A(a) => {
-            let mut json: MeasureMfptBetaOpt = parse_and_add_to_global(a.json);
-            let start = Rational64::approximate_float(a.start).unwrap();
-            let end = Rational64::approximate_float(a.end).unwrap();
-            let num_samples_m1 = NonZeroI64::new(a.steps.get() - 1).unwrap();
-            let ratio = RatioRange{
-                start,
-                end,
-                num_samples_m1
-            };
+            let mut json: MeasureMfptBetaOpt = parse_and_add_to_global(a.json, &a.set)?;
+            let ratio = RatioIter::get_ratio_iter(a.start, a.end, a.steps.get())?;
 
             for a in ratio.float_iter(){
                 let name = format!("a{}.json", a);
                 let json_writer = create_buf(name);
                 json.settimgs.a = a;
-                serde_json::to_writer_pretty(json_writer, &json).unwrap();
+                serde_json::to_writer_pretty(json_writer, &json)?;
             }
         },
         BetaJobSub::Refine(refine_opt) => {
             let iter = glob::glob(&refine_opt.glob)
-                .unwrap()
-                .map(Result::unwrap);
+                .map_err(|e| Error::Input(format!("invalid glob: {e}")))?;
             for path in iter
             {
-                refine(&path, &refine_opt);
+                let path = path.map_err(|e| Error::Io(e.into_error()))?;
+                refine(&path, &refine_opt)?;
             }
         }
     }
+    Ok(())
 }
 
-fn refine(path: &Path, refine: &Refine)
+fn refine(path: &Path, refine: &Refine) -> Result<()>
 {
     println!("Refining {:?}", path);
-    let reader = fs_err::File::open(path).unwrap();
-    let buf = BufReader::new(reader);
-    let mut lines = buf.lines().skip(2).map(Result::unwrap);
-    let json_line = lines.next().unwrap();
-    let json = &json_line[2..];
-
-    let mut opt: MeasureMfptBetaOpt = match serde_json::from_str(json)
-    {
-        Ok(o) => o,
-        Err(e) => {
-            dbg!(e);
-            panic!("json parsing error!")
-        }
-    };
-
+    let file = DatFile::read(path)?;
+    let mut json = file.header
+        .config
+        .clone()
+        .ok_or_else(|| Error::Input(format!("{path:?} contains no json configuration")))?;
+    apply_overrides(&mut json, &refine.overrides())?;
+    let mut opt: MeasureMfptBetaOpt = serde_json::from_value(json)
+        .map_err(|source| Error::Json { path: Some(path.to_owned()), source })?;
 
     // tuple is: (beta, mfpt)
-    let vals: Vec<(f64, f64)> = lines.filter(|line| !line.starts_with('#'))
+    let vals: Vec<(f64, f64)> = file.rows
+        .iter()
         .map(
-            |line| 
-            {
-                let mut iter = line.split_ascii_whitespace();
-                let beta = iter.next().unwrap();
-                let beta = beta.parse().unwrap();
-                let mfpt = iter.next().unwrap();
-                let mfpt = mfpt.parse().unwrap();
-                (beta, mfpt)
+            |row| match row.as_slice(){
+                [beta, mfpt, ..] => Ok((*beta, *mfpt)),
+                _ => Err(Error::Input(format!("{path:?} contains a row without mfpt")))
             }
-        ).collect();
+        ).collect::<Result<_>>()?;
+    if vals.is_empty(){
+        return Err(Error::Input(format!("{path:?} contains no data")));
+    }
 
     let mut min_idx = 0;
     let mut min_val = f64::INFINITY;
@@ -957,7 +1116,7 @@ fn refine(path: &Path, refine: &Refine)
             break;
         }
     }
-    right = right.min(vals.len());
+    right = right.min(vals.len() - 1);
     let mut left = min_idx;
     for (_, mfpt) in vals[..=min_idx].iter().rev()
     {
@@ -971,27 +1130,19 @@ fn refine(path: &Path, refine: &Refine)
     opt.beta_left = vals[left].0;
     opt.beta_right = vals[right].0;
     println!("New: [{}, {}]", opt.beta_left, opt.beta_right);
-    if let Some(p) = refine.samples_per_point{
-        opt.samples_per_point = p;
-    }
-    if let Some(t) = refine.j
-    {
-        opt.j = t;
-    }
-    if let Some(depth) = refine.max_depth{
-        opt.settimgs.max_depth = depth.get();
-    }
+    opt.validate()?;
 
-    let path = path.file_name().unwrap().to_str().unwrap();
+    let path = path.file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| Error::Input(format!("invalid file name {path:?}")))?;
     let path = path.strip_suffix(".dat")
         .unwrap_or(path);
 
     let writer = std::fs::File::options()
         .create_new(true)
         .write(true)
-        .open(path)
-        .unwrap();
+        .open(path)?;
     let buf = BufWriter::new(writer);
-    serde_json::to_writer_pretty(buf, &opt)
-        .unwrap();
-}
\ No newline at end of file
+    serde_json::to_writer_pretty(buf, &opt)?;
+    Ok(())
+}
@@ -1,15 +1,13 @@
 use std::{
-    collections::VecDeque, f64::consts::SQRT_2, io::Write, num::*, sync::Mutex
+    collections::VecDeque, f64::consts::SQRT_2
 };
 use camino::Utf8PathBuf;
 use indicatif::{ProgressIterator, ProgressStyle};
-use kahan::KahanSum;
 use rand::{Rng, SeedableRng};
 use rand_distr::{Distribution, Exp, StandardNormal};
-use rand_pcg::{Pcg32, Pcg64};
-use rayon::prelude::*;
-use super::{Delta, RadomWalkSettings, MeasureMfptBetaOpt};
-use crate::{misc::*, sync_queue::*};
+use rand_pcg::Pcg64;
+use super::{Delta, RadomWalkSettings, MeasureMfptBetaOpt, EffWalkerSettings, Sample, WalkCost, WalkEvent, WalkEventKind, Walker, ensure_threshold};
+use crate::{error::*, scan::{build_global_pool, measure_and_write, PointSeed, ScanPart}, shard::{PointFormat, PointWriter}};
 
 #[derive(Debug)]
 pub struct DeltaWithLevel
@@ -18,26 +16,44 @@ pub struct DeltaWithLevel
     level: usize
 }
 
+/// Like [EffRandWalk](super::EffRandWalk), but the intervals are refined depth first
+/// and only the intervals that still need to be bisected are stored
 #[derive(Debug)]
 pub struct EffRandWalk2<R>
 {
     stack_queue: VecDeque<DeltaWithLevel>,
     delta_fpt: Delta,
     fpt: f64,
+    /// Mirror events of the rough walk
+    mirrors: Vec<WalkEvent>,
+    /// Number of mirror events before the first passage of the last sample
+    mirrors_before: usize,
     rng: R,
     settings: RadomWalkSettings,
-    threshold: f64
+    threshold: f64,
+    /// Intervals of the rough walk of the last sample
+    rough_steps: usize,
+    /// Intervals bisected for the last sample
+    bisections: usize,
+    /// Largest length of the stack queue during the last sample
+    peak_queue: usize,
+    /// Refinement level of `delta_fpt`
+    hit_level: usize,
+    /// Summed crossing probabilities of the deepest intervals before the hit, see [WalkCost::missed_crossings]
+    missed: f64
 }
 
 fn create_initial_walk<R>(
     settings: &RadomWalkSettings,
     mut rng: R,
     stack_queue: &mut VecDeque<DeltaWithLevel>,
+    mirrors: &mut Vec<WalkEvent>,
     threshold: f64
-) -> Delta
+) -> (Delta, usize)
 where R: Rng
 {
     stack_queue.clear();
+    mirrors.clear();
     let mirror_dist = Exp::new(settings.lambda_mirror)
         .unwrap();
     let mut next_mirror_time = mirror_dist.sample(&mut rng);
@@ -45,6 +61,7 @@ where R: Rng
     let sq = sqrt_step_size * SQRT_2;
     let mut current_pos = settings.origin;
     let mut current_time = 0.0;
+    let mut rough_steps = 0;
     loop {
         let div = next_mirror_time / settings.rough_step_size;
         let floored = div.floor();
@@ -72,9 +89,10 @@ where R: Rng
             
             if contained
             {
-                return delta;
+                return (delta, rough_steps + i + 1);
             }
         }
+        rough_steps += steps + 1;
         current_time = settings.rough_step_size.mul_add(steps as f64, current_time);
         let rest_sq = rest.sqrt() * SQRT_2;
         let left_time = current_time;
@@ -97,9 +115,18 @@ where R: Rng
         }
         if (left_pos..=current_pos).contains(&settings.target)
         {
-           return delta;
+           return (delta, rough_steps);
         }
+        let before = current_pos;
         current_pos *= settings.a;
+        mirrors.push(
+            WalkEvent{
+                time: current_time,
+                kind: WalkEventKind::Mirror,
+                before,
+                after: current_pos
+            }
+        );
         next_mirror_time = mirror_dist.sample(&mut rng);
     }
 }
@@ -114,35 +141,65 @@ where R: Rng + SeedableRng
     ) -> Self
     {
         let mut stack_queue = VecDeque::with_capacity(1024*1024);
-        let delta = create_initial_walk(
+        let mut mirrors = Vec::new();
+        let (delta, rough_steps) = create_initial_walk(
             &settings, 
             &mut rng,
             &mut stack_queue,
+            &mut mirrors,
             threshold
         );
         let fpt = delta.left_time + delta.delta_t;
 
         Self {
+            peak_queue: stack_queue.len(),
             stack_queue, 
             fpt,
+            mirrors,
+            mirrors_before: 0,
             settings,
             rng,
             delta_fpt: delta,
-            threshold
+            threshold,
+            rough_steps,
+            bisections: 0,
+            hit_level: 0,
+            missed: 0.0
         }
     }
 
 
     pub fn recycle(&mut self)
     {
-        let delta_fpt = create_initial_walk(
+        let (delta_fpt, rough_steps) = create_initial_walk(
             &self.settings, 
             &mut self.rng, 
             &mut self.stack_queue, 
+            &mut self.mirrors,
             self.threshold
         );
         self.fpt = delta_fpt.left_time + delta_fpt.delta_t;
         self.delta_fpt = delta_fpt;
+        self.rough_steps = rough_steps;
+        self.bisections = 0;
+        self.peak_queue = self.stack_queue.len();
+        self.hit_level = 0;
+        self.missed = 0.0;
+    }
+
+    /// Work, memory and refinement of the last sample. Only the intervals that still have to be
+    /// bisected are stored, so all memory is in the queue
+    pub fn cost(&self) -> WalkCost
+    {
+        WalkCost{
+            rough_steps: self.rough_steps,
+            bisections: self.bisections,
+            walk_bytes: 0,
+            queue_bytes: self.peak_queue * std::mem::size_of::<DeltaWithLevel>(),
+            hit_depth: self.hit_level,
+            depth_limited: self.hit_level == self.settings.max_depth,
+            missed_crossings: self.missed
+        }
     }
 
     fn bisection(&mut self, threshold: f64)
@@ -152,17 +209,24 @@ where R: Rng + SeedableRng
 
             let next_level = item.level + 1;
             let (left, right) = item.delta.bisect(&mut self.rng);
+            self.bisections += 1;
             let mut add_right = true;
+            // the children before the hit, they are only refined further if they are not too deep
+            let mut before_hit = [Some(left), Some(right)];
             if left.contains(&self.settings.target)
             {
                 self.stack_queue.clear();
                 self.fpt = left.left_time + left.delta_t;
                 self.delta_fpt = left;
+                self.hit_level = next_level;
                 add_right = false;
+                before_hit = [None, None];
             } else if right.contains(&self.settings.target) {
                 self.stack_queue.clear();
                 self.fpt = right.left_time + right.delta_t;
                 self.delta_fpt = right;
+                self.hit_level = next_level;
+                before_hit[1] = None;
             }
 
             if next_level < max_len {
@@ -186,99 +250,95 @@ where R: Rng + SeedableRng
                         left
                     );
                 }
+                self.peak_queue = self.peak_queue.max(self.stack_queue.len());
+            } else {
+                self.missed += before_hit.iter()
+                    .flatten()
+                    .map(|delta| delta.calc_prob(self.settings.target))
+                    .filter(|prob| *prob > threshold)
+                    .map(|prob| prob.min(1.0))
+                    .sum::<f64>();
             }
         }
     }
 }
 
+impl Walker for EffRandWalk2<Pcg64>
+{
+    type Settings = EffWalkerSettings;
+
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self
+    {
+        let threshold = settings.bisection.threshold()
+            .expect("Only bisection with threshold allowed here!");
+        Self::new(
+            settings.settings.clone(), 
+            rng, 
+            threshold
+        )
+    }
+
+    fn sample(&mut self) -> Sample
+    {
+        self.bisection(self.threshold);
+        let fpt = self.delta_fpt
+            .interpolate(self.settings.target);
+        // a mirror event at the start of the interval happened before the walker crossed the target
+        let start = self.delta_fpt.left_time;
+        self.mirrors_before = self.mirrors.partition_point(|mirror| mirror.time <= start);
+        Sample { fpt, mirrors: self.mirrors_before as u64, ..Default::default() }
+    }
+
+    fn recycle(&mut self)
+    {
+        EffRandWalk2::recycle(self)
+    }
+
+    /// Nothing to do, the mirror events are always recorded, as they are counted
+    fn record_events(&mut self) {}
+
+    fn events(&self) -> &[WalkEvent] {
+        &self.mirrors[..self.mirrors_before]
+    }
+
+    fn cost(&self) -> Option<WalkCost> {
+        Some(EffRandWalk2::cost(self))
+    }
+}
+
 pub fn eff_measure_mfpt_beta(
     opt: MeasureMfptBetaOpt,
-    file_name: Utf8PathBuf
-)
+    file_name: Utf8PathBuf,
+    part: ScanPart
+) -> Result<()>
 {
-    rayon::ThreadPoolBuilder::new()
-        .num_threads(opt.j.get())
-        .build_global()
-        .unwrap();
-
-    let delta = (opt.beta_right - opt.beta_left) / (opt.beta_samples.get() - 1) as f64;
+    ensure_threshold(&opt.bisection)
+        .map_err(|e| e.within("bisection"))?;
+    build_global_pool(opt.j)?;
 
-    let mut seeding_rng = Pcg32::seed_from_u64(opt.seed);
-    let samples_per_packet = (opt.samples_per_point.get() / (opt.j.get() * 12)).max(1);
+    let points = part.points(opt.beta_samples.get())?;
 
-    let header = [
-        "β",
-        "mfpt"
-    ];
-    let mut buf = create_buf_with_command_and_version(file_name);
-    write_slice_head(&mut buf, header).unwrap();
-    let mut settings = opt.settimgs.clone();
+    let mut writer = PointWriter::create_with_diagnostics(
+        file_name,
+        PointFormat::Mfpt{ x: "β".to_owned() },
+        part.shard,
+        opt.diagnostics
+    )?;
 
     let style = ProgressStyle::default_bar()
         .template("{msg} [{elapsed_precise} - {eta_precise}] {wide_bar}")
         .unwrap();
-    let threshold = opt.bisection.threshold()
-        .expect("Only bisection with threshold allowed here!");
     
-    for i in (0..opt.beta_samples.get()).progress_with_style(style)
+    for i in points.progress_with_style(style)
     {
-        let beta = delta.mul_add(i as f64, opt.beta_left);
-        let b2 = beta / settings.target;
-        settings.lambda_mirror = b2 * b2;
-        let queue = SyncQueue::create_work_queue(
-            opt.samples_per_point.get(), 
-            NonZeroUsize::new(opt.j.get() * 3).unwrap()
-        );
-        let queue = queue.map(
-            |amount|
-            {
-                let rng = Pcg64::from_rng(&mut seeding_rng).unwrap();
-                let walk = EffRandWalk2::new(
-                    settings.clone(), 
-                    rng,
-                    threshold
-                );
-                (walk, amount)
-            }
-        );
-        let global_sum_fpt = Mutex::new(KahanSum::new());
-        (0..opt.j.get())
-            .into_par_iter()
-            .for_each(
-                |_|
-                {
-                    let mut sum_fpt = KahanSum::new();
-                    while let Some((mut walker, amount)) = queue.pop() {
-                        let work = amount.min(samples_per_packet);
-                        let left = amount - work;
-
-                        for _ in 0..work{
-                            walker.bisection(threshold);
-                            let fpt = walker.delta_fpt
-                                .interpolate(walker.settings.target);
-                            sum_fpt += fpt;
-                            walker.recycle();
-                        }
-                        
-                        if left > 0{
-                            queue.push(
-                                (walker, left)
-                            );
-                        }
-                    }
-                    let mut lock = global_sum_fpt
-                        .lock()
-                        .unwrap();
-                    *lock += sum_fpt;
-                    drop(lock);
-                }
-            );
-        let mfpt = global_sum_fpt.into_inner().unwrap().sum() / opt.samples_per_point.get() as f64;
-        writeln!(
-            buf,
-            "{beta} {mfpt}"
-        ).unwrap();
+        let (beta, settings) = opt.point(i);
+        measure_and_write::<EffRandWalk2<Pcg64>>(
+            &mut writer,
+            beta,
+            &settings,
+            PointSeed::new(opt.seed, i),
+            opt.samples_per_point
+        )?;
     }
-    
-    
-}
\ No newline at end of file
+    writer.finish()
+}
@@ -1,21 +1,25 @@
 use std::f64::consts::SQRT_2;
-use std::fs::File;
-use std::io::BufWriter;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use rand_pcg::Pcg64;
 use rand_distr::Exp;
 use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
-use std::io::Write;
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use rand::{SeedableRng, distributions::Distribution};
 use crate::*;
-use crate::parse::parse;
+use crate::parse::parse_and_add_to_global;
+use crate::table::TableWriter;
+use crate::error::*;
+use crate::scan::build_global_pool;
+use super::{log_event, Sample, WalkEvent, WalkEventKind, Walker};
 use rand_distr::StandardNormal;
 use rand::prelude::*;
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ResettingWalkerHusk{
     pub rng_seed: u64,
     pub exp_lambda: f64,
@@ -34,6 +38,24 @@ impl Default for ResettingWalkerHusk {
     }
 }
 
+impl Validate for ResettingWalkerHusk{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("exp_lambda", self.exp_lambda)?;
+        ensure_step_below_event_time("step_size", self.step_size, self.exp_lambda)?;
+        ensure_below_target("target_pos", 0.0, self.target_pos)
+    }
+}
+
+impl ResettingWalkerHusk{
+    /// Validate the husk for [scan_lambda], which replaces `exp_lambda` by every lambda of the scan
+    pub fn validate_lambda_scan(&self, scan: &LambdaScanOpts) -> Result<()>
+    {
+        let mut husk = self.clone();
+        husk.exp_lambda = scan.max_lambda();
+        husk.validate()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct ResettingWalker{
@@ -46,7 +68,9 @@ pub struct ResettingWalker{
     resets_performed: u32,
     steps_until_next_reset: u32,
     step_size: f64,
-    sqrt_step_size: f64
+    sqrt_step_size: f64,
+    /// Events of the current sample, only if they are recorded
+    events: Option<Vec<WalkEvent>>
 }
 
 impl ResettingWalker{
@@ -57,6 +81,13 @@ impl ResettingWalker{
         let steps = (reset_time / self.step_size).floor() as u32;
         self.steps_until_next_reset = steps;
         self.resets_performed += 1;
+        log_event(
+            &mut self.events,
+            self.time_steps_performed as f64 * self.step_size,
+            WalkEventKind::Reset,
+            self.x_pos,
+            0.0
+        );
         self.x_pos = 0.0;
     }
 
@@ -68,6 +99,9 @@ impl ResettingWalker{
         self.resets_performed = 0;
         self.x_pos = 0.0;
         self.time_steps_performed = 0;
+        if let Some(events) = self.events.as_mut(){
+            events.clear();
+        }
     }
 
     pub fn walk_until_found(&mut self)
@@ -106,30 +140,76 @@ impl From<ResettingWalkerHusk> for ResettingWalker
             resets_performed: 0,
             steps_until_next_reset: 0,
             sqrt_step_size: value.step_size.sqrt(),
-            step_size: value.step_size
+            step_size: value.step_size,
+            events: None
         }
     }
 }
 
-pub fn execute(opts: ScanOpts)
+impl Walker for ResettingWalker
 {
-    let (husk, _): (ResettingWalkerHusk, _) = parse(opts.json);
+    type Settings = ResettingWalkerHusk;
+
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self
+    {
+        let mut walker: Self = settings.clone().into();
+        walker.rng = rng;
+        walker
+    }
 
-    let file = File::create("test.dat").unwrap();
-    let mut buf = BufWriter::new(file);
+    fn sample(&mut self) -> Sample
+    {
+        self.walk_until_found();
+        Sample{
+            fpt: self.time_steps_performed as f64 * self.step_size,
+            resets: self.resets_performed as u64,
+            mirrors: 0
+        }
+    }
 
-    writeln!(buf, "#lambda average_resets average_steps average_time").unwrap();
+    /// Nothing to do, [ResettingWalker::walk_until_found] resets the walker itself
+    fn recycle(&mut self) {}
 
-    for i in 0..opts.lambda_samples{
+    fn record_events(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
 
-        let lambda = opts.lambda_start + i as f64 *(opts.lambda_end - opts.lambda_start) / (opts.lambda_samples - 1) as f64;
+    fn events(&self) -> &[WalkEvent] {
+        self.events.as_deref().unwrap_or_default()
+    }
+}
+
+pub fn execute(opts: ScanOpts) -> Result<()>
+{
+    opts.validate()?;
+    let husk: ResettingWalkerHusk = parse_and_add_to_global(opts.json, &opts.set)?;
+    husk.validate_lambda_scan(&opts.scan)?;
+    scan_lambda(&husk, &opts.scan, format!("test.{}", opts.format.extension()))
+}
+
+/// Measure the mean number of resets and the mfpt for all lambdas of the scan
+pub fn scan_lambda<P>(husk: &ResettingWalkerHusk, scan: &LambdaScanOpts, out: P) -> Result<()>
+where P: AsRef<Path>
+{
+    let header = [
+        "lambda",
+        "average_resets",
+        "average_steps",
+        "average_time"
+    ];
+    let mut table = TableWriter::create(out, header)?;
+
+    build_global_pool(scan.threads)?;
+    let threads = scan.threads.get();
+    for lambda in scan.lambdas(){
 
         let mut husk = husk.clone();
         husk.exp_lambda = lambda;
+        let seed = husk.rng_seed;
 
         let mut walker: ResettingWalker = husk.into(); 
 
-        let mut thread_walker: Vec<_> = (0..opts.threads)
+        let mut thread_walker: Vec<_> = (0..threads)
             .map(
                 |_|
                 {
@@ -140,7 +220,7 @@ pub fn execute(opts: ScanOpts)
                 }
             ).collect();
 
-        let samples_per_thread = opts.samples / opts.threads;
+        let samples_per_thread = scan.samples / threads;
 
         let sum_resets = AtomicU64::new(0);
         let sum_time_steps = AtomicU64::new(0);
@@ -162,12 +242,14 @@ pub fn execute(opts: ScanOpts)
         let sum_resets = sum_resets.into_inner();
         let sum_time_steps = sum_time_steps.into_inner();
 
-        let total_samples = opts.threads * samples_per_thread;
+        let total_samples = threads * samples_per_thread;
 
         let average_resets = sum_resets as f64 / total_samples as f64;
         let average_steps = sum_time_steps as f64 / total_samples as f64;
         let average_time = average_steps * thread_walker[0].step_size;
         println!("lambda {lambda} average resets: {average_resets}, average_steps {average_steps} average_time {average_time}");
-        writeln!(buf, "{lambda} {average_resets} {average_steps} {average_time}").unwrap();
+        table.write_row(&[lambda, average_resets, average_steps, average_time])?;
+        table.record_point(lambda, &seed)?;
     }
+    table.finish()
 }
\ No newline at end of file
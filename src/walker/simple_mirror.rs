@@ -1,23 +1,27 @@
 use std::f64::consts::SQRT_2;
-use std::fs::File;
-use std::io::BufWriter;
+use std::path::Path;
 use std::sync::atomic::{AtomicU64, Ordering};
 
 use rand_pcg::Pcg64;
 use rand_distr::Exp;
 use rayon::prelude::{IntoParallelRefMutIterator, ParallelIterator};
-use std::io::Write;
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use rand::{SeedableRng, distributions::Distribution};
 use crate::*;
-use crate::parse::parse;
+use crate::parse::parse_and_add_to_global;
+use crate::table::TableWriter;
+use crate::error::*;
+use crate::scan::build_global_pool;
+use super::{log_event, ResettingMirrorWalkerSettings, Sample, WalkEvent, WalkEventKind, Walker};
 use rand_distr::StandardNormal;
 use rand::prelude::*;
 
 
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ResettingMirrorWalkerHusk{
     pub rng_seed: u64,
     pub exp_lambda: f64,
@@ -36,6 +40,24 @@ impl Default for ResettingMirrorWalkerHusk {
     }
 }
 
+impl Validate for ResettingMirrorWalkerHusk{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("exp_lambda", self.exp_lambda)?;
+        ensure_step_below_event_time("step_size", self.step_size, self.exp_lambda)?;
+        ensure_below_target("target_pos", 0.0, self.target_pos)
+    }
+}
+
+impl ResettingMirrorWalkerHusk{
+    /// Validate the husk for [scan_lambda_mirror], which replaces `exp_lambda` by every lambda of the scan
+    pub fn validate_lambda_scan(&self, scan: &LambdaScanOpts) -> Result<()>
+    {
+        let mut husk = self.clone();
+        husk.exp_lambda = scan.max_lambda();
+        husk.validate()
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Clone)]
 pub struct ResettingMirrorWalker{
@@ -50,7 +72,9 @@ pub struct ResettingMirrorWalker{
     steps_until_next_reset: u32,
     step_size: f64,
     sqrt_step_size: f64,
-    mirror_prob: f64
+    mirror_prob: f64,
+    /// Events of the current sample, only if they are recorded
+    events: Option<Vec<WalkEvent>>
 }
 
 impl ResettingMirrorWalker{
@@ -61,15 +85,24 @@ impl ResettingMirrorWalker{
         let steps = (reset_time / self.step_size).floor() as u32;
         self.steps_until_next_reset = steps;
         
+        let before = self.x_pos;
         let decision: f64 = self.rng.gen();
-        if decision < self.mirror_prob {
+        let kind = if decision < self.mirror_prob {
             self.x_pos = -self.x_pos;
             self.mirrors_performed += 1;
+            WalkEventKind::Mirror
         } else {
             self.x_pos = 0.0;
             self.resets_performed += 1;
-
-        }
+            WalkEventKind::Reset
+        };
+        log_event(
+            &mut self.events,
+            self.time_steps_performed as f64 * self.step_size,
+            kind,
+            before,
+            self.x_pos
+        );
     }
 
     pub fn reset(&mut self)
@@ -81,6 +114,9 @@ impl ResettingMirrorWalker{
         self.mirrors_performed = 0;
         self.x_pos = 0.0;
         self.time_steps_performed = 0;
+        if let Some(events) = self.events.as_mut(){
+            events.clear();
+        }
     }
 
     pub fn walk_until_found(&mut self)
@@ -122,36 +158,91 @@ impl ResettingMirrorWalker{
             steps_until_next_reset: 0,
             sqrt_step_size: value.step_size.sqrt(),
             step_size: value.step_size,
-            mirror_prob
+            mirror_prob,
+            events: None
         }
     }
 }
 
-pub fn execute_mirror(opts: MirrorScanOpts)
+impl Walker for ResettingMirrorWalker
 {
-    let (husk, json): (ResettingMirrorWalkerHusk, _) = parse(opts.json);
+    type Settings = ResettingMirrorWalkerSettings;
 
-    let samples = opts.samples;
-    let name = format!("v{VERSION}_mirror_scan_p_{}_samples{samples}.dat", opts.mirror_prob);
-    println!("creating {name}");
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self
+    {
+        let mut walker = Self::from(settings.husk.clone(), settings.mirror_prob);
+        walker.rng = rng;
+        walker
+    }
 
-    let file = File::create(name).unwrap();
-    let mut buf = BufWriter::new(file);
+    fn sample(&mut self) -> Sample
+    {
+        self.walk_until_found();
+        Sample{
+            fpt: self.time_steps_performed as f64 * self.step_size,
+            resets: self.resets_performed as u64,
+            mirrors: self.mirrors_performed as u64
+        }
+    }
 
-    misc::write_json(&mut buf, &json);
-    misc::write_commands(&mut buf).unwrap();
-    writeln!(buf, "#lambda average_resets var_resets average_steps average_time var_time").unwrap();
-    let samples_per_thread = opts.samples / opts.threads;
-    for i in 0..opts.lambda_samples{
+    /// Nothing to do, [ResettingMirrorWalker::walk_until_found] resets the walker itself
+    fn recycle(&mut self) {}
+
+    fn record_events(&mut self) {
+        self.events.get_or_insert_with(Vec::new);
+    }
+
+    fn events(&self) -> &[WalkEvent] {
+        self.events.as_deref().unwrap_or_default()
+    }
+}
 
-        let lambda = opts.lambda_start + i as f64 *(opts.lambda_end - opts.lambda_start) / (opts.lambda_samples - 1) as f64;
+pub fn execute_mirror(opts: MirrorScanOpts) -> Result<()>
+{
+    opts.validate()?;
+    let husk: ResettingMirrorWalkerHusk = parse_and_add_to_global(opts.json, &opts.set)?;
+    husk.validate_lambda_scan(&opts.scan)?;
+
+    let samples = opts.scan.samples;
+    let name = format!(
+        "v{VERSION}_mirror_scan_p_{}_samples{samples}.{}",
+        opts.mirror_prob,
+        opts.format.extension()
+    );
+    println!("creating {name}");
+    scan_lambda_mirror(&husk, &opts.scan, opts.mirror_prob, name)
+}
+
+/// Measure the mean and variance of the resets and the mfpt for all lambdas of the scan
+pub fn scan_lambda_mirror<P>(
+    husk: &ResettingMirrorWalkerHusk,
+    scan: &LambdaScanOpts,
+    mirror_prob: f64,
+    out: P
+) -> Result<()>
+where P: AsRef<Path>
+{
+    let header = [
+        "lambda",
+        "average_resets",
+        "var_resets",
+        "average_steps",
+        "average_time",
+        "var_time"
+    ];
+    let mut table = TableWriter::create(out, header)?;
+    build_global_pool(scan.threads)?;
+    let threads = scan.threads.get();
+    let samples_per_thread = scan.samples / threads;
+    for lambda in scan.lambdas(){
 
         let mut husk = husk.clone();
         husk.exp_lambda = lambda;
+        let seed = husk.rng_seed;
 
-        let mut walker: ResettingMirrorWalker = ResettingMirrorWalker::from(husk, opts.mirror_prob);
+        let mut walker: ResettingMirrorWalker = ResettingMirrorWalker::from(husk, mirror_prob);
 
-        let mut thread_walker: Vec<_> = (0..opts.threads)
+        let mut thread_walker: Vec<_> = (0..threads)
             .map(
                 |_|
                 {
@@ -189,7 +280,7 @@ pub fn execute_mirror(opts: MirrorScanOpts)
         let sum_resets = sum_resets.into_inner();
         let sum_time_steps = sum_time_steps.into_inner();
 
-        let total_samples = opts.threads * samples_per_thread;
+        let total_samples = threads * samples_per_thread;
 
         let step_size = thread_walker[0].step_size;
         let average_resets = sum_resets as f64 / total_samples as f64;
@@ -200,6 +291,8 @@ pub fn execute_mirror(opts: MirrorScanOpts)
         let var_resets = sum_resets_sq.into_inner() as f64 / total_samples as f64 - average_resets * average_resets;
 
         println!("lambda {lambda} average resets: {average_resets} var: {var_resets}, average_steps {average_steps} average_time {average_time} var {var_time}");
-        writeln!(buf, "{lambda} {average_resets} {var_resets} {average_steps} {average_time} {var_time}").unwrap();
+        table.write_row(&[lambda, average_resets, var_resets, average_steps, average_time, var_time])?;
+        table.record_point(lambda, &seed)?;
     }
+    table.finish()
 }
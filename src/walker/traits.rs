@@ -0,0 +1,293 @@
+use rand_pcg::Pcg64;
+use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
+
+use super::*;
+use crate::error::*;
+
+/// Result of a single first passage sample
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sample{
+    /// first passage time
+    pub fpt: f64,
+    /// number of resets that happened before the target was found
+    pub resets: u64,
+    /// number of mirror events that happened before the target was found
+    pub mirrors: u64
+}
+
+/// Kind of a [WalkEvent]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkEventKind{
+    Mirror = 0,
+    Reset = 1
+}
+
+/// A mirror or reset event of a single sample
+#[derive(Debug, Clone, Copy)]
+pub struct WalkEvent{
+    pub time: f64,
+    pub kind: WalkEventKind,
+    /// position right before the event
+    pub before: f64,
+    /// position right after the event
+    pub after: f64
+}
+
+/// Append an event to the log of a walker, if the walker records its events
+#[inline]
+pub(crate) fn log_event(
+    log: &mut Option<Vec<WalkEvent>>,
+    time: f64,
+    kind: WalkEventKind,
+    before: f64,
+    after: f64
+)
+{
+    if let Some(log) = log{
+        log.push(WalkEvent { time, kind, before, after });
+    }
+}
+
+/// Common interface of all walker implementations.
+///
+/// A walker is created from its settings and a rng,
+/// produces one sample via [Walker::sample] and
+/// has to be [recycled](Walker::recycle) before the next sample is drawn
+pub trait Walker: Sized + Send
+{
+    type Settings: Clone + Sync + Validate;
+
+    /// Create a walker. Note: Any seed contained in the settings is ignored,
+    /// the rng is used instead
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self;
+
+    /// Perform the walk until the target is found
+    fn sample(&mut self) -> Sample;
+
+    /// Prepare the walker for the next sample
+    fn recycle(&mut self);
+
+    /// Record the mirror and reset events of all following samples, see [Walker::events]
+    fn record_events(&mut self);
+
+    /// Mirror and reset events before the first passage of the last sample, ordered by time.
+    /// Empty, unless the events are recorded
+    fn events(&self) -> &[WalkEvent];
+
+    /// Work and refinement of the last sample, only known for the walkers that bisect
+    fn cost(&self) -> Option<WalkCost>
+    {
+        None
+    }
+}
+
+/// Settings for the mirror walker, which either mirrors or resets
+/// at the exponentially distributed event times
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ResettingMirrorWalkerSettings{
+    pub husk: ResettingMirrorWalkerHusk,
+    pub mirror_prob: f64
+}
+
+impl Validate for ResettingMirrorWalkerSettings{
+    fn validate(&self) -> Result<()> {
+        self.husk.validate().map_err(|e| e.within("husk"))?;
+        ensure_probability("mirror_prob", self.mirror_prob)
+    }
+}
+
+/// Which of the walk functions of the [ResettingUniWalker] should be used
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, JsonSchema)]
+pub enum UniMode{
+    /// [ResettingUniWalker::walk_until_found]
+    #[default]
+    MirrorAndReset,
+    /// [ResettingUniWalker::mirror_until_found]
+    OnlyMirror,
+    /// [ResettingUniWalker::adaptive_mirror_until_found]
+    AdaptiveOnlyMirror
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ResettingUniWalkerSettings{
+    pub husk: ResettingUniWalkerHusk,
+    pub mode: UniMode
+}
+
+impl Validate for ResettingUniWalkerSettings{
+    fn validate(&self) -> Result<()> {
+        self.husk.validate().map_err(|e| e.within("husk"))
+    }
+}
+
+/// Settings used by [EffRandWalk] and [even_more_efficient_mirror::EffRandWalk2].
+/// Note: [even_more_efficient_mirror::EffRandWalk2] only works with [Bisect::Threshold]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct EffWalkerSettings{
+    pub settings: RadomWalkSettings,
+    pub bisection: Bisect
+}
+
+impl Validate for EffWalkerSettings{
+    fn validate(&self) -> Result<()> {
+        self.settings.validate().map_err(|e| e.within("settings"))?;
+        self.bisection.validate().map_err(|e| e.within("bisection"))
+    }
+}
+
+/// Settings of any walker. Used to select the walker from a json file
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "walker")]
+pub enum AnyWalkerSettings{
+    Resetting(ResettingWalkerHusk),
+    ResettingMirror(ResettingMirrorWalkerSettings),
+    Uni(ResettingUniWalkerSettings),
+    Eff(EffWalkerSettings),
+    Eff2(EffWalkerSettings)
+}
+
+impl Default for AnyWalkerSettings{
+    fn default() -> Self {
+        Self::Eff2(EffWalkerSettings::default())
+    }
+}
+
+impl AnyWalkerSettings{
+    /// Time resolution that controls the discretisation bias: `step_size` of the naive walkers
+    /// and the length `rough_step_size · 2^-max_depth` of the deepest intervals of the efficient walkers.
+    /// With a threshold the interval that hits the target is always bisected down to the deepest level,
+    /// so their bias is set by `max_depth`, not by the rough step size
+    pub fn resolution(&self) -> f64
+    {
+        match self{
+            Self::Resetting(s) => s.step_size,
+            Self::ResettingMirror(s) => s.husk.step_size,
+            Self::Uni(s) => s.husk.step_size,
+            Self::Eff(s) | Self::Eff2(s) => {
+                let depth = i32::try_from(s.settings.max_depth).unwrap_or(i32::MAX);
+                s.settings.rough_step_size * 2.0_f64.powi(-depth)
+            }
+        }
+    }
+
+    /// Multiply the [resolution](AnyWalkerSettings::resolution) by `refinement`.
+    /// The naive walkers scale their `step_size`, the efficient walkers bisect
+    /// `log2(1 / refinement)` more levels, thus for them `refinement` has to be a power of two
+    pub fn refine(&mut self, refinement: f64) -> Result<()>
+    {
+        match self{
+            Self::Resetting(s) => s.step_size *= refinement,
+            Self::ResettingMirror(s) => s.husk.step_size *= refinement,
+            Self::Uni(s) => s.husk.step_size *= refinement,
+            Self::Eff(s) | Self::Eff2(s) => {
+                let levels = -refinement.log2();
+                if levels.fract() != 0.0 || !levels.is_finite(){
+                    return Err(
+                        Error::validation("", format!("the efficient walkers can only be refined by powers of two, not by {refinement}"))
+                    );
+                }
+                let depth = s.settings.max_depth as f64 + levels;
+                if depth < 1.0{
+                    return Err(
+                        Error::validation("", format!("refining by {refinement} leaves less than one level of max_depth {}", s.settings.max_depth))
+                    );
+                }
+                s.settings.max_depth = depth as usize;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for AnyWalkerSettings{
+    fn validate(&self) -> Result<()> {
+        match self{
+            Self::Resetting(s) => s.validate(),
+            Self::ResettingMirror(s) => s.validate(),
+            Self::Uni(s) => s.validate(),
+            Self::Eff(s) => s.validate(),
+            Self::Eff2(s) => {
+                s.validate()?;
+                ensure_threshold(&s.bisection)
+                    .map_err(|e| e.within("bisection"))
+            }
+        }
+    }
+}
+
+/// Any of the walkers. Can be used whenever the walker should be chosen at runtime
+pub enum AnyWalker{
+    Resetting(ResettingWalker),
+    ResettingMirror(ResettingMirrorWalker),
+    Uni(UniWalker),
+    Eff(EffRandWalk<Pcg64>),
+    Eff2(even_more_efficient_mirror::EffRandWalk2<Pcg64>)
+}
+
+impl Walker for AnyWalker{
+    type Settings = AnyWalkerSettings;
+
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self {
+        match settings{
+            AnyWalkerSettings::Resetting(s) => Self::Resetting(Walker::create(s, rng)),
+            AnyWalkerSettings::ResettingMirror(s) => Self::ResettingMirror(Walker::create(s, rng)),
+            AnyWalkerSettings::Uni(s) => Self::Uni(Walker::create(s, rng)),
+            AnyWalkerSettings::Eff(s) => Self::Eff(Walker::create(s, rng)),
+            AnyWalkerSettings::Eff2(s) => Self::Eff2(Walker::create(s, rng))
+        }
+    }
+
+    fn sample(&mut self) -> Sample {
+        match self{
+            Self::Resetting(w) => w.sample(),
+            Self::ResettingMirror(w) => w.sample(),
+            Self::Uni(w) => w.sample(),
+            Self::Eff(w) => w.sample(),
+            Self::Eff2(w) => w.sample()
+        }
+    }
+
+    fn recycle(&mut self) {
+        match self{
+            Self::Resetting(w) => w.recycle(),
+            Self::ResettingMirror(w) => w.recycle(),
+            Self::Uni(w) => w.recycle(),
+            Self::Eff(w) => Walker::recycle(w),
+            Self::Eff2(w) => Walker::recycle(w)
+        }
+    }
+
+    fn record_events(&mut self) {
+        match self{
+            Self::Resetting(w) => w.record_events(),
+            Self::ResettingMirror(w) => w.record_events(),
+            Self::Uni(w) => w.record_events(),
+            Self::Eff(w) => w.record_events(),
+            Self::Eff2(w) => w.record_events()
+        }
+    }
+
+    fn events(&self) -> &[WalkEvent] {
+        match self{
+            Self::Resetting(w) => w.events(),
+            Self::ResettingMirror(w) => w.events(),
+            Self::Uni(w) => w.events(),
+            Self::Eff(w) => w.events(),
+            Self::Eff2(w) => w.events()
+        }
+    }
+
+    fn cost(&self) -> Option<WalkCost> {
+        match self{
+            Self::Resetting(w) => w.cost(),
+            Self::ResettingMirror(w) => w.cost(),
+            Self::Uni(w) => w.cost(),
+            Self::Eff(w) => Walker::cost(w),
+            Self::Eff2(w) => Walker::cost(w)
+        }
+    }
+}
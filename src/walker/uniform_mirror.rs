@@ -8,14 +8,18 @@ use std::sync::atomic::AtomicU64;
 use rand_pcg::Pcg64;
 use rand_distr::{Exp, Uniform};
 use rayon::prelude::*;
-use std::io::Write;
 use serde::{Serialize, Deserialize};
+use schemars::JsonSchema;
 use rand::{SeedableRng, distributions::Distribution};
 use crate::*;
 use rand_distr::StandardNormal;
 use rand::prelude::*;
 
-use self::{misc::*, parse::parse_and_add_to_global};
+use self::parse::{parse_and_add_to_global, FieldOverride};
+use crate::error::*;
+use crate::scan::build_global_pool;
+use crate::table::{OutputFormat, TableWriter};
+use super::{log_event, ResettingUniWalkerSettings, Sample, UniMode, WalkEvent, WalkEventKind, Walker};
 
 const RELAXED: std::sync::atomic::Ordering = std::sync::atomic::Ordering::Relaxed;
 
@@ -36,7 +40,8 @@ fn interpolate(
     (time_steps_performed as f64 + frac) * step_size
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ResettingUniWalkerHusk{
     pub rng_seed: u64,
     pub uni_mid: f64,
@@ -61,7 +66,42 @@ impl Default for ResettingUniWalkerHusk {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Validate for ResettingUniWalkerHusk{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("reset_lambda", self.reset_lambda)?;
+        ensure_positive("mirror_lambda", self.mirror_lambda)?;
+        ensure_step_below_event_time(
+            "step_size", 
+            self.step_size, 
+            self.reset_lambda.max(self.mirror_lambda)
+        )?;
+        ensure_finite("uni_mid", self.uni_mid)?;
+        validate_uni_delta_2(self.uni_delta_2)?;
+        ensure_below_target("target_pos", 0.0, self.target_pos)
+    }
+}
+
+impl ResettingUniWalkerHusk{
+    /// Validate the husk for [scan_lambda_uni], which replaces `mirror_lambda` by every lambda of the scan
+    pub fn validate_lambda_scan(&self, scan: &LambdaScanOpts) -> Result<()>
+    {
+        let mut husk = self.clone();
+        husk.mirror_lambda = scan.max_lambda();
+        husk.validate()
+    }
+}
+
+fn validate_uni_delta_2(uni_delta_2: f64) -> Result<()>
+{
+    if uni_delta_2 >= 0.0 && uni_delta_2.is_finite(){
+        Ok(())
+    } else {
+        Err(Error::validation("uni_delta_2", format!("has to be non-negative and finite, but is {uni_delta_2}")))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct MirroringWalkerHistJob{
     pub rng_seed: u64,
     pub uni_mid: f64,
@@ -123,6 +163,7 @@ impl MirroringWalkerHistJob{
             rng, 
             x_pos: 0.0, 
             steps_until_next_mirror: 0,
+            time_until_next_mirror: 0.0,
             mirrors_performed: 0,
             reset_distr, 
             mirror_time_distr,
@@ -134,7 +175,28 @@ impl MirroringWalkerHistJob{
             resets_performed: 0,
             steps_until_next_reset: 0,
             sqrt_step_size: self.step_size.sqrt() * SQRT_2,
-            step_size: self.step_size
+            step_size: self.step_size,
+            events: None
+        }
+    }
+}
+
+impl Validate for MirroringWalkerHistJob{
+    fn validate(&self) -> Result<()> {
+        ensure_positive("mirror_lambda", self.mirror_lambda)?;
+        ensure_step_below_event_time("step_size", self.step_size, self.mirror_lambda)?;
+        ensure_finite("uni_mid", self.uni_mid)?;
+        validate_uni_delta_2(self.uni_delta_2)?;
+        let sorted = self.hist_positions
+            .windows(2)
+            .all(|w| w[0] <= w[1]);
+        let non_negative = self.hist_positions
+            .iter()
+            .all(|pos| *pos >= 0.0);
+        if sorted && non_negative {
+            Ok(())
+        } else {
+            Err(Error::validation("hist_positions", "has to be sorted in ascending order and must not contain negative times"))
         }
     }
 }
@@ -169,8 +231,12 @@ pub struct ResettingUniWalker{
     mirrors_performed: u64,
     steps_until_next_reset: u64,
     steps_until_next_mirror: u64,
+    /// The mirror time that was drawn last, as the adaptive walk does not use the steps
+    time_until_next_mirror: f64,
     step_size: f64,
-    sqrt_step_size: f64
+    sqrt_step_size: f64,
+    /// Events of the current sample, only if they are recorded
+    events: Option<Vec<WalkEvent>>
 }
 
 pub enum What{
@@ -187,6 +253,13 @@ impl ResettingUniWalker{
         let steps = (reset_time / self.step_size).floor() as u64;
         self.steps_until_next_reset = steps;
         self.resets_performed += 1;
+        log_event(
+            &mut self.events,
+            self.time_steps_performed as f64 * self.step_size,
+            WalkEventKind::Reset,
+            self.x_pos,
+            0.0
+        );
         self.x_pos = 0.0;
     }
 
@@ -195,9 +268,18 @@ impl ResettingUniWalker{
         let mirror_time = self.mirror_time_distr.sample(&mut self.rng);
         let steps = (mirror_time / self.step_size).floor() as u64;
         self.steps_until_next_mirror = steps;
+        self.time_until_next_mirror = mirror_time;
         self.mirrors_performed += 1; 
         let mirror_factor = self.mirror_dist.sample(&mut self.rng);
+        let before = self.x_pos;
         self.x_pos *= mirror_factor;
+        log_event(
+            &mut self.events,
+            self.time_steps_performed as f64 * self.step_size,
+            WalkEventKind::Mirror,
+            before,
+            self.x_pos
+        );
         mirror_time
     }
 
@@ -209,6 +291,9 @@ impl ResettingUniWalker{
         self.resets_performed = 0;
         self.x_pos = 0.0;
         self.time_steps_performed = 0;
+        if let Some(events) = self.events.as_mut(){
+            events.clear();
+        }
     }
 
     /// Dont forget that you might need to call self.reset(); before calling this,
@@ -356,10 +441,11 @@ impl ResettingUniWalker{
         self.reset();
         assert!(self.x_pos < self.target_pos);
         let mut total_time = 0.0;
+        // the first mirror time was drawn by reset, without mirroring
+        let mut mirror_time = self.time_until_next_mirror;
         // ACHTUNG!!! SQRT2 added
         'outer: loop {
             let mut time = 0.0;
-            let mirror_time = self.mirror_and_draw_next_mirror_time();
             loop{
                 let left = mirror_time - time;
                 let mut sz = calc_stepsize(
@@ -381,6 +467,11 @@ impl ResettingUniWalker{
                     break;
                 }
             }
+            mirror_time = self.mirror_and_draw_next_mirror_time();
+            if let Some(event) = self.events.as_mut().and_then(|events| events.last_mut()){
+                // the steps have different lengths, so the time is not given by the number of steps
+                event.time = total_time;
+            }
         }
     }
 }
@@ -400,6 +491,7 @@ impl From<ResettingUniWalkerHusk> for ResettingUniWalker
             rng, 
             x_pos: 0.0, 
             steps_until_next_mirror: 0,
+            time_until_next_mirror: 0.0,
             mirrors_performed: 0,
             reset_distr, 
             mirror_time_distr,
@@ -411,32 +503,111 @@ impl From<ResettingUniWalkerHusk> for ResettingUniWalker
             resets_performed: 0,
             steps_until_next_reset: 0,
             sqrt_step_size: value.step_size.sqrt() * SQRT_2,
-            step_size: value.step_size
+            step_size: value.step_size,
+            events: None
         }
     }
 }
 
-pub fn execute_uni(opts: UniScanOpts)
+/// [ResettingUniWalker] together with the walk function that should be used for sampling
+#[derive(Clone)]
+pub struct UniWalker{
+    pub walker: ResettingUniWalker,
+    pub mode: UniMode
+}
+
+impl Walker for UniWalker
 {
-    execute_uni_helper(opts, ResettingUniWalker::walk_until_found, true);
+    type Settings = ResettingUniWalkerSettings;
+
+    fn create(settings: &Self::Settings, rng: Pcg64) -> Self
+    {
+        let mut walker: ResettingUniWalker = settings.husk.clone().into();
+        walker.rng = rng;
+        Self{
+            walker,
+            mode: settings.mode
+        }
+    }
+
+    fn sample(&mut self) -> Sample
+    {
+        let fpt = match self.mode{
+            UniMode::MirrorAndReset => self.walker.walk_until_found(),
+            UniMode::OnlyMirror => self.walker.mirror_until_found(),
+            UniMode::AdaptiveOnlyMirror => self.walker.adaptive_mirror_until_found()
+        };
+        Sample{
+            fpt,
+            resets: self.walker.resets_performed,
+            mirrors: self.walker.mirrors_performed
+        }
+    }
+
+    /// Nothing to do, the walk functions reset the walker themselves
+    fn recycle(&mut self) {}
+
+    fn record_events(&mut self) {
+        self.walker.events.get_or_insert_with(Vec::new);
+    }
+
+    fn events(&self) -> &[WalkEvent] {
+        self.walker.events.as_deref().unwrap_or_default()
+    }
+}
+
+pub fn execute_uni(opts: UniScanOpts) -> Result<()>
+{
+    execute_uni_helper(opts, UniMode::MirrorAndReset)
 }
 
-pub fn execute_uni_only_mirror(opts: UniScanOpts)
+pub fn execute_uni_only_mirror(opts: UniScanOpts) -> Result<()>
 {
-    execute_uni_helper(opts, ResettingUniWalker::mirror_until_found, true);
+    execute_uni_helper(opts, UniMode::OnlyMirror)
 }
 
-pub fn execute_uni_only_mirror_adaptive(opts: UniScanOpts)
+pub fn execute_uni_only_mirror_adaptive(opts: UniScanOpts) -> Result<()>
 {
-    execute_uni_helper(opts, ResettingUniWalker::adaptive_mirror_until_found, false);
+    execute_uni_helper(opts, UniMode::AdaptiveOnlyMirror)
 }
 
-pub fn execute_uni_helper<F>(opts: UniScanOpts, fun: F, const_step_size: bool)
-where F: Sync + Fn(&mut ResettingUniWalker) -> f64
+fn execute_uni_helper(opts: UniScanOpts, mode: UniMode) -> Result<()>
 {
-    let husk: ResettingUniWalkerHusk = parse_and_add_to_global(opts.json);
+    opts.validate()?;
+    let husk: ResettingUniWalkerHusk = parse_and_add_to_global(opts.json, &opts.set)?;
+    husk.validate_lambda_scan(&opts.scan)?;
+    scan_lambda_uni(&husk, &opts.scan, mode, opts.out.as_deref().unwrap())
+}
+
+/// Measure the mfpt for all mirror rates of the scan, using the walk function selected by `mode`
+pub fn scan_lambda_uni<P>(
+    husk: &ResettingUniWalkerHusk,
+    scan: &LambdaScanOpts,
+    mode: UniMode,
+    out: P
+) -> Result<()>
+where P: AsRef<Path>
+{
+    match mode{
+        UniMode::MirrorAndReset => scan_lambda_uni_helper(husk, scan, out, ResettingUniWalker::walk_until_found, true),
+        UniMode::OnlyMirror => scan_lambda_uni_helper(husk, scan, out, ResettingUniWalker::mirror_until_found, true),
+        UniMode::AdaptiveOnlyMirror => {
+            scan_lambda_uni_helper(husk, scan, out, ResettingUniWalker::adaptive_mirror_until_found, false)
+        }
+    }
+}
 
-    let mut buf = create_buf_with_command_and_version(opts.out.as_deref().unwrap());
+fn scan_lambda_uni_helper<P, F>(
+    husk: &ResettingUniWalkerHusk,
+    scan: &LambdaScanOpts,
+    out: P,
+    fun: F,
+    const_step_size: bool
+) -> Result<()>
+where F: Sync + Fn(&mut ResettingUniWalker) -> f64,
+    P: AsRef<Path>
+{
+    build_global_pool(scan.threads)?;
     let mut header = vec![
         "lambda",
         "average_resets",
@@ -447,22 +618,21 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
     if const_step_size{
         header.push("interpolated_average_time");
     }
-    write_slice_head(&mut buf, header).unwrap();
+    let mut table = TableWriter::create(out, header)?;
 
     let step_size = husk.step_size;
-    let total_samples = opts.samples as f64;
-    for i in 0..opts.lambda_samples{
-
-        let lambda = opts.lambda_start + i as f64 *(opts.lambda_end - opts.lambda_start) / (opts.lambda_samples - 1) as f64;
+    let total_samples = scan.samples as f64;
+    for lambda in scan.lambdas(){
 
         let mut husk = husk.clone();
         husk.mirror_lambda = lambda;
+        let seed = husk.rng_seed;
 
         let mut walker: ResettingUniWalker = husk.into(); 
 
         let queue = SyncQueue::create_work_queue(
-            opts.samples, 
-            NonZeroUsize::new(opts.threads.get() * 3).unwrap()
+            scan.samples, 
+            NonZeroUsize::new(scan.threads.get() * 3).unwrap()
         );
 
         let queue = queue.map(
@@ -475,14 +645,14 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
             }
         );
 
-        let samples_per_packet = (opts.samples / (opts.threads.get() * 12)).max(1);
+        let samples_per_packet = (scan.samples / (scan.threads.get() * 12)).max(1);
 
         let sum_resets = AtomicU64::new(0);
         let sum_mirrors = AtomicU64::new(0);
         let sum_time_steps = AtomicU64::new(0);
         let sum_time = Mutex::new(0.0);
 
-        (0..opts.threads.get())
+        (0..scan.threads.get())
             .into_par_iter()
             .for_each(
                 |_|
@@ -530,33 +700,60 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
         let average_mirrors = sum_mirrors as f64 / total_samples;
         if const_step_size{
             println!("lambda {lambda} average resets: {average_resets}, average_steps {average_steps} average_time {average_time} interp_time {average_time_interpol}");
-            writeln!(buf, "{lambda} {average_resets} {average_steps} {average_mirrors} {average_time} {average_time_interpol}")
+            table.write_row(&[lambda, average_resets, average_steps, average_mirrors, average_time, average_time_interpol])
         } else {
             println!("lambda {lambda} average resets: {average_resets}, average_steps {average_steps} average_time {average_time_interpol}");
-            writeln!(buf, "{lambda} {average_resets} {average_steps} {average_mirrors} {average_time_interpol}")
-        }.unwrap();
+            table.write_row(&[lambda, average_resets, average_steps, average_mirrors, average_time_interpol])
+        }?;
+        table.record_point(lambda, &seed)?;
     }
+    table.finish()
 }
 
-pub fn execute_pos_scan_uni(opts: LUniScanOpts)
+pub fn execute_pos_scan_uni(opts: LUniScanOpts) -> Result<()>
 {
-    execute_pos_scan(opts, ResettingUniWalker::walk_until_found)
+    execute_pos_scan(opts, UniMode::MirrorAndReset)
 }
 
-pub fn execute_pos_scan_uni_only_mirror(opts: LUniScanOpts)
+pub fn execute_pos_scan_uni_only_mirror(opts: LUniScanOpts) -> Result<()>
 {
-    execute_pos_scan(opts, ResettingUniWalker::mirror_until_found)
+    execute_pos_scan(opts, UniMode::OnlyMirror)
 }
 
-pub fn execute_pos_scan<F>(
-    opts: LUniScanOpts,
-    fun: F
-)
-where F: Sync + Fn(&mut ResettingUniWalker) -> f64
+fn execute_pos_scan(opts: LUniScanOpts, mode: UniMode) -> Result<()>
+{
+    opts.validate()?;
+    let husk: ResettingUniWalkerHusk = parse_and_add_to_global(opts.json, &opts.set)?;
+    scan_l_uni(&husk, &opts.scan, mode, opts.out.as_deref().unwrap())
+}
+
+/// Measure the mfpt for all target positions of the scan.
+/// [UniMode::AdaptiveOnlyMirror] is not supported
+pub fn scan_l_uni<P>(
+    husk: &ResettingUniWalkerHusk,
+    scan: &LScanOpts,
+    mode: UniMode,
+    out: P
+) -> Result<()>
+where P: AsRef<Path>
 {
-    let husk: ResettingUniWalkerHusk = parse_and_add_to_global(opts.json);
+    match mode{
+        UniMode::MirrorAndReset => scan_l_uni_helper(husk, scan, out, ResettingUniWalker::walk_until_found),
+        UniMode::OnlyMirror => scan_l_uni_helper(husk, scan, out, ResettingUniWalker::mirror_until_found),
+        UniMode::AdaptiveOnlyMirror => Err(Error::validation("mode", "AdaptiveOnlyMirror is not supported by the scan of L"))
+    }
+}
 
-    let mut buf = create_buf_with_command_and_version(opts.out.as_deref().unwrap());
+fn scan_l_uni_helper<P, F>(
+    husk: &ResettingUniWalkerHusk,
+    scan: &LScanOpts,
+    out: P,
+    fun: F
+) -> Result<()>
+where F: Sync + Fn(&mut ResettingUniWalker) -> f64,
+    P: AsRef<Path>
+{
+    build_global_pool(scan.threads)?;
     let header = [
         "L",
         "average_resets",
@@ -565,23 +762,21 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
         "average_time",
         "interpolated_average_time"
     ];
-
-    write_slice_head(&mut buf, header).unwrap();
+    let mut table = TableWriter::create(out, header)?;
 
     let step_size = husk.step_size;
-    let total_samples = opts.samples as f64;
-    for i in 0..opts.l_samples{
-
-        let l = opts.l_start + i as f64 *(opts.l_end - opts.l_start) / (opts.l_samples - 1) as f64;
+    let total_samples = scan.samples as f64;
+    for l in scan.ls(){
 
         let mut husk = husk.clone();
         husk.target_pos = l;
+        let seed = husk.rng_seed;
 
         let mut walker: ResettingUniWalker = husk.into(); 
 
         let queue = SyncQueue::create_work_queue(
-            opts.samples, 
-            NonZeroUsize::new(opts.threads.get() * 3).unwrap()
+            scan.samples, 
+            NonZeroUsize::new(scan.threads.get() * 3).unwrap()
         );
 
         let queue = queue.map(
@@ -594,14 +789,14 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
             }
         );
 
-        let samples_per_packet = (opts.samples / (opts.threads.get() * 12)).max(1);
+        let samples_per_packet = (scan.samples / (scan.threads.get() * 12)).max(1);
 
         let sum_resets = AtomicU64::new(0);
         let sum_mirrors = AtomicU64::new(0);
         let sum_time_steps = AtomicU64::new(0);
         let sum_time = Mutex::new(0.0);
 
-        (0..opts.threads.get())
+        (0..scan.threads.get())
             .into_par_iter()
             .for_each(
                 |_|
@@ -648,22 +843,29 @@ where F: Sync + Fn(&mut ResettingUniWalker) -> f64
         let average_time = average_steps * step_size;
         let average_mirrors = sum_mirrors as f64 / total_samples;
         println!("L {l} average resets: {average_resets}, average_steps {average_steps} average_time {average_time} interp_time {average_time_interpol}");
-        writeln!(buf, "{l} {average_resets} {average_steps} {average_mirrors} {average_time} {average_time_interpol}").unwrap();
+        table.write_row(&[l, average_resets, average_steps, average_mirrors, average_time, average_time_interpol])?;
+        table.record_point(l, &seed)?;
     }
+    table.finish()
 }
 
-pub fn exec_mirroring_hists<P>(path: Option<P>)
+pub fn exec_mirroring_hists<P>(path: Option<P>, overrides: &[FieldOverride], format: OutputFormat) -> Result<()>
 where P: AsRef<Path>
 {
-    exec_hist_helper(path, ResettingUniWalker::only_mirror_steps)
+    let opt: MirroringWalkerHistJob = parse_and_add_to_global(path, overrides)?;
+    mirroring_hists(&opt, "test", format)
 }
 
-fn exec_hist_helper<P, F>(path: Option<P>, fun: F)
-where P: AsRef<Path>,
-    F: Fn(&mut ResettingUniWalker, u64) + Sync
+/// Write the positions of the only mirroring walkers at the requested times
+/// into the files `{out_prefix}_{time}.{extension}`
+pub fn mirroring_hists(opt: &MirroringWalkerHistJob, out_prefix: &str, format: OutputFormat) -> Result<()>
 {
-    let opt: MirroringWalkerHistJob = parse_and_add_to_global(path);
+    hist_helper(opt, out_prefix, format, ResettingUniWalker::only_mirror_steps)
+}
 
+fn hist_helper<F>(opt: &MirroringWalkerHistJob, out_prefix: &str, format: OutputFormat, fun: F) -> Result<()>
+where F: Fn(&mut ResettingUniWalker, u64) + Sync
+{
     let mut walkers = opt.get_walkers();
 
     let (times, step_helper) = opt.get_times();
@@ -672,11 +874,11 @@ where P: AsRef<Path>,
         .map(
             |time|
             {
-                let name = format!("test_{time}.dat");
-                let buf = create_buf_with_command_and_version(name);
-                Mutex::new(buf)
+                let name = format!("{out_prefix}_{time}.{}", format.extension());
+                let table = TableWriter::create(name, ["position"])?;
+                Ok(Mutex::new(table.scientific()))
             }
-        ).collect();
+        ).collect::<Result<_>>()?;
 
     walkers.par_iter_mut()
         .for_each(
@@ -692,10 +894,13 @@ where P: AsRef<Path>,
                             
                             let pos = walker.x_pos;
                             let mut lock = writer.lock().unwrap();
-                            writeln!(lock, "{pos:e}").unwrap();
+                            lock.write_row(&[pos])
+                                .expect("unable to write position");
                             drop(lock);
                         }
                     )
             }
-        )
+        );
+    writer.into_iter()
+        .try_for_each(|table| table.into_inner().unwrap().finish())
 }
\ No newline at end of file
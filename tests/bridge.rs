@@ -0,0 +1,139 @@
+//! The brownian bridges used to refine the walks of the efficient walkers, see [Delta::bisect]
+mod common;
+
+use common::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use resetting_rw::walker::Delta;
+
+const SAMPLES: usize = 100_000;
+
+/// Interval from 0.3 at t = 5 to 1.7 at t = 7
+const DELTA: Delta = Delta{
+    left_pos: 0.3,
+    right_pos: 1.7,
+    delta_t: 2.0,
+    left_time: 5.0
+};
+
+/// Variance of a brownian bridge with D = 1 of duration `duration` at time `s`
+fn bridge_variance(s: f64, duration: f64) -> f64
+{
+    2.0 * s * (duration - s) / duration
+}
+
+#[test]
+fn bisect_splits_the_interval()
+{
+    let mut rng = Pcg64::seed_from_u64(1);
+    for _ in 0..100{
+        let (left, right) = DELTA.bisect(&mut rng);
+        assert_eq!(left.left_pos, DELTA.left_pos);
+        assert_eq!(left.left_time, DELTA.left_time);
+        assert_eq!(left.right_pos, right.left_pos);
+        assert_eq!(right.right_pos, DELTA.right_pos);
+        assert_eq!(left.delta_t, 1.0);
+        assert_eq!(right.delta_t, 1.0);
+        assert_eq!(right.left_time, 6.0);
+    }
+}
+
+#[test]
+fn bisect_midpoint_moments()
+{
+    let mut rng = Pcg64::seed_from_u64(2);
+    let mid: Vec<f64> = (0..SAMPLES)
+        .map(|_| DELTA.bisect(&mut rng).0.right_pos)
+        .collect();
+    let moments = Moments::new(&mid);
+
+    let variance = bridge_variance(1.0, 2.0);
+    assert_close("mean", moments.mean, 1.0, moments.standard_error());
+    // for gaussian samples Var[s²] = 2σ⁴/N
+    let se_variance = (2.0 / SAMPLES as f64).sqrt() * variance;
+    assert_close("variance", moments.variance, variance, se_variance);
+
+    // the midpoint is gaussian, so the fourth central moment is 3σ⁴ with Var = 96σ⁸/N
+    let fourth = mid.iter()
+        .map(|x| (x - moments.mean).powi(4))
+        .sum::<f64>() / SAMPLES as f64;
+    let se_fourth = (96.0 / SAMPLES as f64).sqrt() * variance * variance;
+    assert_close("fourth moment", fourth, 3.0 * variance * variance, se_fourth);
+}
+
+/// Bisecting a half again has to give the points of the same bridge at a quarter and the half of its duration
+#[test]
+fn nested_bisection_is_the_same_bridge()
+{
+    let mut rng = Pcg64::seed_from_u64(3);
+    let (quarter, half): (Vec<f64>, Vec<f64>) = (0..SAMPLES)
+        .map(
+            |_| {
+                let (left, _) = DELTA.bisect(&mut rng);
+                let (first, _) = left.bisect(&mut rng);
+                (first.right_pos, left.right_pos)
+            }
+        ).unzip();
+    let q = Moments::new(&quarter);
+    let h = Moments::new(&half);
+
+    let var_q = bridge_variance(0.5, 2.0);
+    let var_h = bridge_variance(1.0, 2.0);
+    assert_close("mean at a quarter", q.mean, 0.65, q.standard_error());
+    assert_close("variance at a quarter", q.variance, var_q, (2.0 / SAMPLES as f64).sqrt() * var_q);
+
+    // Cov(B(s), B(u)) = 2 s (t - u) / t for s < u
+    let covariance = quarter.iter()
+        .zip(half.iter())
+        .map(|(x, y)| (x - q.mean) * (y - h.mean))
+        .sum::<f64>() / SAMPLES as f64;
+    let expected = 2.0 * 0.5 * (2.0 - 1.0) / 2.0;
+    let se_covariance = ((var_q * var_h + expected * expected) / SAMPLES as f64).sqrt();
+    assert_close("covariance", covariance, expected, se_covariance);
+}
+
+/// Probability that the refinement of `delta` to `depth` levels crosses the target,
+/// given the positions drawn for the refinement
+fn refined_crossing_prob(delta: &Delta, target: f64, depth: usize, rng: &mut Pcg64) -> f64
+{
+    if delta.contains(&target){
+        return 1.0;
+    }
+    if depth == 0{
+        return delta.calc_prob(target);
+    }
+    let (left, right) = delta.bisect(rng);
+    let p_left = refined_crossing_prob(&left, target, depth - 1, rng);
+    let p_right = refined_crossing_prob(&right, target, depth - 1, rng);
+    1.0 - (1.0 - p_left) * (1.0 - p_right)
+}
+
+/// The crossing probability of an interval is the mean of the crossing probabilities of its refinements.
+/// This is what the bisection relies on
+#[test]
+fn crossing_probability_is_consistent_with_bisection()
+{
+    let target = 1.0;
+    let delta = Delta{ left_pos: 0.0, right_pos: 0.5, delta_t: 1.0, left_time: 0.0 };
+    let expected = delta.calc_prob(target);
+    assert!((expected - (-0.5_f64).exp()).abs() < 1e-15);
+
+    let mut rng = Pcg64::seed_from_u64(4);
+    for depth in [1, 3]{
+        let probs: Vec<f64> = (0..SAMPLES / 10)
+            .map(|_| refined_crossing_prob(&delta, target, depth, &mut rng))
+            .collect();
+        let moments = Moments::new(&probs);
+        assert_close(&format!("crossing probability at depth {depth}"), moments.mean, expected, moments.standard_error());
+    }
+}
+
+#[test]
+fn interpolate_hits_the_target()
+{
+    let delta = Delta{ left_pos: 0.0, right_pos: 2.0, delta_t: 1.0, left_time: 3.0 };
+    assert_eq!(delta.interpolate(1.0), 3.5);
+    assert_eq!(delta.interpolate(0.0), 3.0);
+    assert!(delta.contains(&2.0));
+    assert!(!delta.contains(&2.5));
+}
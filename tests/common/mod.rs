@@ -0,0 +1,115 @@
+//! Statistics shared by the validation tests.
+//!
+//! All tests use fixed seeds, so they are deterministic. The tolerance bands are
+//! [SIGMAS] standard errors wide, such that a correct implementation would practically
+//! never fail for another seed either, while wrong factors, e.g., of the diffusion
+//! constant, are still far outside of the bands.
+#![allow(dead_code)]
+
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use resetting_rw::walker::{Bisect, EffWalkerSettings, RadomWalkSettings, Sample, Walker};
+
+/// Width of the tolerance bands in standard errors
+pub const SIGMAS: f64 = 5.0;
+
+/// Mean and population variance of some samples
+#[derive(Debug, Clone, Copy)]
+pub struct Moments{
+    pub samples: usize,
+    pub mean: f64,
+    pub variance: f64
+}
+
+impl Moments{
+    pub fn new(values: &[f64]) -> Self
+    {
+        let samples = values.len();
+        let mean = values.iter().sum::<f64>() / samples as f64;
+        let variance = values.iter()
+            .map(|val| (val - mean) * (val - mean))
+            .sum::<f64>() / samples as f64;
+        Self { samples, mean, variance }
+    }
+
+    /// Standard error of the mean
+    pub fn standard_error(&self) -> f64
+    {
+        (self.variance / self.samples as f64).sqrt()
+    }
+}
+
+/// Asserts that `measured` is within [SIGMAS] standard errors of `expected`
+pub fn assert_close(what: &str, measured: f64, expected: f64, standard_error: f64)
+{
+    let z = (measured - expected) / standard_error;
+    assert!(
+        z.abs() < SIGMAS,
+        "{what}: measured {measured}, expected {expected}, standard error {standard_error}, z = {z}"
+    );
+}
+
+/// Asserts that the fraction `hits / samples` is within [SIGMAS] binomial standard errors of `probability`
+pub fn assert_probability(what: &str, hits: usize, samples: usize, probability: f64)
+{
+    let standard_error = (probability * (1.0 - probability) / samples as f64).sqrt();
+    assert_close(what, hits as f64 / samples as f64, probability, standard_error);
+}
+
+/// Asserts that two independent measurements agree within [SIGMAS] combined standard errors
+pub fn assert_agree(what: &str, a: Moments, b: Moments)
+{
+    let standard_error = (a.standard_error().powi(2) + b.standard_error().powi(2)).sqrt();
+    assert_close(what, a.mean, b.mean, standard_error);
+}
+
+/// Asserts that the fractions of two independent sets of samples above `threshold` agree
+pub fn assert_same_tail(what: &str, a: &[f64], b: &[f64], threshold: f64)
+{
+    let fraction = |values: &[f64]| values.iter().filter(|val| **val > threshold).count() as f64 / values.len() as f64;
+    let (p_a, p_b) = (fraction(a), fraction(b));
+    let pooled = (p_a * a.len() as f64 + p_b * b.len() as f64) / (a.len() + b.len()) as f64;
+    let standard_error = (pooled * (1.0 - pooled) * (1.0 / a.len() as f64 + 1.0 / b.len() as f64)).sqrt();
+    assert_close(&format!("{what}: P(T > {threshold})"), p_a, p_b, standard_error);
+}
+
+/// `samples` samples of the walker `W`
+pub fn draw_samples<W>(settings: &W::Settings, seed: u64, samples: usize) -> Vec<Sample>
+where W: Walker
+{
+    let mut walker = W::create(settings, Pcg64::seed_from_u64(seed));
+    (0..samples)
+        .map(
+            |_| {
+                let sample = walker.sample();
+                walker.recycle();
+                sample
+            }
+        ).collect()
+}
+
+/// First passage times of `samples` samples of the walker `W`
+pub fn first_passage_times<W>(settings: &W::Settings, seed: u64, samples: usize) -> Vec<f64>
+where W: Walker
+{
+    draw_samples::<W>(settings, seed, samples)
+        .iter()
+        .map(|sample| sample.fpt)
+        .collect()
+}
+
+/// Settings of the efficient walkers, which start at 0
+pub fn eff_settings(a: f64, lambda_mirror: f64, target: f64, rough_step_size: f64) -> EffWalkerSettings
+{
+    EffWalkerSettings{
+        settings: RadomWalkSettings{
+            lambda_mirror,
+            rough_step_size,
+            target,
+            a,
+            max_depth: 40,
+            origin: 0.0
+        },
+        bisection: Bisect::Threshold(1e-6)
+    }
+}
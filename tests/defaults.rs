@@ -0,0 +1,54 @@
+//! The example json files, which the subcommands print if no file is given, have to be valid themselves
+use resetting_rw::{
+    benchmark::BenchmarkJob,
+    cross_validation::CrossValidationJob,
+    error::Validate,
+    extrapolation::ExtrapolationJob,
+    job::RunJob,
+    scan::MeasureWalkerOpt,
+    tune::TuneJob,
+    walker::{
+        MeasureMfptBetaOpt,
+        MeasureMfptLOpt,
+        MeasureMfptOpt,
+        MirroringWalkerHistJob,
+        ResettingMirrorWalkerHusk,
+        ResettingUniWalkerHusk,
+        ResettingWalkerHusk
+    }
+};
+
+fn check_default<T: Default + Validate>(name: &str)
+{
+    if let Err(e) = T::default().validate(){
+        panic!("the default {name} is invalid: {e}");
+    }
+}
+
+#[test]
+fn eff_defaults_are_valid()
+{
+    check_default::<MeasureMfptOpt>("MeasureMfptOpt");
+    check_default::<MeasureMfptLOpt>("MeasureMfptLOpt");
+    check_default::<MeasureMfptBetaOpt>("MeasureMfptBetaOpt");
+}
+
+#[test]
+fn walker_defaults_are_valid()
+{
+    check_default::<ResettingWalkerHusk>("ResettingWalkerHusk");
+    check_default::<ResettingMirrorWalkerHusk>("ResettingMirrorWalkerHusk");
+    check_default::<ResettingUniWalkerHusk>("ResettingUniWalkerHusk");
+    check_default::<MirroringWalkerHistJob>("MirroringWalkerHistJob");
+    check_default::<MeasureWalkerOpt>("MeasureWalkerOpt");
+}
+
+#[test]
+fn job_defaults_are_valid()
+{
+    check_default::<RunJob>("RunJob");
+    check_default::<CrossValidationJob>("CrossValidationJob");
+    check_default::<BenchmarkJob>("BenchmarkJob");
+    check_default::<ExtrapolationJob>("ExtrapolationJob");
+    check_default::<TuneJob>("TuneJob");
+}
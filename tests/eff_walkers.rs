@@ -0,0 +1,133 @@
+//! Consistency of the efficient walkers: [EffRandWalk] and [EffRandWalk2] refine the rough walk differently,
+//! but have to sample the same first passage times, which must not depend on the rough step size either.
+//! All walkers that mirror have to count the mirrors before the first passage correctly
+mod common;
+
+use common::*;
+use rand::SeedableRng;
+use rand_pcg::Pcg64;
+use resetting_rw::walker::{
+    even_more_efficient_mirror::EffRandWalk2,
+    EffRandWalk,
+    EffWalkerSettings,
+    ResettingUniWalkerHusk,
+    ResettingUniWalkerSettings,
+    UniMode,
+    UniWalker,
+    Walker
+};
+
+const SAMPLES: usize = 20_000;
+const TIMES: [f64; 4] = [0.1, 0.3, 1.0, 3.0];
+
+/// Negative a, for which the analytic mfpt is not known
+fn settings(rough_step_size: f64) -> EffWalkerSettings
+{
+    eff_settings(-0.5, 4.0, 1.0, rough_step_size)
+}
+
+fn check_agreement(what: &str, a: &[f64], b: &[f64])
+{
+    assert_agree(&format!("{what}: mfpt"), Moments::new(a), Moments::new(b));
+    for t in TIMES{
+        assert_same_tail(what, a, b, t);
+    }
+}
+
+#[test]
+fn eff_and_eff2_agree()
+{
+    let eff = first_passage_times::<EffRandWalk<Pcg64>>(&settings(0.05), 40, SAMPLES);
+    let eff2 = first_passage_times::<EffRandWalk2<Pcg64>>(&settings(0.05), 41, SAMPLES);
+    check_agreement("Eff vs Eff2", &eff, &eff2);
+}
+
+/// The bisection has to find the first crossing, no matter how rough the initial walk is
+#[test]
+fn eff_does_not_depend_on_the_rough_step()
+{
+    let fine = first_passage_times::<EffRandWalk<Pcg64>>(&settings(0.005), 42, SAMPLES);
+    let rough = first_passage_times::<EffRandWalk<Pcg64>>(&settings(0.2), 43, SAMPLES);
+    check_agreement("Eff", &fine, &rough);
+}
+
+#[test]
+fn eff2_does_not_depend_on_the_rough_step()
+{
+    let fine = first_passage_times::<EffRandWalk2<Pcg64>>(&settings(0.005), 44, SAMPLES);
+    let rough = first_passage_times::<EffRandWalk2<Pcg64>>(&settings(0.2), 45, SAMPLES);
+    check_agreement("Eff2", &fine, &rough);
+}
+
+/// The mirror events are a poisson process with rate λ, so the mean number of them
+/// before the first passage is λ times the mfpt
+fn check_mirror_count<W>(settings: &W::Settings, lambda: f64, seed: u64, samples: usize)
+where W: Walker
+{
+    let differences: Vec<f64> = draw_samples::<W>(settings, seed, samples)
+        .iter()
+        .map(|sample| sample.mirrors as f64 - lambda * sample.fpt)
+        .collect();
+    let moments = Moments::new(&differences);
+    assert_close("mirrors - λ fpt", moments.mean, 0.0, moments.standard_error());
+}
+
+#[test]
+fn eff_mirror_count()
+{
+    let settings = settings(0.05);
+    check_mirror_count::<EffRandWalk<Pcg64>>(&settings, settings.settings.lambda_mirror, 46, SAMPLES);
+}
+
+#[test]
+fn eff2_mirror_count()
+{
+    let settings = settings(0.05);
+    check_mirror_count::<EffRandWalk2<Pcg64>>(&settings, settings.settings.lambda_mirror, 47, SAMPLES);
+}
+
+/// The adaptive walker times its mirrors itself. Its steps become tiny close to the target, so it is slow,
+/// but few samples suffice: a mirror too many per sample is far outside of the band
+#[test]
+fn adaptive_uni_mirror_count()
+{
+    let settings = ResettingUniWalkerSettings{
+        husk: ResettingUniWalkerHusk{
+            rng_seed: 0,
+            uni_mid: 0.5,
+            uni_delta_2: 0.0,
+            reset_lambda: 1.0,
+            mirror_lambda: 4.0,
+            target_pos: 0.3,
+            step_size: 1e-3
+        },
+        mode: UniMode::AdaptiveOnlyMirror
+    };
+    check_mirror_count::<UniWalker>(&settings, settings.husk.mirror_lambda, 50, 50);
+}
+
+/// With a threshold the interval in which the target is hit is always bisected down to the deepest level,
+/// also if it is an interval of the rough walk
+fn check_hitting_interval_is_refined<W>(seed: u64)
+where W: Walker<Settings = EffWalkerSettings>
+{
+    let mut walker = W::create(&settings(0.05), Pcg64::seed_from_u64(seed));
+    for sample in 0..2000{
+        walker.sample();
+        let cost = walker.cost().expect("the efficient walkers know their cost");
+        assert!(cost.depth_limited, "sample {sample}: the target was hit on level {}", cost.hit_depth);
+        walker.recycle();
+    }
+}
+
+#[test]
+fn eff_refines_the_hitting_interval()
+{
+    check_hitting_interval_is_refined::<EffRandWalk<Pcg64>>(48);
+}
+
+#[test]
+fn eff2_refines_the_hitting_interval()
+{
+    check_hitting_interval_is_refined::<EffRandWalk2<Pcg64>>(49);
+}
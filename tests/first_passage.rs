@@ -0,0 +1,179 @@
+//! First passage times of the walkers compared with known results:
+//! free diffusion, diffusion with resetting and diffusion with mirroring, see [resetting_rw::analytical::mfpt]
+mod common;
+
+use common::*;
+use resetting_rw::{
+    analytical::mfpt,
+    walker::{
+        even_more_efficient_mirror::EffRandWalk2,
+        EffRandWalk,
+        EffWalkerSettings,
+        ResettingMirrorWalker,
+        ResettingMirrorWalkerHusk,
+        ResettingMirrorWalkerSettings,
+        ResettingUniWalkerHusk,
+        ResettingUniWalkerSettings,
+        ResettingWalker,
+        ResettingWalkerHusk,
+        UniMode,
+        UniWalker,
+        Walker
+    }
+};
+use rand_pcg::Pcg64;
+
+/// Overshoot of a gaussian random walk over a barrier in units of its step width, ζ(1/2)/√(2π).
+/// The walkers with a fixed step size see the target this much further away
+const OVERSHOOT: f64 = 0.5826;
+
+/// Complementary error function, Abramowitz and Stegun 7.1.26, absolute error below 1.5e-7
+fn erfc(x: f64) -> f64
+{
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t * (0.254829592 + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let val = poly * (-x * x).exp();
+    if x >= 0.0 { val } else { 2.0 - val }
+}
+
+/// Mfpt of a walker with D = 1 that is reset to the origin with rate `r`
+fn resetting_mfpt(r: f64, target: f64) -> f64
+{
+    ((r.sqrt() * target).exp() - 1.0) / r
+}
+
+/// Target of the continuous walk that corresponds to a walk with steps of `step_size`
+fn effective_target(target: f64, step_size: f64) -> f64
+{
+    OVERSHOOT.mul_add((2.0 * step_size).sqrt(), target)
+}
+
+/// Without any events, P(T > t) = erf(L / √(4t)).
+/// As this distribution has no mean, the walker is reset (a = 0), but so rarely,
+/// that P(T > t) is changed by less than 1e-3 for the times that are checked
+fn check_free_diffusion<W>(seed: u64)
+where W: Walker<Settings = EffWalkerSettings>
+{
+    let samples = 20_000;
+    let target = 1.0;
+    let settings = eff_settings(0.0, 1e-4, target, 10.0);
+    let fpts = first_passage_times::<W>(&settings, seed, samples);
+    for t in [0.1, 0.5, 2.0, 10.0]{
+        let survived = fpts.iter()
+            .filter(|fpt| **fpt > t)
+            .count();
+        let expected = 1.0 - erfc(target / (4.0 * t).sqrt());
+        assert_probability(&format!("P(T > {t})"), survived, samples, expected);
+    }
+}
+
+#[test]
+fn eff_free_diffusion()
+{
+    check_free_diffusion::<EffRandWalk<Pcg64>>(10);
+}
+
+#[test]
+fn eff2_free_diffusion()
+{
+    check_free_diffusion::<EffRandWalk2<Pcg64>>(11);
+}
+
+#[test]
+fn eff_resetting()
+{
+    // a = 0 moves the walker back to the origin
+    let settings = eff_settings(0.0, 2.0, 1.5, 0.1);
+    let moments = Moments::new(&first_passage_times::<EffRandWalk<Pcg64>>(&settings, 12, 20_000));
+    assert_close("mfpt", moments.mean, resetting_mfpt(2.0, 1.5), moments.standard_error());
+}
+
+#[test]
+fn eff2_resetting()
+{
+    let settings = eff_settings(0.0, 2.0, 1.5, 0.1);
+    let moments = Moments::new(&first_passage_times::<EffRandWalk2<Pcg64>>(&settings, 13, 20_000));
+    assert_close("mfpt", moments.mean, resetting_mfpt(2.0, 1.5), moments.standard_error());
+}
+
+#[test]
+fn resetting_walker()
+{
+    let husk = ResettingWalkerHusk{ rng_seed: 0, exp_lambda: 1.0, target_pos: 1.0, step_size: 1e-3 };
+    let moments = Moments::new(&first_passage_times::<ResettingWalker>(&husk, 14, 5000));
+    let expected = resetting_mfpt(1.0, effective_target(1.0, husk.step_size));
+    assert_close("mfpt", moments.mean, expected, moments.standard_error());
+}
+
+#[test]
+fn resetting_mirror_walker_without_mirrors()
+{
+    let settings = ResettingMirrorWalkerSettings{
+        husk: ResettingMirrorWalkerHusk{ rng_seed: 0, exp_lambda: 1.0, target_pos: 1.0, step_size: 1e-3 },
+        mirror_prob: 0.0
+    };
+    let moments = Moments::new(&first_passage_times::<ResettingMirrorWalker>(&settings, 15, 5000));
+    let expected = resetting_mfpt(1.0, effective_target(1.0, settings.husk.step_size));
+    assert_close("mfpt", moments.mean, expected, moments.standard_error());
+}
+
+fn uni_settings(uni_mid: f64, reset_lambda: f64, mirror_lambda: f64, mode: UniMode) -> ResettingUniWalkerSettings
+{
+    ResettingUniWalkerSettings{
+        husk: ResettingUniWalkerHusk{
+            rng_seed: 0,
+            uni_mid,
+            uni_delta_2: 0.0,
+            reset_lambda,
+            mirror_lambda,
+            target_pos: 1.0,
+            step_size: 1e-3
+        },
+        mode
+    }
+}
+
+#[test]
+fn uni_walker_resetting()
+{
+    // mirroring with factor 1 does nothing
+    let settings = uni_settings(1.0, 1.0, 0.5, UniMode::MirrorAndReset);
+    let moments = Moments::new(&first_passage_times::<UniWalker>(&settings, 16, 5000));
+    let expected = resetting_mfpt(1.0, effective_target(1.0, settings.husk.step_size));
+    assert_close("mfpt", moments.mean, expected, moments.standard_error());
+}
+
+/// Mfpt of a walker with D = 1 that is mirrored, x → a x, with rate `r`, see [mfpt]
+fn mirror_mfpt(r: f64, a: f64, target: f64) -> f64
+{
+    mfpt(r.sqrt() * target, a, 1.0, 1000) * target * target
+}
+
+#[test]
+fn eff_mirroring()
+{
+    for (a, seed) in [(0.5, 17), (0.9, 18)]{
+        let settings = eff_settings(a, 4.0, 1.0, 0.05);
+        let moments = Moments::new(&first_passage_times::<EffRandWalk<Pcg64>>(&settings, seed, 20_000));
+        assert_close(&format!("mfpt for a = {a}"), moments.mean, mirror_mfpt(4.0, a, 1.0), moments.standard_error());
+    }
+}
+
+#[test]
+fn eff2_mirroring()
+{
+    for (a, seed) in [(0.5, 19), (0.9, 20)]{
+        let settings = eff_settings(a, 4.0, 1.0, 0.05);
+        let moments = Moments::new(&first_passage_times::<EffRandWalk2<Pcg64>>(&settings, seed, 20_000));
+        assert_close(&format!("mfpt for a = {a}"), moments.mean, mirror_mfpt(4.0, a, 1.0), moments.standard_error());
+    }
+}
+
+#[test]
+fn uni_walker_mirroring()
+{
+    let settings = uni_settings(0.5, 1.0, 4.0, UniMode::OnlyMirror);
+    let moments = Moments::new(&first_passage_times::<UniWalker>(&settings, 21, 5000));
+    let target = effective_target(1.0, settings.husk.step_size);
+    assert_close("mfpt", moments.mean, mirror_mfpt(4.0, 0.5, target), moments.standard_error());
+}
@@ -0,0 +1,54 @@
+//! The overrides of the command line have to find their field, even if the json file uses an alias of a key
+use clap::Parser;
+use resetting_rw::{
+    parse::{apply_overrides, from_value, FieldOverride},
+    walker::MeasureMfptBetaOpt,
+    BetaOpt,
+    Refine
+};
+use serde_json::Value;
+
+/// The default beta scan, written with the alias `settings` instead of `settimgs`
+fn alias_json() -> Value
+{
+    let mut json = serde_json::to_value(MeasureMfptBetaOpt::default()).unwrap();
+    let map = json.as_object_mut().unwrap();
+    let settings = map.remove("settimgs").unwrap();
+    map.insert("settings".to_owned(), settings);
+    json
+}
+
+fn apply(overrides: &[FieldOverride]) -> MeasureMfptBetaOpt
+{
+    let mut json = alias_json();
+    apply_overrides(&mut json, overrides).unwrap();
+    from_value(&json, None).unwrap()
+}
+
+#[test]
+fn a_and_set_on_an_alias_file()
+{
+    let opt = BetaOpt::parse_from(["eff-rand-walk-beta", "-a", "0.25", "--set", "settimgs.target=2.0"]);
+    let json = apply(&opt.overrides());
+    assert_eq!(json.settimgs.a, 0.25);
+    assert_eq!(json.settimgs.target, 2.0);
+
+    let opt = BetaOpt::parse_from(["eff-rand-walk-beta", "--set", "settings.a=0.75"]);
+    assert_eq!(apply(&opt.overrides()).settimgs.a, 0.75);
+}
+
+#[test]
+fn max_depth_of_refine_on_an_alias_file()
+{
+    let opt = Refine::parse_from(["refine", "0.1", "--glob", "*.dat", "--max-depth", "7"]);
+    assert_eq!(apply(&opt.overrides()).settimgs.max_depth, 7);
+}
+
+#[test]
+fn unknown_top_level_key()
+{
+    let err = FieldOverride::new("nothing.a", 1.0)
+        .apply(&mut alias_json())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "unable to set `nothing.a`: `nothing` does not exist in the top level");
+}
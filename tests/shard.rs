@@ -0,0 +1,79 @@
+//! A scan that is split into shards and merged has to give exactly the output of a single run
+use std::num::NonZeroUsize;
+
+use camino::Utf8PathBuf;
+use resetting_rw::{
+    scan::ScanPart,
+    shard::{merge, MergeOpt, Shard},
+    walker::{even_more_efficient_mirror::eff_measure_mfpt_beta, MeasureMfptBetaOpt, RadomWalkSettings}
+};
+
+const SHARDS: usize = 3;
+
+/// Fresh directory for the outputs of one test
+fn output_dir(name: &str) -> Utf8PathBuf
+{
+    let dir = std::env::temp_dir().join(format!("resetting_rw_{name}_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    Utf8PathBuf::from_path_buf(dir).unwrap()
+}
+
+/// Many points, as the order in which the Kahan sums are combined only rarely changes their last bits
+fn scan(samples_per_point: usize) -> MeasureMfptBetaOpt
+{
+    MeasureMfptBetaOpt{
+        settimgs: RadomWalkSettings{
+            lambda_mirror: 1.0,
+            rough_step_size: 0.05,
+            target: 1.0,
+            a: 0.5,
+            max_depth: 20,
+            origin: 0.0
+        },
+        samples_per_point: NonZeroUsize::new(samples_per_point).unwrap(),
+        beta_left: 0.5,
+        beta_right: 2.0,
+        beta_samples: NonZeroUsize::new(40).unwrap(),
+        seed: 7,
+        ..Default::default()
+    }
+}
+
+fn check_merge_is_exact(name: &str, samples_per_point: usize)
+{
+    let dir = output_dir(name);
+    let whole = dir.join("whole.dat");
+    eff_measure_mfpt_beta(scan(samples_per_point), whole.clone(), ScanPart::default()).unwrap();
+
+    let shards: Vec<_> = (0..SHARDS)
+        .map(
+            |index| {
+                let shard = Shard{ index, count: NonZeroUsize::new(SHARDS).unwrap() };
+                let path = shard.output_path(&whole);
+                let part = ScanPart{ shard: Some(shard), point: None };
+                eff_measure_mfpt_beta(scan(samples_per_point), path.clone(), part).unwrap();
+                path
+            }
+        ).collect();
+    let merged = dir.join("merged.dat");
+    merge(MergeOpt { out: merged.clone(), shards }).unwrap();
+
+    let whole = std::fs::read(&whole).unwrap();
+    let merged = std::fs::read(&merged).unwrap();
+    assert!(whole == merged, "{name}: the merged shards differ from the single run");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn merged_shards_equal_a_single_run()
+{
+    check_merge_is_exact("shards", 1000);
+}
+
+/// With fewer packets than shards some shards have no packets of a point at all
+#[test]
+fn merged_shards_with_empty_shards()
+{
+    check_merge_is_exact("empty_shards", 2);
+}
@@ -0,0 +1,106 @@
+//! Stationary density of the walker that is only mirrored, x → a x, compared with Eq. 23,
+//! see [resetting_rw::analytical::Eq23]
+mod common;
+
+use std::num::NonZeroUsize;
+
+use common::*;
+use resetting_rw::{
+    analytical::Eq23,
+    dat::DatFile,
+    table::OutputFormat,
+    walker::{mirroring_hists, MirroringWalkerHistJob}
+};
+
+const SAMPLES: usize = 20_000;
+
+/// Integral of `f` from `a` to `b` by Simpson's rule
+fn integrate<F>(f: F, a: f64, b: f64) -> f64
+where F: Fn(f64) -> f64
+{
+    let n = 100;
+    let h = (b - a) / n as f64;
+    let inner: f64 = (1..n)
+        .map(|i| f(h.mul_add(i as f64, a)) * if i % 2 == 1 { 4.0 } else { 2.0 })
+        .sum();
+    (f(a) + f(b) + inner) * h / 3.0
+}
+
+#[test]
+fn eq23_is_normalized()
+{
+    for a in [0.3, 0.5, 0.9]{
+        let eq23 = Eq23::new(a, 2.0, 1000);
+        let norm = 2.0 * (0..100)
+            .map(|i| integrate(|x| eq23.density(x), i as f64, (i + 1) as f64))
+            .sum::<f64>();
+        assert!((norm - 1.0).abs() < 1e-6, "a = {a}: the density integrates to {norm}");
+    }
+}
+
+/// a^-i overflows long before the cutoff for small a, which must not turn the density into NaN
+#[test]
+fn eq23_is_finite_for_small_a()
+{
+    let eq23 = Eq23::new(0.1, 1.0, 1000);
+    for x in [0.0, 0.5, 3.0]{
+        let density = eq23.density(x);
+        assert!(density.is_finite() && density > 0.0, "P({x}) = {density}");
+    }
+}
+
+/// Positions of the mirroring walkers after a time long enough to be stationary
+fn stationary_positions(a: f64, r: f64, seed: u64) -> Vec<f64>
+{
+    let job = MirroringWalkerHistJob{
+        rng_seed: seed,
+        uni_mid: a,
+        uni_delta_2: 0.0,
+        mirror_lambda: r,
+        step_size: 0.01,
+        hist_positions: vec![30.0],
+        samples: NonZeroUsize::new(SAMPLES).unwrap()
+    };
+    let dir = std::env::temp_dir().join(format!("resetting_rw_stationary_{}_{seed}", std::process::id()));
+    fs_err::create_dir_all(&dir).unwrap();
+    let prefix = dir.join("hist");
+    mirroring_hists(&job, prefix.to_str().unwrap(), OutputFormat::Dat).unwrap();
+    let file = DatFile::read(dir.join("hist_3000.dat")).unwrap();
+    fs_err::remove_dir_all(&dir).unwrap();
+    file.column("position").unwrap()
+}
+
+fn check_stationary_density(a: f64, r: f64, seed: u64)
+{
+    let positions = stationary_positions(a, r, seed);
+    assert_eq!(positions.len(), SAMPLES);
+
+    // the mean squared distance relaxes to 2D / (r (1 - a²))
+    let squares: Vec<f64> = positions.iter().map(|x| x * x).collect();
+    let moments = Moments::new(&squares);
+    assert_close("<x²>", moments.mean, 2.0 / (r * (1.0 - a * a)), moments.standard_error());
+
+    let eq23 = Eq23::new(a, r, 1000);
+    let width = 0.5;
+    for i in -6..6{
+        let left = i as f64 * width;
+        let right = left + width;
+        let hits = positions.iter()
+            .filter(|x| (left..right).contains(*x))
+            .count();
+        let expected = integrate(|x| eq23.density(x), left, right);
+        assert_probability(&format!("a = {a}: P({left} <= x < {right})"), hits, SAMPLES, expected);
+    }
+}
+
+#[test]
+fn stationary_density_of_negative_a()
+{
+    check_stationary_density(-0.5, 1.0, 30);
+}
+
+#[test]
+fn stationary_density_of_positive_a()
+{
+    check_stationary_density(0.7, 2.0, 31);
+}